@@ -0,0 +1,108 @@
+-- Amounts are exact NUMERIC(39, 0) base units; prices and ratios are NUMERIC.
+
+CREATE TABLE depth_history (
+    pool TEXT NOT NULL,
+    start_time BIGINT NOT NULL,
+    end_time BIGINT NOT NULL,
+    asset_depth NUMERIC(39, 0) NOT NULL,
+    asset_price NUMERIC NOT NULL,
+    asset_price_usd NUMERIC NOT NULL,
+    liquidity_units NUMERIC(39, 0) NOT NULL,
+    members_count BIGINT NOT NULL,
+    rune_depth NUMERIC(39, 0) NOT NULL,
+    synth_supply NUMERIC(39, 0) NOT NULL,
+    synth_units NUMERIC(39, 0) NOT NULL,
+    units NUMERIC(39, 0) NOT NULL,
+    luvi NUMERIC NOT NULL,
+    PRIMARY KEY (pool, start_time)
+);
+
+CREATE INDEX depth_history_start_time_end_time ON depth_history (start_time, end_time);
+
+CREATE TABLE swaps_history (
+    pool TEXT NOT NULL,
+    start_time BIGINT NOT NULL,
+    end_time BIGINT NOT NULL,
+    average_slip NUMERIC NOT NULL,
+    rune_price_usd NUMERIC NOT NULL,
+    synth_mint_average_slip NUMERIC NOT NULL,
+    synth_mint_count BIGINT NOT NULL,
+    synth_mint_fees NUMERIC(39, 0) NOT NULL,
+    synth_mint_volume NUMERIC(39, 0) NOT NULL,
+    synth_mint_volume_usd NUMERIC NOT NULL,
+    synth_redeem_average_slip NUMERIC NOT NULL,
+    synth_redeem_count BIGINT NOT NULL,
+    synth_redeem_fees NUMERIC(39, 0) NOT NULL,
+    synth_redeem_volume NUMERIC(39, 0) NOT NULL,
+    synth_redeem_volume_usd NUMERIC NOT NULL,
+    to_asset_average_slip NUMERIC NOT NULL,
+    to_asset_count BIGINT NOT NULL,
+    to_asset_fees NUMERIC(39, 0) NOT NULL,
+    to_asset_volume NUMERIC(39, 0) NOT NULL,
+    to_asset_volume_usd NUMERIC NOT NULL,
+    to_rune_average_slip NUMERIC NOT NULL,
+    to_rune_count BIGINT NOT NULL,
+    to_rune_fees NUMERIC(39, 0) NOT NULL,
+    to_rune_volume NUMERIC(39, 0) NOT NULL,
+    to_rune_volume_usd NUMERIC NOT NULL,
+    total_count BIGINT NOT NULL,
+    total_fees NUMERIC(39, 0) NOT NULL,
+    total_volume NUMERIC(39, 0) NOT NULL,
+    total_volume_usd NUMERIC NOT NULL,
+    PRIMARY KEY (pool, start_time)
+);
+
+CREATE INDEX swaps_history_start_time_end_time ON swaps_history (start_time, end_time);
+
+CREATE TABLE runepool_history (
+    start_time BIGINT PRIMARY KEY,
+    end_time BIGINT NOT NULL,
+    count BIGINT NOT NULL,
+    units NUMERIC(39, 0) NOT NULL
+);
+
+CREATE INDEX runepool_history_end_time ON runepool_history (end_time);
+
+CREATE TABLE earnings_history (
+    start_time BIGINT PRIMARY KEY,
+    end_time BIGINT NOT NULL,
+    avg_node_count NUMERIC NOT NULL,
+    block_rewards NUMERIC(39, 0) NOT NULL,
+    bonding_earnings NUMERIC(39, 0) NOT NULL,
+    earnings NUMERIC(39, 0) NOT NULL,
+    liquidity_earnings NUMERIC(39, 0) NOT NULL,
+    liquidity_fees NUMERIC(39, 0) NOT NULL,
+    rune_price_usd NUMERIC NOT NULL
+);
+
+CREATE INDEX earnings_history_end_time ON earnings_history (end_time);
+
+-- Each interval's per-pool breakdown; `position` keeps Midgard's order within the interval.
+CREATE TABLE pool_earnings (
+    pool TEXT NOT NULL,
+    start_time BIGINT NOT NULL REFERENCES earnings_history (start_time) ON DELETE CASCADE,
+    end_time BIGINT NOT NULL,
+    position BIGINT NOT NULL,
+    asset_liquidity_fees NUMERIC(39, 0) NOT NULL,
+    earnings NUMERIC(39, 0) NOT NULL,
+    rewards NUMERIC(39, 0) NOT NULL,
+    rune_liquidity_fees NUMERIC(39, 0) NOT NULL,
+    saver_earning NUMERIC(39, 0) NOT NULL,
+    total_liquidity_fees_rune NUMERIC(39, 0) NOT NULL,
+    PRIMARY KEY (pool, start_time)
+);
+
+CREATE INDEX pool_earnings_start_time ON pool_earnings (start_time, end_time);
+
+-- `pool` is '' for datasets that are not stored per pool, so it can be part of the key.
+CREATE TABLE ingestion_state (
+    dataset TEXT NOT NULL,
+    pool TEXT NOT NULL,
+    last_end_time BIGINT NOT NULL,
+    status TEXT NOT NULL,
+    error TEXT,
+    last_batch_rows BIGINT NOT NULL,
+    total_rows BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL,
+    PRIMARY KEY (dataset, pool)
+);
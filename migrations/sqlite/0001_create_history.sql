@@ -0,0 +1,109 @@
+-- SQLite has no exact wide numeric type, so amounts and prices are stored as their
+-- decimal text; cast them in queries that need arithmetic.
+
+CREATE TABLE depth_history (
+    pool TEXT NOT NULL,
+    start_time BIGINT NOT NULL,
+    end_time BIGINT NOT NULL,
+    asset_depth TEXT NOT NULL,
+    asset_price TEXT NOT NULL,
+    asset_price_usd TEXT NOT NULL,
+    liquidity_units TEXT NOT NULL,
+    members_count BIGINT NOT NULL,
+    rune_depth TEXT NOT NULL,
+    synth_supply TEXT NOT NULL,
+    synth_units TEXT NOT NULL,
+    units TEXT NOT NULL,
+    luvi TEXT NOT NULL,
+    PRIMARY KEY (pool, start_time)
+);
+
+CREATE INDEX depth_history_start_time_end_time ON depth_history (start_time, end_time);
+
+CREATE TABLE swaps_history (
+    pool TEXT NOT NULL,
+    start_time BIGINT NOT NULL,
+    end_time BIGINT NOT NULL,
+    average_slip TEXT NOT NULL,
+    rune_price_usd TEXT NOT NULL,
+    synth_mint_average_slip TEXT NOT NULL,
+    synth_mint_count BIGINT NOT NULL,
+    synth_mint_fees TEXT NOT NULL,
+    synth_mint_volume TEXT NOT NULL,
+    synth_mint_volume_usd TEXT NOT NULL,
+    synth_redeem_average_slip TEXT NOT NULL,
+    synth_redeem_count BIGINT NOT NULL,
+    synth_redeem_fees TEXT NOT NULL,
+    synth_redeem_volume TEXT NOT NULL,
+    synth_redeem_volume_usd TEXT NOT NULL,
+    to_asset_average_slip TEXT NOT NULL,
+    to_asset_count BIGINT NOT NULL,
+    to_asset_fees TEXT NOT NULL,
+    to_asset_volume TEXT NOT NULL,
+    to_asset_volume_usd TEXT NOT NULL,
+    to_rune_average_slip TEXT NOT NULL,
+    to_rune_count BIGINT NOT NULL,
+    to_rune_fees TEXT NOT NULL,
+    to_rune_volume TEXT NOT NULL,
+    to_rune_volume_usd TEXT NOT NULL,
+    total_count BIGINT NOT NULL,
+    total_fees TEXT NOT NULL,
+    total_volume TEXT NOT NULL,
+    total_volume_usd TEXT NOT NULL,
+    PRIMARY KEY (pool, start_time)
+);
+
+CREATE INDEX swaps_history_start_time_end_time ON swaps_history (start_time, end_time);
+
+CREATE TABLE runepool_history (
+    start_time BIGINT PRIMARY KEY,
+    end_time BIGINT NOT NULL,
+    count BIGINT NOT NULL,
+    units TEXT NOT NULL
+);
+
+CREATE INDEX runepool_history_end_time ON runepool_history (end_time);
+
+CREATE TABLE earnings_history (
+    start_time BIGINT PRIMARY KEY,
+    end_time BIGINT NOT NULL,
+    avg_node_count TEXT NOT NULL,
+    block_rewards TEXT NOT NULL,
+    bonding_earnings TEXT NOT NULL,
+    earnings TEXT NOT NULL,
+    liquidity_earnings TEXT NOT NULL,
+    liquidity_fees TEXT NOT NULL,
+    rune_price_usd TEXT NOT NULL
+);
+
+CREATE INDEX earnings_history_end_time ON earnings_history (end_time);
+
+-- Each interval's per-pool breakdown; `position` keeps Midgard's order within the interval.
+CREATE TABLE pool_earnings (
+    pool TEXT NOT NULL,
+    start_time BIGINT NOT NULL REFERENCES earnings_history (start_time) ON DELETE CASCADE,
+    end_time BIGINT NOT NULL,
+    position BIGINT NOT NULL,
+    asset_liquidity_fees TEXT NOT NULL,
+    earnings TEXT NOT NULL,
+    rewards TEXT NOT NULL,
+    rune_liquidity_fees TEXT NOT NULL,
+    saver_earning TEXT NOT NULL,
+    total_liquidity_fees_rune TEXT NOT NULL,
+    PRIMARY KEY (pool, start_time)
+);
+
+CREATE INDEX pool_earnings_start_time ON pool_earnings (start_time, end_time);
+
+-- `pool` is '' for datasets that are not stored per pool, so it can be part of the key.
+CREATE TABLE ingestion_state (
+    dataset TEXT NOT NULL,
+    pool TEXT NOT NULL,
+    last_end_time BIGINT NOT NULL,
+    status TEXT NOT NULL,
+    error TEXT,
+    last_batch_rows BIGINT NOT NULL,
+    total_rows BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL,
+    PRIMARY KEY (dataset, pool)
+);
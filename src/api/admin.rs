@@ -0,0 +1,113 @@
+use crate::api::error::ApiError;
+use crate::models::ingestion_state::Dataset;
+use crate::repository::Repositories;
+use crate::services::gaps::{backfill_gaps, find_gaps, Gap};
+use crate::services::midgard_client::MidgardClient;
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use utoipa::IntoParams;
+use std::env;
+
+#[derive(Debug, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GapQueryParams {
+    /// `depth`, `runepool`, `swaps` or `earnings`; every dataset when left out.
+    dataset: Option<String>,
+    pool: Option<String>,
+    /// Unix seconds.
+    from: Option<i64>,
+    /// Unix seconds.
+    to: Option<i64>,
+}
+
+/// Admin routes are only served when `ADMIN_TOKEN` is set, and then require it in the
+/// `x-admin-token` header.
+fn _authorize(req: &HttpRequest) -> Result<(), ApiError> {
+    let token = match env::var("ADMIN_TOKEN") {
+        Ok(token) if !token.is_empty() => token,
+        _ => return Err(ApiError::Forbidden("Admin API disabled".to_string())),
+    };
+    match req.headers().get("x-admin-token").and_then(|value| value.to_str().ok()) {
+        Some(provided) if provided == token => Ok(()),
+        _ => Err(ApiError::Unauthorized("Invalid admin token".to_string())),
+    }
+}
+
+async fn _scan(repos: &Repositories, params: &GapQueryParams) -> Result<Vec<Gap>, ApiError> {
+    if let (Some(start), Some(end)) = (params.from, params.to) {
+        if start >= end {
+            return Err(ApiError::bad_request("start_time must be less than end_time"));
+        }
+    }
+    let datasets = match &params.dataset {
+        Some(dataset) => vec![dataset.parse::<Dataset>().map_err(ApiError::bad_request)?],
+        None => Dataset::ALL.to_vec(),
+    };
+
+    let mut gaps = Vec::new();
+    for dataset in datasets {
+        let found = find_gaps(repos, dataset, params.pool.as_deref(), params.from, params.to)
+            .await
+            .map_err(|e| ApiError::Internal(format!("scanning {} for gaps: {}", dataset, e)))?;
+        gaps.extend(found);
+    }
+
+    Ok(gaps)
+}
+
+/// `GET /admin/gaps` lists missing intervals without fetching anything.
+#[utoipa::path(
+    get,
+    path = "/admin/gaps",
+    tag = "admin",
+    params(GapQueryParams, ("x-admin-token" = String, Header, description = "The server's `ADMIN_TOKEN`")),
+    responses(
+        (status = 200, description = "Missing intervals", body = Vec<Gap>),
+        (status = 400, description = "Invalid parameters", body = ErrorBody),
+        (status = 401, description = "Missing or wrong `x-admin-token`", body = ErrorBody),
+        (status = 403, description = "`ADMIN_TOKEN` is not set", body = ErrorBody),
+        (status = 500, description = "Storage failed", body = ErrorBody),
+    )
+)]
+pub async fn gaps_route(
+    req: HttpRequest,
+    query: web::Query<GapQueryParams>,
+    repos: web::Data<Repositories>,
+) -> Result<HttpResponse, ApiError> {
+    _authorize(&req)?;
+    let gaps = _scan(&repos, &query).await?;
+    Ok(HttpResponse::Ok().json(gaps))
+}
+
+/// `POST /admin/gaps/backfill` re-fetches every missing interval and reports each outcome.
+#[utoipa::path(
+    post,
+    path = "/admin/gaps/backfill",
+    tag = "admin",
+    params(GapQueryParams, ("x-admin-token" = String, Header, description = "The server's `ADMIN_TOKEN`")),
+    responses(
+        (status = 200, description = "Every gap was fetched", body = Vec<BackfillOutcome>),
+        (status = 400, description = "Invalid parameters", body = ErrorBody),
+        (status = 401, description = "Missing or wrong `x-admin-token`", body = ErrorBody),
+        (status = 403, description = "`ADMIN_TOKEN` is not set", body = ErrorBody),
+        (status = 500, description = "Storage failed", body = ErrorBody),
+        (status = 502, description = "Midgard failed some gaps; each outcome says which", body = Vec<BackfillOutcome>),
+    )
+)]
+pub async fn backfill_gaps_route(
+    req: HttpRequest,
+    query: web::Query<GapQueryParams>,
+    repos: web::Data<Repositories>,
+    midgard: web::Data<MidgardClient>,
+) -> Result<HttpResponse, ApiError> {
+    _authorize(&req)?;
+    let gaps = _scan(&repos, &query).await?;
+
+    // Per-gap outcomes are the body either way; 502 flags that Midgard failed some of them.
+    let outcomes = backfill_gaps(&repos, &midgard, gaps).await;
+    if outcomes.iter().any(|outcome| outcome.error.is_some()) {
+        Ok(HttpResponse::BadGateway().json(outcomes))
+    } else {
+        Ok(HttpResponse::Ok().json(outcomes))
+    }
+}
@@ -1,185 +1,151 @@
 use crate::models::depth_history::{DepthHistory, Metadata};
-use actix_web::{web, HttpResponse, Responder};
-use bson::{doc, to_document};
-use futures::stream::StreamExt;
-use mongodb::Collection;
-use serde::{Deserialize, Serialize};
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct DepthHistoryQueryParams {
-    from: Option<i64>,
-    to: Option<i64>,
-    pool: Option<String>,
-    page: Option<u32>,
-    limit: Option<u32>,
-    sort_by: Option<String>,
-    order: Option<String>,
+use actix_web::{web, HttpResponse};
+use crate::api::error::ApiError;
+use crate::api::history;
+use crate::api::query;
+use crate::models::fields::serialized_fields;
+use crate::services::sorting;
+use crate::repository::{aggregate_string, Accumulator, HistoryFilter, HistoryRepository, RepoResult};
+
+/// Hourly pool depths, or `interval` buckets of them, with metadata over the whole range
+/// of a single pool.
+#[utoipa::path(
+    get,
+    path = "/depth-history",
+    tag = "history",
+    params(query::HistoryParams),
+    responses(
+        (status = 200, description = "A page of depth rows, or an object of pages keyed by pool for several pools or a wildcard", body = DepthHistoryPage),
+        (status = 400, description = "Invalid parameters", body = ErrorBody),
+        (status = 500, description = "Storage failed", body = ErrorBody),
+    )
+)]
+pub async fn depth_history_route(
+    params: query::HistoryQuery,
+    repository: web::Data<dyn HistoryRepository<DepthHistory>>,
+) -> Result<HttpResponse, ApiError> {
+    let query = params.storage_query(&sorting::sortable::<DepthHistory>(), &["startTime", "pool"])?;
+    let fields = params.project(serialized_fields::<DepthHistory>())?;
+    let (repository, fields, params) = (&**repository, fields.as_deref(), &params);
+
+    history::respond(params, repository, query, |query, cursor| async move {
+        // Depths are per pool, so rows of every pool have no single start, end or average.
+        let meta = query.filter.pool.is_some().then(|| _range_metadata(repository, &query.filter));
+        history::page(repository, &query, params, cursor.as_ref(), fields, meta, |_| {}).await
+    })
+    .await
 }
 
-#[derive(Debug, Serialize)]
-struct ResponseWithMeta {
-    data: Vec<bson::Document>,
-    meta: Metadata,
-}
+/// Fields with a start, end and average in the metadata.
+const TRACKED: [&str; 5] = ["assetDepth", "liquidityUnits", "membersCount", "runeDepth", "synthUnits"];
 
-pub async fn depth_history_route(
-    query: web::Query<DepthHistoryQueryParams>,
-    collection: web::Data<Collection<DepthHistory>>,
-) -> impl Responder {
-    let params = query.into_inner();
-    let mut filter_conditions = Vec::new();
-
-    if let (Some(start), Some(end)) = (params.from, params.to) {
-        if start >= end {
-            return HttpResponse::BadRequest().json("start_time must be less than end_time");
-        }
-        filter_conditions.push(doc! { "startTime": { "$gte": start } });
-        filter_conditions.push(doc! { "endTime": { "$lte": end } });
-    } else if let Some(start) = params.from {
-        filter_conditions.push(doc! { "startTime": { "$gte": start } });
-    } else if let Some(end) = params.to {
-        filter_conditions.push(doc! { "endTime": { "$lte": end } });
+/// Metadata over every row in `filter` rather than the returned page.
+async fn _range_metadata(repository: &dyn HistoryRepository<DepthHistory>, filter: &HistoryFilter) -> RepoResult<Metadata> {
+    let mut accumulators = vec![Accumulator::Count, Accumulator::Min("startTime"), Accumulator::Max("endTime")];
+    for field in TRACKED {
+        accumulators.extend([Accumulator::First(field), Accumulator::Last(field), Accumulator::Sum(field)]);
     }
+    let values = repository.aggregate(filter, &accumulators).await?;
+
+    let count: i128 = aggregate_string(&values[0]).parse().unwrap_or_default();
+    // Each field's [start, end, average], in the order of `TRACKED`.
+    let mut fields = values[3..].chunks(3).map(|field| {
+        let total: i128 = aggregate_string(&field[2]).parse().unwrap_or_default();
+        let average = if count > 0 { total / count } else { 0 };
+        [aggregate_string(&field[0]), aggregate_string(&field[1]), average.to_string()]
+    });
+    let [start_asset_depth, end_asset_depth, avg_asset_depth] = fields.next().unwrap_or_default();
+    let [start_lp_units, end_lp_units, avg_lp_units] = fields.next().unwrap_or_default();
+    let [start_member_count, end_member_count, avg_member_count] = fields.next().unwrap_or_default();
+    let [start_rune_depth, end_rune_depth, avg_rune_depth] = fields.next().unwrap_or_default();
+    let [start_synth_units, end_synth_units, avg_synth_units] = fields.next().unwrap_or_default();
+
+    Ok(Metadata {
+        start_time: aggregate_string(&values[1]),
+        end_time: aggregate_string(&values[2]),
+        start_asset_depth,
+        end_asset_depth,
+        avg_asset_depth,
+        start_lp_units,
+        end_lp_units,
+        avg_lp_units,
+        start_member_count,
+        end_member_count,
+        avg_member_count,
+        start_rune_depth,
+        end_rune_depth,
+        avg_rune_depth,
+        start_synth_units,
+        end_synth_units,
+        avg_synth_units,
+    })
+}
 
-    if let Some(pool) = params.pool {
-        filter_conditions.push(doc! { "pool": pool });
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use serde_json::{json, Value};
+    use crate::api::testing::{depth_repos, get};
+
+    fn _starts(page: &Value) -> Vec<i64> {
+        page["data"].as_array().unwrap().iter().map(|row| row["startTime"].as_i64().unwrap()).collect()
     }
 
-    let filter = if !filter_conditions.is_empty() {
-        doc! { "$and": filter_conditions }
-    } else {
-        doc! {}
-    };
-
-    let limit = params.limit.unwrap_or(10).clamp(1, 100) as i64;
-    let skip = ((params.page.unwrap_or(1).max(1) - 1) * params.limit.unwrap_or(10)) as u64;
-
-    let sort_doc = if let Some(sort_by) = params.sort_by.clone() {
-        let sort_order = match params.order.as_deref() {
-            Some("asc") => 1,
-            Some("desc") | _ => -1,
-        };
-        doc! { sort_by: sort_order }
-    } else {
-        doc! { "startTime": -1 }
-    };
-
-    let mut cursor = match collection
-        .find(filter)
-        .sort(sort_doc)
-        .skip(skip)
-        .limit(limit)
-        .await
-    {
-        Ok(cursor) => cursor,
-        Err(e) => {
-            eprintln!("Error fetching data: {:?}", e);
-            return HttpResponse::InternalServerError().json("Error fetching data");
-        }
-    };
-
-    let mut histories = Vec::new();
-    let mut total_asset_depth = 0;
-    let mut total_lp_units = 0;
-    let mut total_member_count = 0;
-    let mut total_rune_depth = 0;
-    let mut total_synth_units = 0;
-
-    let mut first_start_time = None;
-    let mut last_end_time = None;
-
-    let mut start_asset_depth = 0;
-    let mut start_lp_units = 0;
-    let mut start_member_count = 0;
-    let mut start_rune_depth = 0;
-    let mut start_synth_units = 0;
-
-    let mut end_asset_depth = 0;
-    let mut end_lp_units = 0;
-    let mut end_member_count = 0;
-    let mut end_rune_depth = 0;
-    let mut end_synth_units = 0;
-
-    while let Some(result) = cursor.next().await {
-        match result {
-            Ok(raw_history) => {
-                let mut response = to_document(&raw_history).unwrap();
-                response.remove("_id");
-
-                let start_time = response.get_i64("startTime").unwrap_or(0);
-                let end_time = response.get_i64("endTime").unwrap_or(0);
-                let asset_depth = response.get_i64("assetDepth").unwrap_or(0);
-                let lp_units = response.get_i64("liquidityUnits").unwrap_or(0);
-                let member_count = response.get_i64("membersCount").unwrap_or(0);
-                let rune_depth = response.get_i64("runeDepth").unwrap_or(0);
-                let synth_units = response.get_i64("synthUnits").unwrap_or(0);
-
-                if first_start_time.is_none() {
-                    first_start_time = Some(start_time);
-                    start_asset_depth = asset_depth;
-                    start_lp_units = lp_units;
-                    start_member_count = member_count;
-                    start_rune_depth = rune_depth;
-                    start_synth_units = synth_units;
-                }
-
-                last_end_time = Some(end_time);
-                end_asset_depth = asset_depth;
-                end_lp_units = lp_units;
-                end_member_count = member_count;
-                end_rune_depth = rune_depth;
-                end_synth_units = synth_units;
-
-                total_asset_depth += asset_depth;
-                total_lp_units += lp_units;
-                total_member_count += member_count;
-                total_rune_depth += rune_depth;
-                total_synth_units += synth_units;
-
-                histories.push(response);
-            }
-            Err(e) => {
-                eprintln!("Error processing data: {:?}", e);
-                return HttpResponse::InternalServerError().json("Error processing data");
-            }
+    #[actix_web::test]
+    async fn pages_a_pool_with_metadata_over_the_range() {
+        let repos = depth_repos().await;
+        let (status, first) = get(&repos, "/depth-history?pool=BTC.BTC&order=asc&limit=2&include_total=true").await;
+        assert_eq!(status, StatusCode::OK, "{}", first);
+        assert_eq!((_starts(&first), &first["total"], &first["prev_cursor"]), (vec![0, 3600], &json!(4), &Value::Null));
+
+        let (_, second) = get(&repos, "/depth-history?pool=BTC.BTC&order=asc&limit=2&page=2").await;
+        assert_eq!((_starts(&second), &second["next_cursor"]), (vec![7200, 10800], &Value::Null));
+        let cursor = first["next_cursor"].as_str().unwrap();
+        let (_, resumed) = get(&repos, &format!("/depth-history?pool=BTC.BTC&order=asc&limit=2&cursor={}", cursor)).await;
+        assert_eq!(resumed["data"], second["data"]);
+
+        // The same metadata on every page.
+        for page in [&first, &second] {
+            let meta = &page["meta"];
+            assert_eq!((&meta["startTime"], &meta["endTime"]), (&json!("0"), &json!("14400")));
+            assert_eq!((&meta["startAssetDepth"], &meta["endAssetDepth"], &meta["avgAssetDepth"]), (&json!("100"), &json!("400"), &json!("250")));
+            assert_eq!((&meta["startMemberCount"], &meta["endMemberCount"]), (&json!("1"), &json!("4")));
         }
     }
 
-    if histories.is_empty() {
-        return HttpResponse::NotFound().json("No data found");
+    #[actix_web::test]
+    async fn filters_rows_and_metadata_to_the_range() {
+        let repos = depth_repos().await;
+        let (_, contained) = get(&repos, "/depth-history?pool=BTC.BTC&order=asc&from=3600&to=10800").await;
+        assert_eq!(_starts(&contained), [3600, 7200]);
+        let meta = &contained["meta"];
+        assert_eq!((&meta["startTime"], &meta["endTime"], &meta["startAssetDepth"]), (&json!("3600"), &json!("10800"), &json!("200")));
+
+        let (_, overlapping) = get(&repos, "/depth-history?pool=BTC.BTC&order=asc&from=3601&to=10799&range=overlapping").await;
+        assert_eq!(_starts(&overlapping), [3600, 7200]);
+        let (_, empty) = get(&repos, "/depth-history?pool=BTC.BTC&from=3601&to=10799").await;
+        assert_eq!((_starts(&empty), &empty["meta"]), (vec![], &Value::Null));
     }
 
-    let count = histories.len() as i64;
-    let avg_asset_depth = total_asset_depth / count;
-    let avg_lp_units = total_lp_units / count;
-    let avg_member_count = total_member_count / count;
-    let avg_rune_depth = total_rune_depth / count;
-    let avg_synth_units = total_synth_units / count;
-
-    let metadata = Metadata {
-        start_time: first_start_time.unwrap_or(0).to_string(),
-        end_time: last_end_time.unwrap_or(0).to_string(),
-        start_asset_depth: start_asset_depth.to_string(),
-        end_asset_depth: end_asset_depth.to_string(),
-        avg_asset_depth: avg_asset_depth.to_string(),
-        start_lp_units: start_lp_units.to_string(),
-        end_lp_units: end_lp_units.to_string(),
-        avg_lp_units: avg_lp_units.to_string(),
-        start_member_count: start_member_count.to_string(),
-        end_member_count: end_member_count.to_string(),
-        avg_member_count: avg_member_count.to_string(),
-        start_rune_depth: start_rune_depth.to_string(),
-        end_rune_depth: end_rune_depth.to_string(),
-        avg_rune_depth: avg_rune_depth.to_string(),
-        start_synth_units: start_synth_units.to_string(),
-        end_synth_units: end_synth_units.to_string(),
-        avg_synth_units: avg_synth_units.to_string(),
-    };
-
-    let response = ResponseWithMeta {
-        data: histories,
-        meta: metadata,
-    };
-
-    HttpResponse::Ok().json(response)
+    #[actix_web::test]
+    async fn metadata_needs_a_single_pool() {
+        let repos = depth_repos().await;
+        let (_, mixed) = get(&repos, "/depth-history").await;
+        assert_eq!((mixed["data"].as_array().unwrap().len(), &mixed["meta"]), (8, &Value::Null));
+
+        let (status, grouped) = get(&repos, "/depth-history?pool=BTC.BTC,ETH.ETH&limit=1").await;
+        assert_eq!(status, StatusCode::OK, "{}", grouped);
+        assert_eq!(grouped["BTC.BTC"]["meta"]["endAssetDepth"], "400");
+        assert_eq!(grouped["ETH.ETH"]["meta"]["avgAssetDepth"], "1000");
+        assert_eq!(_starts(&grouped["ETH.ETH"]), [10800]);
+    }
+
+    #[actix_web::test]
+    async fn intervals_keep_the_last_depth_of_each_bucket() {
+        let repos = depth_repos().await;
+        let (status, body) = get(&repos, "/depth-history?pool=BTC.BTC&interval=day&from=0&count=1&include_total=true").await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!((_starts(&body), &body["total"]), (vec![0], &json!(1)));
+        assert_eq!((&body["data"][0]["assetDepth"], &body["data"][0]["endTime"]), (&json!("400"), &json!(86400)));
+    }
 }
@@ -1,175 +1,198 @@
-use actix_web::{web, HttpResponse, Responder};
-use mongodb::Collection;
-use crate::models::{earnings_history::EarningsHistory, pools_history::PoolHistory};
-use mongodb::bson::{doc, to_document};
-use futures::stream::StreamExt;
+use actix_web::{web, HttpResponse};
+use crate::models::amount::Decimal;
+use crate::models::earnings_history::{EarningsHistory, Metadata, PoolEarnings};
+use crate::api::error::ApiError;
+use crate::api::history;
+use crate::api::query::{self, shape};
+use crate::models::fields::serialized_fields;
+use crate::services::pagination::{self, Cursor, Envelope};
+use crate::services::sorting;
+use crate::repository::{aggregate_string, Accumulator, EarningsRepository, HistoryFilter, HistoryQuery, RepoResult};
+use mongodb::bson::{to_document, Document};
 use serde::{Deserialize, Serialize};
+use utoipa::IntoParams;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct EarningsWithPoolsQueryParams {
-    from: Option<i64>,
-    to: Option<i64>,
+    /// Pages whole-network intervals with their pools embedded instead of per-pool rows.
     summary: Option<bool>,
-    page: Option<u32>,
-    limit: Option<u32>,
-    sort_by: Option<String>,
-    order: Option<String>,
 }
 
+/// Earnings as per-pool rows, or with `summary=true` as whole-network intervals with
+/// metadata over the whole range.
+#[utoipa::path(
+    get,
+    path = "/earnings",
+    tag = "history",
+    params(query::HistoryParams, EarningsWithPoolsQueryParams),
+    responses(
+        (status = 200, description = "A page of pool earnings rows or summary intervals, or an object of pages keyed by pool for several pools or a wildcard", body = EarningsHistoryPage),
+        (status = 400, description = "Invalid parameters", body = ErrorBody),
+        (status = 500, description = "Storage failed", body = ErrorBody),
+    )
+)]
 pub async fn earnings_with_pools_route(
-    query: web::Query<EarningsWithPoolsQueryParams>,
-    earnings_collection: web::Data<Collection<EarningsHistory>>,
-    pools_collection: web::Data<Collection<PoolHistory>>,
-) -> impl Responder {
-    let params = query.into_inner();
-    let mut filter_conditions = Vec::new();
-
-    if let (Some(start), Some(end)) = (params.from, params.to) {
-        if start >= end {
-            return HttpResponse::BadRequest().json("start_time must be less than end_time");
-        }
-        filter_conditions.push(doc! { "startTime": { "$gte": start } });
-        filter_conditions.push(doc! { "endTime": { "$lte": end } });
-    } else if let Some(start) = params.from {
-        filter_conditions.push(doc! { "startTime": { "$gte": start } });
-    } else if let Some(end) = params.to {
-        filter_conditions.push(doc! { "endTime": { "$lte": end } });
+    params: query::HistoryQuery,
+    earnings: web::Query<EarningsWithPoolsQueryParams>,
+    repository: web::Data<dyn EarningsRepository>,
+) -> Result<HttpResponse, ApiError> {
+    let summary = earnings.summary.unwrap_or(false);
+    // Pool rows carry their interval's times beside the pool's own fields.
+    let (sortable, available): (Vec<&str>, Vec<&str>) = match summary {
+        true => (sorting::sortable::<EarningsHistory>(), serialized_fields::<EarningsHistory>().to_vec()),
+        false => (
+            ["startTime", "endTime"].into_iter().chain(sorting::sortable::<PoolEarnings>()).collect(),
+            ["startTime", "endTime"].iter().chain(serialized_fields::<PoolEarnings>()).copied().collect(),
+        ),
+    };
+    // Summary intervals are keyed on time alone, pool rows on time and pool.
+    let mut query = params.storage_query(&sortable, if summary { &["startTime"] } else { &["startTime", "pool"] })?;
+    let fields = params.project(&available)?;
+    let (repository, fields, params) = (&**repository, fields.as_deref(), &params);
+
+    if summary {
+        // Intervals stay whole-network; a pool selection only trims their per-pool breakdown.
+        query.filter.pool = params.single_pool();
+        let meta = Some(_range_metadata(repository, &query.filter));
+        let trim = |earnings: &mut EarningsHistory| {
+            if let Some(selection) = &params.pools {
+                earnings.pools.retain(|pool| selection.matches(&pool.pool));
+            }
+        };
+        let response = history::page(repository, &query, params, params.cursor.as_ref(), fields, meta, trim).await?;
+        return Ok(HttpResponse::Ok().json(response));
     }
 
-    let filter_doc = if filter_conditions.is_empty() {
-        doc! {}
-    } else {
-        doc! { "$and": filter_conditions }
-    };
+    // Each interval contributes a row per pool, so sorting and paging apply to the pool rows.
+    history::respond(params, repository, query, |query, cursor| async move {
+        _pool_rows(repository, &query, params, cursor.as_ref(), fields).await
+    })
+    .await
+}
 
-    let limit = params.limit.unwrap_or(10).clamp(1, 100) as i64;
-    let page = params.page.unwrap_or(1).max(1);
-    let skip = (page - 1) * limit as u32;
+/// The page of per-pool rows at `cursor`.
+async fn _pool_rows(
+    repository: &dyn EarningsRepository,
+    query: &HistoryQuery,
+    params: &query::HistoryQuery,
+    cursor: Option<&Cursor>,
+    fields: Option<&[String]>,
+) -> RepoResult<Envelope<Metadata>> {
+    let find = |query: HistoryQuery| async move { repository.find_pool_rows(&query).await };
+    let projected = HistoryQuery { fields: fields.map(<[String]>::to_vec), ..query.clone() };
+    let page = pagination::read(&projected, cursor, find, |document: &Document| Ok(document.clone())).await?;
+
+    let mut pools_data = Vec::new();
+    for row in page.rows {
+        let times = (row.get_i64("startTime").unwrap_or_default(), row.get_i64("endTime").unwrap_or_default());
+        let mut pool_doc = to_document(&history::filled(row, &PoolEarnings::default())?)?;
+        pool_doc.insert("startTime", times.0);
+        pool_doc.insert("endTime", times.1);
+        pools_data.push(shape(pool_doc, fields));
+    }
 
-    let sort_doc = if let Some(sort_by) = params.sort_by {
-        let sort_order = match params.order.as_deref() {
-            Some("asc") => 1,
-            Some("desc") | _ => -1,
-        };
-        doc! { sort_by: sort_order }
-    } else {
-        doc! { "startTime": -1 }
+    let total = match params.include_total {
+        true => Some(repository.count_pool_rows(query).await?),
+        false => None,
     };
+    Ok(Envelope { data: pools_data, meta: None, next_cursor: page.next_cursor, prev_cursor: page.prev_cursor, total })
+}
 
-    if params.summary.unwrap_or(false) {
-        let mut earnings_cursor = match earnings_collection.find(filter_doc.clone())
-            .sort(sort_doc.clone())
-            .skip(skip as u64)
-            .limit(limit)
-            .await {
-            Ok(cursor) => cursor,
-            Err(e) => {
-                eprintln!("Error fetching earnings data: {:?}", e);
-                return HttpResponse::InternalServerError().json("Error fetching earnings data");
-            }
-        };
+/// Summary metadata over every interval in `filter` rather than the returned page.
+async fn _range_metadata(repository: &dyn EarningsRepository, filter: &HistoryFilter) -> RepoResult<Metadata> {
+    let accumulators = [
+        Accumulator::Count,
+        Accumulator::Min("startTime"),
+        Accumulator::Max("endTime"),
+        Accumulator::Sum("avgNodeCount"),
+        Accumulator::Sum("blockRewards"),
+        Accumulator::Sum("bondingEarnings"),
+        Accumulator::Sum("earnings"),
+        Accumulator::Sum("liquidityEarnings"),
+        Accumulator::Sum("liquidityFees"),
+        Accumulator::Last("runePriceUSD"),
+    ];
+    let values = repository.aggregate(filter, &accumulators).await?;
+
+    let rows: f64 = aggregate_string(&values[0]).parse().unwrap_or_default();
+    let node_counts: f64 = aggregate_string(&values[3]).parse().unwrap_or_default();
+    let avg_node_count = if rows > 0.0 { node_counts / rows } else { 0.0 };
+
+    Ok(Metadata {
+        start_time: aggregate_string(&values[1]),
+        end_time: aggregate_string(&values[2]),
+        avg_node_count: Decimal::from_f64(avg_node_count).to_string(),
+        block_rewards: aggregate_string(&values[4]),
+        bonding_earnings: aggregate_string(&values[5]),
+        earnings: aggregate_string(&values[6]),
+        liquidity_earnings: aggregate_string(&values[7]),
+        liquidity_fees: aggregate_string(&values[8]),
+        rune_price_usd: aggregate_string(&values[9]),
+    })
+}
 
-        let mut earnings_with_pools = Vec::new();
-
-        while let Some(earnings_result) = earnings_cursor.next().await {
-            match earnings_result {
-                Ok(earnings) => {
-                    let mut pools_cursor = match pools_collection
-                        .find(doc! { "earnings_id": earnings.id.unwrap() })
-                        .sort(sort_doc.clone())
-                        .limit(10)
-                        .await {
-                        Ok(cursor) => cursor,
-                        Err(e) => {
-                            eprintln!("Error fetching pools data: {:?}", e);
-                            return HttpResponse::InternalServerError().json("Error fetching pools data");
-                        }
-                    };
-
-                    let mut pools = Vec::new();
-                    while let Some(pool_result) = pools_cursor.next().await {
-                        match pool_result {
-                            Ok(pool) => {
-                                let mut pool_doc = to_document(&pool).unwrap();
-                                pool_doc.remove("_id");
-                                pool_doc.remove("earnings_id");
-                                pools.push(pool_doc);
-                            }
-                            Err(e) => {
-                                eprintln!("Error processing pool data: {:?}", e);
-                                return HttpResponse::InternalServerError().json("Error processing pool data");
-                            }
-                        }
-                    }
-
-                    let mut earnings_doc = to_document(&earnings).unwrap();
-                    earnings_doc.remove("_id");
-                    earnings_doc.insert("pools", pools);
-                    earnings_with_pools.push(earnings_doc);
-                }
-                Err(e) => {
-                    eprintln!("Error processing earnings data: {:?}", e);
-                    return HttpResponse::InternalServerError().json("Error processing earnings data");
-                }
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use serde_json::{json, Value};
+    use crate::api::testing::{earnings_repos, get};
 
-        if earnings_with_pools.is_empty() {
-            HttpResponse::NotFound().json("No data found")
-        } else {
-            HttpResponse::Ok().json(earnings_with_pools)
-        }
-    } else {
-        let mut pools_cursor = match pools_collection.find(filter_doc)
-            .sort(sort_doc.clone())
-            .skip(skip as u64)
-            .limit(limit)
-            .await {
-            Ok(cursor) => cursor,
-            Err(e) => {
-                eprintln!("Error fetching pools data: {:?}", e);
-                return HttpResponse::InternalServerError().json("Error fetching pools data");
-            }
-        };
+    fn _rows(page: &Value) -> Vec<(i64, &str, &str)> {
+        let rows = page["data"].as_array().unwrap().iter();
+        rows.map(|row| (row["startTime"].as_i64().unwrap(), row["pool"].as_str().unwrap(), row["earnings"].as_str().unwrap())).collect()
+    }
 
-        let mut pools_data = Vec::new();
-
-        while let Some(pool_result) = pools_cursor.next().await {
-            match pool_result {
-                Ok(pool) => {
-                    let earnings_id = pool.earnings_id.clone(); 
-                    let earnings = match earnings_collection.find_one(doc! { "_id": earnings_id }).await {
-                        Ok(Some(earnings)) => earnings,
-                        Ok(None) => {
-                            eprintln!("No earnings found for pool: {:?}", pool);
-                            return HttpResponse::NotFound().json("No earnings found for pool");
-                        }
-                        Err(e) => {
-                            eprintln!("Error fetching earnings for pool: {:?}", e);
-                            return HttpResponse::InternalServerError().json("Error fetching earnings for pool");
-                        }
-                    };
-
-                    let mut pool_doc = to_document(&pool).unwrap();
-                    pool_doc.remove("_id");
-                    pool_doc.remove("earnings_id");
-                    
-                    pool_doc.insert("startTime", earnings.start_time);
-                    pool_doc.insert("endTime", earnings.end_time);
-                    
-                    pools_data.push(pool_doc);
-                }
-                Err(e) => {
-                    eprintln!("Error processing pool data: {:?}", e);
-                    return HttpResponse::InternalServerError().json("Error processing pool data");
-                }
-            }
-        }
+    #[actix_web::test]
+    async fn pages_pool_rows_across_intervals() {
+        let repos = earnings_repos().await;
+        let (status, first) = get(&repos, "/earnings?order=asc&limit=3&include_total=true").await;
+        assert_eq!(status, StatusCode::OK, "{}", first);
+        // Rows of one interval fall back to `pool`, descending.
+        assert_eq!(_rows(&first), [(0, "ETH.ETH", "1"), (0, "BTC.BTC", "10"), (3600, "ETH.ETH", "1")]);
+        assert_eq!((&first["total"], &first["meta"]), (&json!(6), &Value::Null));
+
+        let cursor = first["next_cursor"].as_str().unwrap();
+        let (_, second) = get(&repos, &format!("/earnings?order=asc&limit=3&cursor={}", cursor)).await;
+        assert_eq!(_rows(&second), [(3600, "BTC.BTC", "20"), (7200, "ETH.ETH", "1"), (7200, "BTC.BTC", "30")]);
+        assert_eq!(second["next_cursor"], Value::Null);
+    }
 
-        if pools_data.is_empty() {
-            HttpResponse::NotFound().json("No data found")
-        } else {
-            HttpResponse::Ok().json(pools_data)
+    #[actix_web::test]
+    async fn filters_pool_rows_to_the_range_and_pools() {
+        let repos = earnings_repos().await;
+        let (_, body) = get(&repos, "/earnings?pool=BTC.BTC&from=3600&order=asc&include_total=true").await;
+        assert_eq!((_rows(&body), &body["total"]), (vec![(3600, "BTC.BTC", "20"), (7200, "BTC.BTC", "30")], &json!(2)));
+
+        let (_, grouped) = get(&repos, "/earnings?pool=BTC.BTC,ETH.ETH&to=3600").await;
+        assert_eq!(_rows(&grouped["BTC.BTC"]), [(0, "BTC.BTC", "10")]);
+        assert_eq!(_rows(&grouped["ETH.ETH"]), [(0, "ETH.ETH", "1")]);
+    }
+
+    #[actix_web::test]
+    async fn summaries_carry_metadata_over_the_range() {
+        let repos = earnings_repos().await;
+        let (status, body) = get(&repos, "/earnings?summary=true&from=3600&order=asc&limit=1").await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!((&body["data"][0]["startTime"], &body["data"][0]["earnings"]), (&json!(3600), &json!("21")));
+        assert_eq!(body["data"][0]["pools"].as_array().unwrap().len(), 2);
+        assert!(body["next_cursor"].is_string());
+
+        let meta = &body["meta"];
+        assert_eq!((&meta["startTime"], &meta["endTime"], &meta["earnings"]), (&json!("3600"), &json!("10800"), &json!("52")));
+        assert_eq!(meta["avgNodeCount"], "3");
+    }
+
+    #[actix_web::test]
+    async fn a_summary_pool_trims_the_embedded_pools() {
+        let repos = earnings_repos().await;
+        let (_, body) = get(&repos, "/earnings?summary=true&pool=ETH.ETH&order=asc").await;
+        let intervals = body["data"].as_array().unwrap();
+        assert_eq!(intervals.len(), 3);
+        for interval in intervals {
+            assert_eq!(interval["pools"], json!([{ "pool": "ETH.ETH", "assetLiquidityFees": "0", "earnings": "1", "rewards": "0", "runeLiquidityFees": "0", "saverEarning": "0", "totalLiquidityFeesRune": "0" }]));
         }
+        // Intervals stay whole-network.
+        assert_eq!((&body["data"][0]["earnings"], &body["meta"]["earnings"]), (&json!("11"), &json!("63")));
     }
-}
\ No newline at end of file
+}
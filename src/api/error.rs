@@ -0,0 +1,178 @@
+use std::fmt;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::{HttpResponse, ResponseError};
+use serde::Serialize;
+use serde_json::Value;
+use utoipa::ToSchema;
+use crate::repository::RepositoryError;
+
+const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Tags every request with an id, the caller's `x-request-id` if sent, and echoes it back.
+pub async fn with_request_id(request: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty() && value.len() <= 128)
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("{:016x}", rand::random::<u64>()));
+
+    let mut response = REQUEST_ID.scope(id.clone(), next.call(request)).await?;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    Ok(response)
+}
+
+/// Every error the API returns, rendered as the same JSON body.
+#[derive(Debug)]
+pub enum ApiError {
+    /// A request parameter is missing or invalid; `details` says more where it helps.
+    BadRequest { message: String, details: Option<Value> },
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    /// Storage or another dependency failed. The cause is logged, not returned.
+    Internal(String),
+}
+
+/// The body of every error response.
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody<'a> {
+    /// `bad_request`, `unauthorized`, `forbidden`, `not_found` or `internal_error`.
+    code: &'static str,
+    message: &'a str,
+    #[schema(value_type = Option<Object>)]
+    details: Option<&'a Value>,
+    /// The request's `x-request-id`, also returned as that header.
+    request_id: Option<String>,
+}
+
+impl ApiError {
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        ApiError::BadRequest { message: message.into(), details: None }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest { .. } => "bad_request",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::BadRequest { message, .. } | ApiError::Unauthorized(message) | ApiError::Forbidden(message) | ApiError::NotFound(message) => {
+                f.write_str(message)
+            }
+            ApiError::Internal(_) => f.write_str("Internal server error"),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest { .. } => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let request_id = REQUEST_ID.try_with(Clone::clone).ok();
+        if let ApiError::Internal(cause) = self {
+            log::error!("Request {} failed: {}", request_id.as_deref().unwrap_or("-"), cause);
+        }
+        let details = match self {
+            ApiError::BadRequest { details, .. } => details.as_ref(),
+            _ => None,
+        };
+        HttpResponse::build(self.status_code()).json(ErrorBody { code: self.code(), message: &self.to_string(), details, request_id })
+    }
+}
+
+impl From<RepositoryError> for ApiError {
+    fn from(e: RepositoryError) -> Self {
+        ApiError::Internal(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use serde_json::json;
+    use crate::api::testing::{call, get};
+    use crate::repository::Repositories;
+
+    #[actix_web::test]
+    async fn errors_share_one_json_shape() {
+        let repos = Repositories::in_memory();
+        let (status, body) = get(&repos, "/depth-history?limit=ten").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "bad_request");
+        assert!(body["message"].as_str().unwrap().contains("invalid digit"), "{}", body);
+        assert_eq!(body["details"], Value::Null);
+        assert_eq!(body["request_id"].as_str().map(str::len), Some(16));
+
+        let (status, body) = get(&repos, "/swaps-history?sort_by=depth").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["details"]["sortable"].as_array().unwrap().contains(&json!("totalVolume")));
+
+        let (status, body) = get(&repos, "/no-such-route").await;
+        assert_eq!((status, &body["code"], &body["message"]), (StatusCode::NOT_FOUND, &json!("not_found"), &json!("No such route")));
+    }
+
+    #[actix_web::test]
+    async fn internal_errors_hide_their_cause() {
+        let response = ApiError::Internal("connection refused".to_string()).error_response();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body: Value = serde_json::from_slice(&actix_web::body::to_bytes(response.into_body()).await.unwrap()).unwrap();
+        assert_eq!(body, json!({ "code": "internal_error", "message": "Internal server error", "details": null, "request_id": null }));
+    }
+
+    #[actix_web::test]
+    async fn echoes_the_callers_request_id() {
+        let repos = Repositories::in_memory();
+        let request = TestRequest::get().uri("/earnings?from=yesterday").insert_header(("x-request-id", "trace-42"));
+        let (status, headers, body) = call(&repos, request).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(headers.get("x-request-id").unwrap(), "trace-42");
+        assert_eq!(body["request_id"], "trace-42");
+
+        let (status, headers, _) = call(&repos, TestRequest::get().uri("/runepool-history").insert_header(("x-request-id", "ok-1"))).await;
+        assert_eq!((status, headers.get("x-request-id").unwrap().to_str().unwrap()), (StatusCode::OK, "ok-1"));
+        let (_, headers, _) = call(&repos, TestRequest::get().uri("/runepool-history")).await;
+        assert_eq!(headers.get("x-request-id").unwrap().len(), 16);
+    }
+
+    #[actix_web::test]
+    async fn an_empty_range_is_an_empty_page() {
+        let repos = Repositories::in_memory();
+        for uri in ["/depth-history?pool=BTC.BTC", "/swaps-history", "/runepool-history", "/earnings?include_total=true"] {
+            let (status, body) = get(&repos, uri).await;
+            assert_eq!(status, StatusCode::OK, "{}", uri);
+            assert_eq!((&body["data"], &body["meta"], &body["next_cursor"], &body["prev_cursor"]), (&json!([]), &Value::Null, &Value::Null, &Value::Null), "{}", uri);
+            assert!(body.as_object().unwrap().contains_key("meta"), "{}", uri);
+        }
+        let (_, body) = get(&repos, "/earnings?include_total=true").await;
+        assert_eq!(body["total"], 0);
+    }
+}
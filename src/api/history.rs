@@ -0,0 +1,144 @@
+use std::future::Future;
+use actix_web::HttpResponse;
+use bson::{from_document, to_document, Document};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use crate::api::error::ApiError;
+use crate::api::query::{self, shape};
+use crate::repository::{stored_document, HistoryFilter, HistoryQuery, HistoryRecord, HistoryRepository, RepoResult};
+use crate::services::pagination::{self, Cursor, Envelope};
+use crate::services::pools;
+
+/// One page, or for several pools or a wildcard an object of pages keyed by pool; those
+/// pages take no cursor.
+pub async fn respond<T, R, P, F, Fut>(params: &query::HistoryQuery, repository: &R, query: HistoryQuery, page: F) -> Result<HttpResponse, ApiError>
+where
+    T: HistoryRecord,
+    R: HistoryRepository<T> + ?Sized,
+    P: Serialize,
+    F: Fn(HistoryQuery, Option<Cursor>) -> Fut,
+    Fut: Future<Output = RepoResult<P>>,
+{
+    let narrowed = |pool: Option<String>| HistoryQuery { filter: HistoryFilter { pool, ..query.filter.clone() }, ..query.clone() };
+    if let Some(selection) = params.grouped_pools()? {
+        let pools = selection.resolve(repository).await?;
+        let groups = pools::grouped(pools, |pool| page(narrowed(Some(pool)), None)).await?;
+        return Ok(HttpResponse::Ok().json(groups));
+    }
+
+    let response = page(narrowed(params.single_pool()), params.cursor.clone()).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// The page of `query` at `cursor`, with `total` when asked for and `meta` unless the page
+/// is empty. `trim` adjusts each row before it is narrowed to `fields`.
+pub async fn page<T, R, M>(
+    repository: &R,
+    query: &HistoryQuery,
+    params: &query::HistoryQuery,
+    cursor: Option<&Cursor>,
+    fields: Option<&[String]>,
+    meta: Option<impl Future<Output = RepoResult<M>>>,
+    trim: impl Fn(&mut T),
+) -> RepoResult<Envelope<M>>
+where
+    T: HistoryRecord + Default,
+    R: HistoryRepository<T> + ?Sized,
+{
+    let projected = HistoryQuery { fields: fields.map(<[String]>::to_vec), ..query.clone() };
+    let find = |query: HistoryQuery| async move { repository.find_documents(&query).await };
+    let page = pagination::read(&projected, cursor, find, |document: &Document| Ok(document.clone())).await?;
+
+    let mut data = Vec::with_capacity(page.rows.len());
+    for document in page.rows {
+        let mut row: T = filled(document, &T::default())?;
+        trim(&mut row);
+        let mut document = to_document(&row)?;
+        document.remove("_id");
+        data.push(shape(document, fields));
+    }
+
+    let total = match params.include_total {
+        true => Some(repository.count(query).await?),
+        false => None,
+    };
+    let meta = match (data.is_empty(), meta) {
+        (false, Some(meta)) => Some(meta.await?),
+        _ => None,
+    };
+    Ok(Envelope { data, meta, next_cursor: page.next_cursor, prev_cursor: page.prev_cursor, total })
+}
+
+/// Reads a projected row as a model, filling the fields left out from `template`.
+pub fn filled<R: DeserializeOwned + Serialize>(mut document: Document, template: &R) -> RepoResult<R> {
+    for (field, value) in stored_document(template)? {
+        if !document.contains_key(&field) {
+            document.insert(field, value);
+        }
+    }
+    Ok(from_document(document)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use serde_json::{json, Value};
+    use crate::api::testing::{get, tied_swaps_repos};
+
+    fn _fields(body: &Value) -> Vec<Vec<String>> {
+        body["data"].as_array().unwrap().iter().map(|row| row.as_object().unwrap().keys().cloned().collect()).collect()
+    }
+
+    #[actix_web::test]
+    async fn fields_keep_only_the_named_fields() {
+        let repos = tied_swaps_repos().await;
+        let (status, body) = get(&repos, "/swaps-history?pool=BTC.BTC&fields=totalVolume,startTime&limit=2").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(_fields(&body), [["startTime", "totalVolume"], ["startTime", "totalVolume"]]);
+        assert_eq!(body["data"][0], json!({ "startTime": 14400, "totalVolume": "100" }));
+        // Metadata is over the whole rows, whatever the page keeps.
+        assert_eq!(body["meta"]["totalCount"], "10");
+    }
+
+    #[actix_web::test]
+    async fn exclude_drops_the_named_fields() {
+        let repos = tied_swaps_repos().await;
+        let (_, body) = get(&repos, "/swaps-history?pool=BTC.BTC&exclude=pool,averageSlip&limit=1").await;
+        let fields = &_fields(&body)[0];
+        assert!(fields.contains(&"totalVolume".to_string()) && fields.contains(&"endTime".to_string()));
+        assert!(!fields.contains(&"pool".to_string()) && !fields.contains(&"averageSlip".to_string()));
+    }
+
+    #[actix_web::test]
+    async fn fields_and_exclude_together_are_rejected() {
+        let repos = tied_swaps_repos().await;
+        let (status, body) = get(&repos, "/swaps-history?fields=pool&exclude=startTime").await;
+        assert_eq!((status, &body["message"]), (StatusCode::BAD_REQUEST, &json!("use either fields or exclude, not both")));
+        let (status, body) = get(&repos, "/earnings?fields=volume").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["details"]["fields"].as_array().unwrap().contains(&json!("earnings")));
+    }
+
+    #[actix_web::test]
+    async fn cursors_page_on_fields_left_out() {
+        let repos = tied_swaps_repos().await;
+        // Sorted on `totalVolume` and then `startTime` and `pool`, none of them returned.
+        let uri = |cursor: Option<&str>| {
+            let cursor = cursor.map(|cursor| format!("&cursor={}", cursor)).unwrap_or_default();
+            format!("/swaps-history?pool=BTC.BTC&fields=totalCount&sort=-totalVolume&limit=2{}", cursor)
+        };
+        let mut counts = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let (status, body) = get(&repos, &uri(cursor.as_deref())).await;
+            assert_eq!(status, StatusCode::OK, "{}", body);
+            counts.extend(body["data"].as_array().unwrap().iter().map(|row| row["totalCount"].as_i64().unwrap()));
+            match body["next_cursor"].as_str() {
+                Some(next) => cursor = Some(next.to_string()),
+                None => break,
+            }
+        }
+        // Hour 2 has the most volume, then hours 4 and 1, then 3 and 0; ties go newest first.
+        assert_eq!(counts, [2, 4, 1, 3, 0]);
+    }
+}
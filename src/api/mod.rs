@@ -1,4 +1,200 @@
+use actix_web::{web, HttpResponse};
+use crate::api::error::ApiError;
+use crate::repository::Repositories;
+
 pub mod depth_history;
 pub mod runepool;
 pub mod swaps;
-pub mod earnings;
\ No newline at end of file
+pub mod earnings;
+pub mod admin;
+pub mod error;
+pub mod history;
+pub mod openapi;
+pub mod query;
+pub mod v2;
+
+/// Declares every route once, so the app registers and the OpenAPI test checks the same list.
+macro_rules! routes {
+    ($($method:ident $path:literal => $handler:path,)*) => {
+        /// Method and path of every route `configure` registers.
+        #[cfg(test)]
+        pub const ROUTES: &[(&str, &str)] = &[$((stringify!($method), $path)),*];
+
+        pub fn configure(cfg: &mut web::ServiceConfig) {
+            $(cfg.route($path, web::$method().to($handler));)*
+        }
+    };
+}
+
+routes! {
+    get "/depth-history" => depth_history::depth_history_route,
+    get "/runepool-history" => runepool::runepool_history_route,
+    get "/earnings" => earnings::earnings_with_pools_route,
+    get "/swaps-history" => swaps::swaps_history_route,
+    get "/v2/history/depths/{pool}" => v2::depths_route,
+    get "/v2/history/swaps" => v2::swaps_route,
+    get "/v2/history/runepool" => v2::runepool_route,
+    get "/v2/history/earnings" => v2::earnings_route,
+    get "/admin/gaps" => admin::gaps_route,
+    post "/admin/gaps/backfill" => admin::backfill_gaps_route,
+}
+
+/// The routes, with malformed queries and unknown paths answered in the shared error body.
+pub fn configure_app(cfg: &mut web::ServiceConfig, repos: &Repositories) {
+    cfg.app_data(web::QueryConfig::default().error_handler(|e, _| ApiError::bad_request(e.to_string()).into()))
+        .app_data(web::Data::new(repos.clone()))
+        .app_data(web::Data::from(repos.runepool.clone()))
+        .app_data(web::Data::from(repos.depth.clone()))
+        .app_data(web::Data::from(repos.swaps.clone()))
+        .app_data(web::Data::from(repos.earnings.clone()))
+        .configure(configure)
+        .default_service(web::to(|| async { Err::<HttpResponse, _>(ApiError::NotFound("No such route".to_string())) }));
+}
+
+#[cfg(test)]
+pub mod testing {
+    use actix_web::http::header::HeaderMap;
+    use actix_web::http::StatusCode;
+    use actix_web::middleware::from_fn;
+    use actix_web::test::{self, TestRequest};
+    use actix_web::App;
+    use serde_json::Value;
+    use crate::api::error::with_request_id;
+    use crate::models::amount::{Amount, Decimal};
+    use crate::models::depth_history::DepthHistory;
+    use crate::models::earnings_history::{EarningsHistory, PoolEarnings};
+    use crate::models::runepool_history::RunePoolHistory;
+    use crate::models::swaps_history::SwapHistory;
+    use crate::repository::Repositories;
+
+    /// Sends `request` through the routes as `serve` mounts them, returning the status,
+    /// headers and JSON body.
+    pub async fn call(repos: &Repositories, request: TestRequest) -> (StatusCode, HeaderMap, Value) {
+        let app = test::init_service(App::new().wrap(from_fn(with_request_id)).configure(|cfg| super::configure_app(cfg, repos))).await;
+        let response = test::call_service(&app, request.to_request()).await;
+        let (status, headers) = (response.status(), response.headers().clone());
+        let body = test::read_body(response).await;
+        (status, headers, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    /// `GET uri`, returning the status and JSON body.
+    pub async fn get(repos: &Repositories, uri: &str) -> (StatusCode, Value) {
+        let (status, _, body) = call(repos, TestRequest::get().uri(uri)).await;
+        (status, body)
+    }
+
+    /// Four hours of two pools, BTC.BTC deepening by 100 an hour.
+    pub async fn depth_repos() -> Repositories {
+        let repos = Repositories::in_memory();
+        let depths = (0..4)
+            .flat_map(|hour| {
+                [("BTC.BTC", 100 * (hour + 1)), ("ETH.ETH", 1000)].map(|(pool, depth)| DepthHistory {
+                    pool: pool.to_string(),
+                    start_time: hour as i64 * 3600,
+                    end_time: hour as i64 * 3600 + 3600,
+                    asset_depth: Amount(depth),
+                    members_count: hour as i64 + 1,
+                    ..DepthHistory::default()
+                })
+            })
+            .collect();
+        repos.depth.upsert(depths, None).await.unwrap();
+        repos
+    }
+
+    /// Four hours of BTC.BTC swaps, volume growing by 100 an hour and the first hour slipping most.
+    pub async fn swaps_repos() -> Repositories {
+        let repos = Repositories::in_memory();
+        let swaps = (0..4)
+            .map(|hour| SwapHistory {
+                pool: "BTC.BTC".to_string(),
+                start_time: hour * 3600,
+                end_time: hour * 3600 + 3600,
+                total_count: 2,
+                total_volume: Amount(100 * (hour as i128 + 1)),
+                average_slip: Decimal::from_f64(if hour == 0 { 4.0 } else { 1.0 }),
+                ..SwapHistory::default()
+            })
+            .collect();
+        repos.swaps.upsert(swaps, None).await.unwrap();
+        repos
+    }
+
+    /// Five hours of BTC.BTC swaps whose volumes repeat 0, 100, 200, each counting its hour.
+    pub async fn tied_swaps_repos() -> Repositories {
+        let repos = Repositories::in_memory();
+        let swaps = (0..5)
+            .map(|hour| SwapHistory {
+                pool: "BTC.BTC".to_string(),
+                start_time: hour * 3600,
+                end_time: hour * 3600 + 3600,
+                total_count: hour,
+                total_volume: Amount(100 * (hour as i128 % 3)),
+                ..SwapHistory::default()
+            })
+            .collect();
+        repos.swaps.upsert(swaps, None).await.unwrap();
+        repos
+    }
+
+    /// Four hours of RUNEPool, gaining a member and 10 units an hour.
+    pub async fn runepool_repos() -> Repositories {
+        let repos = Repositories::in_memory();
+        let rows = (0..4)
+            .map(|hour| RunePoolHistory {
+                start_time: hour * 3600,
+                end_time: hour * 3600 + 3600,
+                count: hour + 1,
+                units: Amount(10 * (hour as i128 + 1)),
+                ..RunePoolHistory::default()
+            })
+            .collect();
+        repos.runepool.upsert(rows, None).await.unwrap();
+        repos
+    }
+
+    /// Three hours of earnings from two pools, BTC.BTC earning 10 more each hour.
+    pub async fn earnings_repos() -> Repositories {
+        let repos = Repositories::in_memory();
+        let rows = (0..3)
+            .map(|hour| {
+                let pools = [("BTC.BTC", 10 * (hour as i128 + 1)), ("ETH.ETH", 1)]
+                    .map(|(pool, earnings)| PoolEarnings { pool: pool.to_string(), earnings: Amount(earnings), ..PoolEarnings::default() });
+                EarningsHistory {
+                    start_time: hour * 3600,
+                    end_time: hour * 3600 + 3600,
+                    earnings: pools.iter().map(|pool| pool.earnings).fold(Amount(0), |sum, earnings| Amount(sum.0 + earnings.0)),
+                    avg_node_count: Decimal::from_f64(3.0),
+                    pools: pools.to_vec(),
+                    ..EarningsHistory::default()
+                }
+            })
+            .collect();
+        repos.earnings.upsert(rows, None).await.unwrap();
+        repos
+    }
+
+    /// An hour of depth and swaps on the first and third days, none on the second.
+    pub async fn gapped_repos() -> Repositories {
+        let repos = Repositories::in_memory();
+        let days = [0, 2 * 86400];
+        let depths = days
+            .iter()
+            .enumerate()
+            .map(|(day, start_time)| DepthHistory {
+                pool: "BTC.BTC".to_string(),
+                start_time: *start_time,
+                end_time: start_time + 3600,
+                asset_depth: Amount(100 * (day as i128 + 1)),
+                ..DepthHistory::default()
+            })
+            .collect();
+        repos.depth.upsert(depths, None).await.unwrap();
+        let swaps = days
+            .iter()
+            .map(|start_time| SwapHistory { pool: "BTC.BTC".to_string(), start_time: *start_time, end_time: start_time + 3600, total_count: 3, ..SwapHistory::default() })
+            .collect();
+        repos.swaps.upsert(swaps, None).await.unwrap();
+        repos
+    }
+}
@@ -0,0 +1,115 @@
+use utoipa::OpenApi;
+use crate::api::{admin, depth_history, earnings, error::ErrorBody, runepool, swaps, v2};
+use crate::models::amount::{Amount, Decimal};
+use crate::models::depth_history::{self as depth_model, DepthHistory};
+use crate::models::earnings_history::{self as earnings_model, EarningsHistory, PoolEarnings};
+use crate::models::runepool_history::{self as runepool_model, RunePoolHistory};
+use crate::models::swaps_history::{self as swaps_model, SwapHistory};
+use crate::services::gaps::{BackfillOutcome, Gap};
+use crate::services::pagination::{DepthHistoryPage, EarningsHistoryPage, RunePoolHistoryPage, SwapHistoryPage};
+
+/// The spec served at `/api-docs/openapi.json` and browsed at `/swagger-ui/`.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Midgard history API", description = "Hourly THORChain pool history mirrored from Midgard."),
+    paths(
+        depth_history::depth_history_route,
+        runepool::runepool_history_route,
+        earnings::earnings_with_pools_route,
+        swaps::swaps_history_route,
+        v2::depths_route,
+        v2::swaps_route,
+        v2::runepool_route,
+        v2::earnings_route,
+        admin::gaps_route,
+        admin::backfill_gaps_route,
+    ),
+    components(schemas(
+        Amount,
+        Decimal,
+        DepthHistory,
+        SwapHistory,
+        RunePoolHistory,
+        EarningsHistory,
+        PoolEarnings,
+        depth_model::Metadata,
+        swaps_model::Metadata,
+        runepool_model::Metadata,
+        earnings_model::Metadata,
+        DepthHistoryPage,
+        SwapHistoryPage,
+        RunePoolHistoryPage,
+        EarningsHistoryPage,
+        ErrorBody,
+        Gap,
+        BackfillOutcome,
+    )),
+    tags(
+        (name = "history", description = "Paged history with metadata over the whole range"),
+        (name = "midgard", description = "Midgard's v2 history routes, served from stored history"),
+        (name = "admin", description = "Gap scans and backfills, behind `ADMIN_TOKEN`"),
+    )
+)]
+pub struct ApiDoc;
+
+#[cfg(test)]
+mod tests {
+    use super::ApiDoc;
+    use crate::api::ROUTES;
+    use serde_json::Value;
+    use utoipa::OpenApi;
+
+    /// Every registered route is documented under the same method and path, nothing else is,
+    /// and every `{param}` in a path is documented as a path parameter.
+    #[test]
+    fn spec_matches_routes() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).expect("spec serializes");
+        let paths = spec["paths"].as_object().expect("spec has paths");
+
+        let mut documented = Vec::new();
+        for (path, item) in paths {
+            for (method, operation) in item.as_object().into_iter().flatten() {
+                documented.push((method.clone(), path.clone()));
+                let parameters = operation["parameters"].as_array().cloned().unwrap_or_default();
+                for name in path.split('/').filter_map(|segment| segment.strip_prefix('{')?.strip_suffix('}')) {
+                    assert!(
+                        parameters.iter().any(|parameter| parameter["name"] == name && parameter["in"] == "path"),
+                        "{} {} does not document its {{{}}} path parameter",
+                        method,
+                        path,
+                        name,
+                    );
+                }
+            }
+        }
+        let mut registered: Vec<(String, String)> = ROUTES.iter().map(|(method, path)| (method.to_string(), path.to_string())).collect();
+        documented.sort();
+        registered.sort();
+        assert_eq!(documented, registered, "the OpenAPI paths have drifted from the routes in api::configure");
+    }
+
+    /// Every `$ref` names a schema listed in `components`.
+    #[test]
+    fn spec_refs_resolve() {
+        fn refs(value: &Value, found: &mut Vec<String>) {
+            match value {
+                Value::Object(fields) => {
+                    if let Some(Value::String(target)) = fields.get("$ref") {
+                        found.push(target.clone());
+                    }
+                    fields.values().for_each(|field| refs(field, found));
+                }
+                Value::Array(items) => items.iter().for_each(|item| refs(item, found)),
+                _ => {}
+            }
+        }
+
+        let spec = serde_json::to_value(ApiDoc::openapi()).expect("spec serializes");
+        let mut found = Vec::new();
+        refs(&spec, &mut found);
+        for target in found {
+            let name = target.strip_prefix("#/components/schemas/").unwrap_or(&target);
+            assert!(spec["components"]["schemas"].get(name).is_some(), "{} is not a component schema", target);
+        }
+    }
+}
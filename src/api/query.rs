@@ -0,0 +1,345 @@
+use std::future::{ready, Ready};
+use actix_web::dev::Payload;
+use actix_web::{web, FromRequest, HttpRequest};
+use bson::Document;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use utoipa::IntoParams;
+use crate::api::error::ApiError;
+use crate::repository::{self, HistoryFilter, RangeMatch};
+use crate::services::intervals::{self, Interval};
+use crate::services::pagination::{self, Cursor};
+use crate::services::pools::PoolSelection;
+use crate::services::sorting;
+
+/// Rows a page holds when `limit` is not given, and the most it may ask for.
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 100;
+
+/// The query string [`HistoryQuery`] is read from.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HistoryParams {
+    /// Start of the range: unix seconds, or an ISO-8601 date or date-time.
+    from: Option<String>,
+    /// End of the range, in the same forms as `from`.
+    to: Option<String>,
+    /// The range ending at `to`, or now, in hours, days or weeks: `36h`, `7d`, `2w`.
+    last: Option<String>,
+    /// `contained` (the default) for rows wholly inside the range, `overlapping` for rows
+    /// sharing any time with it.
+    range: Option<String>,
+    /// Comma-separated pools and `CHAIN.*` wildcards. Several pools or a wildcard return an
+    /// object of pages keyed by pool.
+    pool: Option<String>,
+    /// 1-based page number, when no `cursor` is given.
+    page: Option<u32>,
+    /// Rows per page, 1 to 100; 10 by default.
+    limit: Option<u32>,
+    /// Comma-separated sort fields, descending with a leading `-`: `-totalVolume,startTime`.
+    sort: Option<String>,
+    /// A single sort field, the older form of `sort`.
+    sort_by: Option<String>,
+    /// `asc` or `desc` (the default), for `sort_by`, or for `startTime` without a sort.
+    order: Option<String>,
+    /// Bucket size: `hour`, `day`, `week`, `month`, `quarter` or `year`.
+    interval: Option<String>,
+    /// Number of `interval` buckets, 1 to 400; 400 unless both `from` and `to` are given.
+    count: Option<u32>,
+    /// A `next_cursor` or `prev_cursor` from an earlier page.
+    cursor: Option<String>,
+    /// Counts the rows across every page into `total`.
+    include_total: Option<bool>,
+    /// Comma-separated fields to return, leaving out the rest: `startTime,totalVolumeUSD`.
+    fields: Option<String>,
+    /// Comma-separated fields to leave out, returning the rest.
+    exclude: Option<String>,
+}
+
+/// The range, pool, paging, sort and field parameters every history route takes. Sorting
+/// and fields depend on the model, so `storage_query` and `project` check them.
+#[derive(Debug)]
+pub struct HistoryQuery {
+    /// The resolved range, without a pool.
+    pub filter: HistoryFilter,
+    pub interval: Option<Interval>,
+    pub pools: Option<PoolSelection>,
+    pub limit: i64,
+    pub skip: u64,
+    pub cursor: Option<Cursor>,
+    pub include_total: bool,
+    sort: Option<String>,
+    sort_by: Option<String>,
+    order: Option<String>,
+    fields: Option<String>,
+    exclude: Option<String>,
+}
+
+impl FromRequest for HistoryQuery {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, ApiError>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(HistoryQuery::parse(req.query_string()))
+    }
+}
+
+impl HistoryQuery {
+    fn parse(query_string: &str) -> Result<Self, ApiError> {
+        let params = web::Query::<HistoryParams>::from_query(query_string)
+            .map_err(|e| ApiError::bad_request(e.to_string()))?
+            .into_inner();
+
+        let mut from = params.from.as_deref().map(|from| _timestamp("from", from)).transpose()?;
+        let to = params.to.as_deref().map(|to| _timestamp("to", to)).transpose()?;
+        if let Some(last) = params.last.as_deref() {
+            if from.is_some() || params.count.is_some() {
+                return Err(ApiError::bad_request("last cannot be combined with from or count"));
+            }
+            from = Some(to.unwrap_or_else(|| Utc::now().timestamp()) - _duration(last)?);
+        }
+        if let (Some(start), Some(end)) = (from, to) {
+            if start >= end {
+                return Err(ApiError::bad_request("start_time must be less than end_time"));
+            }
+        }
+        let range = match params.range.as_deref() {
+            None | Some("contained") => RangeMatch::Contained,
+            Some("overlapping") => RangeMatch::Overlapping,
+            Some(other) => return Err(ApiError::bad_request(format!("range must be contained or overlapping, not {:?}", other))),
+        };
+        let (interval, from, to) = intervals::from_params(params.interval.as_deref(), params.count, from, to).map_err(ApiError::bad_request)?;
+
+        let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+        Ok(HistoryQuery {
+            filter: HistoryFilter { from, to, range, pool: None },
+            interval,
+            pools: params.pool.as_deref().map(PoolSelection::parse).transpose().map_err(ApiError::bad_request)?,
+            limit: limit as i64,
+            skip: (params.page.unwrap_or(1).max(1) - 1) as u64 * limit as u64,
+            cursor: params.cursor.as_deref().map(Cursor::decode).transpose().map_err(ApiError::bad_request)?,
+            include_total: params.include_total.unwrap_or(false),
+            sort: params.sort,
+            sort_by: params.sort_by,
+            order: params.order,
+            fields: params.fields,
+            exclude: params.exclude,
+        })
+    }
+
+    /// The storage query for this request's page, sorted on the `sortable` fields it asks for
+    /// and then on `key`, so a cursor always has a row to resume from.
+    pub fn storage_query(&self, sortable: &[&str], key: &[&str]) -> Result<repository::HistoryQuery, ApiError> {
+        let sort = sorting::parse(self.sort.as_deref(), self.sort_by.as_deref(), self.order.as_deref(), sortable)
+            .map_err(|message| ApiError::BadRequest { message, details: Some(json!({ "sortable": sortable })) })?;
+        let sort = pagination::with_tiebreak(sort, key);
+        if let Some(cursor) = &self.cursor {
+            cursor.check(&sort).map_err(ApiError::bad_request)?;
+        }
+        let buckets = self.interval.map(|interval| repository::Buckets { interval: Some(interval), per_pool: true });
+        Ok(repository::HistoryQuery { filter: self.filter.clone(), sort, skip: self.skip, limit: Some(self.limit), after: None, fields: None, buckets })
+    }
+
+    /// The `available` fields that `fields` or `exclude` keep, or `None` to return whole
+    /// rows. Storage reads only these and the sort keys, so cursors keep every sort key.
+    pub fn project(&self, available: &[&str]) -> Result<Option<Vec<String>>, ApiError> {
+        let available: Vec<&str> = available.iter().copied().filter(|field| *field != "_id").collect();
+        let kept: Vec<&str> = match (self.fields.as_deref(), self.exclude.as_deref()) {
+            (None, None) => return Ok(None),
+            (Some(_), Some(_)) => return Err(ApiError::bad_request("use either fields or exclude, not both")),
+            (Some(fields), None) => _field_list("fields", fields, &available)?,
+            (None, Some(exclude)) => {
+                let excluded = _field_list("exclude", exclude, &available)?;
+                available.iter().copied().filter(|field| !excluded.contains(field)).collect()
+            }
+        };
+        Ok(Some(kept.into_iter().map(str::to_string).collect()))
+    }
+
+    /// Rejects `pool` on routes whose history is not kept per pool, rather than ignoring it.
+    pub fn reject_pools(&self) -> Result<(), ApiError> {
+        match self.pools {
+            Some(_) => Err(ApiError::bad_request("this history is not kept per pool; drop pool")),
+            None => Ok(()),
+        }
+    }
+
+    /// The pool, when `pool` names exactly one.
+    pub fn single_pool(&self) -> Option<String> {
+        self.pools.as_ref().and_then(PoolSelection::single).map(str::to_string)
+    }
+
+    /// The selection, when `pool` names several pools or a wildcard and the response is
+    /// grouped by pool. Cursors page a single pool, so none may be given.
+    pub fn grouped_pools(&self) -> Result<Option<&PoolSelection>, ApiError> {
+        let selection = self.pools.as_ref().filter(|selection| selection.single().is_none());
+        if selection.is_some() && self.cursor.is_some() {
+            return Err(ApiError::bad_request("cursor pages a single pool; pass the pool it was returned for"));
+        }
+        Ok(selection)
+    }
+}
+
+/// The fields named in a `fields` or `exclude` list, each one of `available`.
+fn _field_list<'a>(name: &str, list: &str, available: &[&'a str]) -> Result<Vec<&'a str>, ApiError> {
+    let mut fields = Vec::new();
+    for field in list.split(',').map(str::trim) {
+        match available.iter().find(|available| **available == field) {
+            _ if field.is_empty() => return Err(ApiError::bad_request(format!("{} must not contain empty entries", name))),
+            Some(field) if !fields.contains(field) => fields.push(*field),
+            Some(_) => return Err(ApiError::bad_request(format!("{} names {:?} more than once", name, field))),
+            None => {
+                return Err(ApiError::BadRequest {
+                    message: format!("{} names unknown field {:?}", name, field),
+                    details: Some(json!({ "fields": available })),
+                })
+            }
+        }
+    }
+    Ok(fields)
+}
+
+/// Keeps only `fields` of a response row, in the row's own order.
+pub fn shape(row: Document, fields: Option<&[String]>) -> Document {
+    match fields {
+        Some(fields) => row.into_iter().filter(|(field, _)| fields.contains(field)).collect(),
+        None => row,
+    }
+}
+
+/// Unix seconds, an RFC 3339 date-time, or a date or date-time without an offset in UTC.
+fn _timestamp(name: &str, value: &str) -> Result<i64, ApiError> {
+    if let Ok(seconds) = value.parse::<i64>() {
+        return Ok(seconds);
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.timestamp())
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f").map(|time| time.and_utc().timestamp()))
+        .or_else(|_| NaiveDate::parse_from_str(value, "%Y-%m-%d").map(|date| date.and_time(NaiveTime::MIN).and_utc().timestamp()))
+        .map_err(|_| ApiError::bad_request(format!("{} must be unix seconds or an ISO-8601 date-time, not {:?}", name, value)))
+}
+
+/// Seconds in a `last` range such as `36h`, `7d` or `2w`.
+fn _duration(value: &str) -> Result<i64, ApiError> {
+    let invalid = || ApiError::bad_request(format!("last must be a number of hours, days or weeks such as 36h, 7d or 2w, not {:?}", value));
+    let unit = value.chars().last().ok_or_else(invalid)?;
+    let amount: i64 = value[..value.len() - unit.len_utf8()].parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        'h' => 3600,
+        'd' => 86400,
+        'w' => 7 * 86400,
+        _ => return Err(invalid()),
+    };
+    match amount > 0 {
+        true => amount.checked_mul(seconds).ok_or_else(invalid),
+        false => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn _error(query_string: &str) -> String {
+        HistoryQuery::parse(query_string).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn timestamps_take_seconds_and_iso_dates() {
+        assert_eq!(_timestamp("from", "1700000000").unwrap(), 1700000000);
+        assert_eq!(_timestamp("from", "2024-03-01").unwrap(), 1709251200);
+        assert_eq!(_timestamp("from", "2024-03-01T01:00:00").unwrap(), 1709254800);
+        assert_eq!(_timestamp("from", "2024-03-01T01:00:00.5").unwrap(), 1709254800);
+        assert_eq!(_timestamp("from", "2024-03-01T03:00:00+02:00").unwrap(), 1709254800);
+        assert_eq!(_timestamp("from", "2024-03-01T01:00:00Z").unwrap(), 1709254800);
+        for invalid in ["yesterday", "2024-13-01", "2024-03-01 01:00", ""] {
+            assert!(_timestamp("from", invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn durations_are_positive_hours_days_or_weeks() {
+        assert_eq!(_duration("36h").unwrap(), 36 * 3600);
+        assert_eq!(_duration("7d").unwrap(), 7 * 86400);
+        assert_eq!(_duration("2w").unwrap(), 14 * 86400);
+        for invalid in ["0d", "-1d", "h", "", "7", "7m", "1.5d", "99999999999999999999h", "9999999999999999w"] {
+            assert!(_duration(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn last_ends_at_to_and_excludes_from_and_count() {
+        let query = HistoryQuery::parse("last=2d&to=1700000000").unwrap();
+        assert_eq!((query.filter.from, query.filter.to), (Some(1700000000 - 2 * 86400), Some(1700000000)));
+
+        let query = HistoryQuery::parse("last=1h").unwrap();
+        let now = Utc::now().timestamp();
+        assert!((now - 3600 - 5..=now - 3600).contains(&query.filter.from.unwrap()));
+        assert_eq!(query.filter.to, None);
+
+        assert_eq!(_error("last=1d&from=1700000000"), "last cannot be combined with from or count");
+        assert_eq!(_error("last=1d&interval=hour&count=5"), "last cannot be combined with from or count");
+        assert!(_error("last=0h").starts_with("last must be"));
+    }
+
+    #[test]
+    fn ranges_must_run_forwards() {
+        assert_eq!(_error("from=1700000000&to=1700000000"), "start_time must be less than end_time");
+        assert_eq!(_error("from=2024-03-02&to=2024-03-01"), "start_time must be less than end_time");
+        assert!(HistoryQuery::parse("from=2024-03-01&to=2024-03-02").is_ok());
+        assert!(_error("range=inside").starts_with("range must be"));
+        assert_eq!(HistoryQuery::parse("range=overlapping").unwrap().filter.range, RangeMatch::Overlapping);
+    }
+
+    #[test]
+    fn intervals_move_from_to_a_bucket_start() {
+        let query = HistoryQuery::parse("interval=day&from=2024-03-01T13:00:00&to=2024-03-05").unwrap();
+        assert_eq!(query.interval, Some(Interval::Day));
+        assert_eq!((query.filter.from, query.filter.to), (Some(1709251200), Some(1709596800)));
+        assert!(_error("interval=minute").starts_with("unknown interval"));
+    }
+
+    #[test]
+    fn limits_are_clamped_and_pages_become_skips() {
+        let paging = |query_string: &str| {
+            let query = HistoryQuery::parse(query_string).unwrap();
+            (query.limit, query.skip)
+        };
+        assert_eq!(paging(""), (10, 0));
+        assert_eq!(paging("limit=0"), (1, 0));
+        assert_eq!(paging("limit=500"), (100, 0));
+        assert_eq!(paging("page=3&limit=20"), (20, 40));
+        assert_eq!(paging("page=0&limit=20"), (20, 0));
+        assert_eq!(paging("page=2&limit=500"), (100, 100));
+        assert!(HistoryQuery::parse("limit=-1").is_err());
+        assert!(HistoryQuery::parse("page=first").is_err());
+    }
+
+    #[test]
+    fn pools_and_cursors_are_checked() {
+        let query = HistoryQuery::parse("pool=BTC.BTC").unwrap();
+        assert_eq!((query.single_pool().as_deref(), query.grouped_pools().unwrap()), (Some("BTC.BTC"), None));
+
+        let query = HistoryQuery::parse("pool=ETH.*,BTC.BTC").unwrap();
+        assert_eq!(query.single_pool(), None);
+        assert!(query.grouped_pools().unwrap().is_some());
+
+        assert!(_error("pool=BTC.BTC,").starts_with("pool must not"));
+        assert_eq!(_error("cursor=zz"), "cursor is not a token returned by this API");
+    }
+
+    #[test]
+    fn projections_keep_or_drop_known_fields() {
+        let available = ["_id", "pool", "startTime", "endTime", "assetDepth"];
+        let project = |query_string: &str| HistoryQuery::parse(query_string).unwrap().project(&available);
+
+        assert_eq!(project("").unwrap(), None);
+        assert_eq!(project("fields=assetDepth,startTime").unwrap(), Some(vec!["assetDepth".to_string(), "startTime".to_string()]));
+        assert_eq!(project("exclude=pool,endTime").unwrap(), Some(vec!["startTime".to_string(), "assetDepth".to_string()]));
+        assert_eq!(project("fields=pool&exclude=endTime").unwrap_err().to_string(), "use either fields or exclude, not both");
+        assert_eq!(project("fields=_id").unwrap_err().to_string(), "fields names unknown field \"_id\"");
+        assert_eq!(project("exclude=pool,pool").unwrap_err().to_string(), "exclude names \"pool\" more than once");
+        assert!(project("fields=pool,,startTime").is_err());
+    }
+}
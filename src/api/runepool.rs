@@ -1,90 +1,115 @@
-use crate::models::runepool_history::RunePoolHistory;
-use bson::{doc, to_document};
-use futures::stream::StreamExt;
-use mongodb::Collection;
-use serde::{Deserialize, Serialize};
-use actix_web::{web, HttpResponse, Responder};
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct RunePoolQueryParams {
-    from: Option<i64>,
-    to: Option<i64>,
-    page: Option<u32>,
-    limit: Option<u32>,
-    sort_by: Option<String>,
-    order: Option<String>,
-}
+use crate::models::runepool_history::{Metadata, RunePoolHistory};
+use crate::api::error::ApiError;
+use crate::api::history;
+use crate::api::query;
+use crate::models::fields::serialized_fields;
+use crate::services::sorting;
+use crate::repository::{aggregate_string, Accumulator, HistoryFilter, HistoryRepository, RepoResult};
+use bson::Bson;
+use actix_web::{web, HttpResponse};
 
+/// Hourly RUNEPool members and units, or `interval` buckets of them, with metadata over
+/// the whole range.
+#[utoipa::path(
+    get,
+    path = "/runepool-history",
+    tag = "history",
+    params(query::HistoryParams),
+    responses(
+        (status = 200, description = "A page of RUNEPool rows", body = RunePoolHistoryPage),
+        (status = 400, description = "Invalid parameters", body = ErrorBody),
+        (status = 500, description = "Storage failed", body = ErrorBody),
+    )
+)]
 pub async fn runepool_history_route(
-    query: web::Query<RunePoolQueryParams>,
-    collection: web::Data<Collection<RunePoolHistory>>,
-) -> impl Responder {
-    let params = query.into_inner();
-    let mut filter_conditions = Vec::new();
+    params: query::HistoryQuery,
+    repository: web::Data<dyn HistoryRepository<RunePoolHistory>>,
+) -> Result<HttpResponse, ApiError> {
+    params.reject_pools()?;
+    let query = params.storage_query(&sorting::sortable::<RunePoolHistory>(), &["startTime"])?;
+    let fields = params.project(serialized_fields::<RunePoolHistory>())?;
+    let meta = Some(_range_metadata(&**repository, &query.filter));
+    let response = history::page(&**repository, &query, &params, params.cursor.as_ref(), fields.as_deref(), meta, |_| {}).await?;
 
-    if let (Some(start), Some(end)) = (params.from, params.to) {
-        if start >= end {
-            return HttpResponse::BadRequest().json("start_time must be less than end_time");
-        }
-        filter_conditions.push(doc! { "startTime": { "$gte": start } });
-        filter_conditions.push(doc! { "endTime": { "$lte": end } });
-    } else if let Some(start) = params.from {
-        filter_conditions.push(doc! { "startTime": { "$gte": start } });
-    } else if let Some(end) = params.to {
-        filter_conditions.push(doc! { "endTime": { "$lte": end } });
-    }
+    Ok(HttpResponse::Ok().json(response))
+}
 
-    let filter = if !filter_conditions.is_empty() {
-        doc! { "$and": filter_conditions }
-    } else {
-        doc! {}
+/// Metadata over every row in `filter` rather than the returned page.
+async fn _range_metadata(repository: &dyn HistoryRepository<RunePoolHistory>, filter: &HistoryFilter) -> RepoResult<Metadata> {
+    let accumulators = [
+        Accumulator::Count,
+        Accumulator::Min("startTime"),
+        Accumulator::Max("endTime"),
+        Accumulator::First("count"),
+        Accumulator::Last("count"),
+        Accumulator::Sum("count"),
+        Accumulator::First("units"),
+        Accumulator::Last("units"),
+        Accumulator::Sum("units"),
+    ];
+    let values = repository.aggregate(filter, &accumulators).await?;
+
+    let rows: i128 = aggregate_string(&values[0]).parse().unwrap_or_default();
+    let average = |total: &Bson| {
+        let total: i128 = aggregate_string(total).parse().unwrap_or_default();
+        if rows > 0 { total / rows } else { 0 }.to_string()
     };
 
-    let limit = params.limit.unwrap_or(10).clamp(1, 100) as i64;
-    let skip = ((params.page.unwrap_or(1).max(1) - 1) * params.limit.unwrap_or(10)) as u64;
+    Ok(Metadata {
+        start_time: aggregate_string(&values[1]),
+        end_time: aggregate_string(&values[2]),
+        start_count: aggregate_string(&values[3]),
+        end_count: aggregate_string(&values[4]),
+        avg_count: average(&values[5]),
+        start_units: aggregate_string(&values[6]),
+        end_units: aggregate_string(&values[7]),
+        avg_units: average(&values[8]),
+    })
+}
 
-    let sort_doc = if let Some(sort_by) = params.sort_by {
-        let sort_order = match params.order.as_deref() {
-            Some("asc") => 1,
-            Some("desc") | _ => -1,
-        };
-        doc! { &sort_by: sort_order }
-    } else {
-        doc! { "startTime": -1 }
-    };
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use serde_json::{json, Value};
+    use crate::api::testing::{get, runepool_repos};
 
-    let mut cursor = match collection
-        .find(filter)
-        .sort(sort_doc)
-        .skip(skip)
-        .limit(limit)
-        .await
-    {
-        Ok(cursor) => cursor,
-        Err(e) => {
-            eprintln!("Error fetching data: {:?}", e);
-            return HttpResponse::InternalServerError().json("Error fetching data");
-        }
-    };
+    fn _counts(page: &Value) -> Vec<i64> {
+        page["data"].as_array().unwrap().iter().map(|row| row["count"].as_i64().unwrap()).collect()
+    }
 
-    let mut histories = Vec::new();
-    while let Some(result) = cursor.next().await {
-        match result {
-            Ok(raw_history) => {
-                let mut response = to_document(&raw_history).unwrap();
-                response.remove("_id");
-                histories.push(response);
-            }
-            Err(e) => {
-                eprintln!("Error processing data: {:?}", e);
-                return HttpResponse::InternalServerError().json("Error processing data");
-            }
-        }
+    #[actix_web::test]
+    async fn pages_with_metadata_over_the_range() {
+        let repos = runepool_repos().await;
+        let (status, body) = get(&repos, "/runepool-history?order=asc&limit=2&page=2&include_total=true").await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!((_counts(&body), &body["total"], &body["next_cursor"]), (vec![3, 4], &json!(4), &Value::Null));
+        assert!(body["prev_cursor"].is_string());
+
+        let (_, back) = get(&repos, &format!("/runepool-history?order=asc&limit=2&cursor={}", body["prev_cursor"].as_str().unwrap())).await;
+        assert_eq!(_counts(&back), [1, 2]);
+
+        let meta = &body["meta"];
+        assert_eq!((&meta["startCount"], &meta["endCount"], &meta["avgCount"]), (&json!("1"), &json!("4"), &json!("2")));
+        assert_eq!((&meta["startUnits"], &meta["endUnits"], &meta["avgUnits"]), (&json!("10"), &json!("40"), &json!("25")));
+        assert_eq!(back["meta"], body["meta"]);
+    }
+
+    #[actix_web::test]
+    async fn filters_rows_and_metadata_to_the_range() {
+        let repos = runepool_repos().await;
+        let (_, body) = get(&repos, "/runepool-history?from=3600&to=10800").await;
+        assert_eq!(_counts(&body), [3, 2]);
+        let meta = &body["meta"];
+        assert_eq!((&meta["startTime"], &meta["endTime"]), (&json!("3600"), &json!("10800")));
+        assert_eq!((&meta["startCount"], &meta["endCount"], &meta["avgUnits"]), (&json!("2"), &json!("3"), &json!("25")));
     }
 
-    if histories.is_empty() {
-        HttpResponse::NotFound().json("No data found")
-    } else {
-        HttpResponse::Ok().json(histories)
+    #[actix_web::test]
+    async fn pools_are_rejected() {
+        let repos = runepool_repos().await;
+        for uri in ["/runepool-history?pool=BTC.BTC", "/runepool-history?pool=BTC.*", "/v2/history/runepool?pool=BTC.BTC"] {
+            let (status, body) = get(&repos, uri).await;
+            assert_eq!((status, &body["message"]), (StatusCode::BAD_REQUEST, &json!("this history is not kept per pool; drop pool")), "{}", uri);
+        }
     }
 }
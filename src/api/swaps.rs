@@ -1,90 +1,157 @@
-use crate::models::swaps_history::SwapHistory;
-use bson::{doc, to_document};
-use futures::stream::StreamExt;
-use mongodb::Collection;
-use serde::{Deserialize, Serialize};
-use actix_web::{web, HttpResponse, Responder};
+use crate::models::amount::Decimal;
+use crate::models::swaps_history::{Metadata, SwapHistory};
+use crate::api::error::ApiError;
+use crate::api::history;
+use crate::api::query;
+use crate::models::fields::serialized_fields;
+use crate::services::sorting;
+use crate::repository::{aggregate_string, Accumulator, HistoryFilter, HistoryRepository, RepoResult};
+use actix_web::{web, HttpResponse};
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SwapQueryParams {
-    from: Option<i64>,
-    to: Option<i64>,
-    page: Option<u32>,
-    limit: Option<u32>,
-    sort_by: Option<String>,
-    order: Option<String>,
+/// Hourly swap counts, fees and volumes per pool, or `interval` buckets of them, with
+/// metadata over the whole range.
+#[utoipa::path(
+    get,
+    path = "/swaps-history",
+    tag = "history",
+    params(query::HistoryParams),
+    responses(
+        (status = 200, description = "A page of swap rows, or an object of pages keyed by pool for several pools or a wildcard", body = SwapHistoryPage),
+        (status = 400, description = "Invalid parameters", body = ErrorBody),
+        (status = 500, description = "Storage failed", body = ErrorBody),
+    )
+)]
+pub async fn swaps_history_route(
+    params: query::HistoryQuery,
+    repository: web::Data<dyn HistoryRepository<SwapHistory>>,
+) -> Result<HttpResponse, ApiError> {
+    let query = params.storage_query(&sorting::sortable::<SwapHistory>(), &["startTime", "pool"])?;
+    let fields = params.project(serialized_fields::<SwapHistory>())?;
+    let (repository, fields, params) = (&**repository, fields.as_deref(), &params);
+
+    history::respond(params, repository, query, |query, cursor| async move {
+        let meta = Some(_range_metadata(repository, &query.filter));
+        history::page(repository, &query, params, cursor.as_ref(), fields, meta, |_| {}).await
+    })
+    .await
 }
 
-pub async fn swaps_history_route(
-    query: web::Query<SwapQueryParams>,
-    collection: web::Data<Collection<SwapHistory>>,
-) -> impl Responder {
-    let params = query.into_inner();
-    let mut filter_conditions = Vec::new();
+/// Swap directions, as the field prefix each one's slip, count, fees and volumes share.
+const DIRECTIONS: [(&str, &str, &str, &str, &str); 5] = [
+    ("synthMintAverageSlip", "synthMintCount", "synthMintFees", "synthMintVolume", "synthMintVolumeUSD"),
+    ("synthRedeemAverageSlip", "synthRedeemCount", "synthRedeemFees", "synthRedeemVolume", "synthRedeemVolumeUSD"),
+    ("toAssetAverageSlip", "toAssetCount", "toAssetFees", "toAssetVolume", "toAssetVolumeUSD"),
+    ("toRuneAverageSlip", "toRuneCount", "toRuneFees", "toRuneVolume", "toRuneVolumeUSD"),
+    ("averageSlip", "totalCount", "totalFees", "totalVolume", "totalVolumeUSD"),
+];
 
-    if let (Some(start), Some(end)) = (params.from, params.to) {
-        if start >= end {
-            return HttpResponse::BadRequest().json("start_time must be less than end_time");
-        }
-        filter_conditions.push(doc! { "startTime": { "$gte": start } });
-        filter_conditions.push(doc! { "endTime": { "$lte": end } });
-    } else if let Some(start) = params.from {
-        filter_conditions.push(doc! { "startTime": { "$gte": start } });
-    } else if let Some(end) = params.to {
-        filter_conditions.push(doc! { "endTime": { "$lte": end } });
+/// Metadata over every swap row in `filter`, not just the returned page.
+async fn _range_metadata(repository: &dyn HistoryRepository<SwapHistory>, filter: &HistoryFilter) -> RepoResult<Metadata> {
+    let mut accumulators = vec![Accumulator::Min("startTime"), Accumulator::Max("endTime"), Accumulator::Last("runePriceUSD")];
+    for (slip, count, fees, volume, volume_usd) in DIRECTIONS {
+        accumulators.extend([
+            Accumulator::SumProduct(slip, volume),
+            Accumulator::Sum(count),
+            Accumulator::Sum(fees),
+            Accumulator::Sum(volume),
+            Accumulator::Sum(volume_usd),
+        ]);
     }
+    let values = repository.aggregate(filter, &accumulators).await?;
+
+    // Each direction's [average slip, count, fees, volume, volume USD].
+    let mut directions = values[3..]
+        .chunks(5)
+        .map(|direction| {
+            let volume: f64 = aggregate_string(&direction[3]).parse().unwrap_or_default();
+            let slip_volume: f64 = aggregate_string(&direction[0]).parse().unwrap_or_default();
+            let average_slip = if volume > 0.0 { slip_volume / volume } else { 0.0 };
+            [
+                Decimal::from_f64(average_slip).to_string(),
+                aggregate_string(&direction[1]),
+                aggregate_string(&direction[2]),
+                aggregate_string(&direction[3]),
+                aggregate_string(&direction[4]),
+            ]
+        });
+    // In the order of `DIRECTIONS`.
+    let [synth_mint_average_slip, synth_mint_count, synth_mint_fees, synth_mint_volume, synth_mint_volume_usd] = directions.next().unwrap_or_default();
+    let [synth_redeem_average_slip, synth_redeem_count, synth_redeem_fees, synth_redeem_volume, synth_redeem_volume_usd] = directions.next().unwrap_or_default();
+    let [to_asset_average_slip, to_asset_count, to_asset_fees, to_asset_volume, to_asset_volume_usd] = directions.next().unwrap_or_default();
+    let [to_rune_average_slip, to_rune_count, to_rune_fees, to_rune_volume, to_rune_volume_usd] = directions.next().unwrap_or_default();
+    let [average_slip, total_count, total_fees, total_volume, total_volume_usd] = directions.next().unwrap_or_default();
 
-    let filter = if !filter_conditions.is_empty() {
-        doc! { "$and": filter_conditions }
-    } else {
-        doc! {}
-    };
+    Ok(Metadata {
+        average_slip,
+        end_time: aggregate_string(&values[1]),
+        rune_price_usd: aggregate_string(&values[2]),
+        start_time: aggregate_string(&values[0]),
+        synth_mint_average_slip,
+        synth_mint_count,
+        synth_mint_fees,
+        synth_mint_volume,
+        synth_mint_volume_usd,
+        synth_redeem_average_slip,
+        synth_redeem_count,
+        synth_redeem_fees,
+        synth_redeem_volume,
+        synth_redeem_volume_usd,
+        to_asset_average_slip,
+        to_asset_count,
+        to_asset_fees,
+        to_asset_volume,
+        to_asset_volume_usd,
+        to_rune_average_slip,
+        to_rune_count,
+        to_rune_fees,
+        to_rune_volume,
+        to_rune_volume_usd,
+        total_count,
+        total_fees,
+        total_volume,
+        total_volume_usd,
+    })
+}
 
-    let limit = params.limit.unwrap_or(10).clamp(1, 100) as i64;
-    let skip = ((params.page.unwrap_or(1).max(1) - 1) * params.limit.unwrap_or(10)) as u64;
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use serde_json::{json, Value};
+    use crate::api::testing::{get, swaps_repos};
 
-    let sort_doc = if let Some(sort_by) = params.sort_by {
-        let sort_order = match params.order.as_deref() {
-            Some("asc") => 1,
-            Some("desc") | _ => -1,
-        };
-        doc! { &sort_by: sort_order }
-    } else {
-        doc! { "startTime": -1 }
-    };
+    fn _volumes(page: &Value) -> Vec<&str> {
+        page["data"].as_array().unwrap().iter().map(|row| row["totalVolume"].as_str().unwrap()).collect()
+    }
 
-    let mut cursor = match collection
-        .find(filter)
-        .sort(sort_doc)
-        .skip(skip)
-        .limit(limit)
-        .await
-    {
-        Ok(cursor) => cursor,
-        Err(e) => {
-            eprintln!("Error fetching data: {:?}", e);
-            return HttpResponse::InternalServerError().json("Error fetching data");
-        }
-    };
+    #[actix_web::test]
+    async fn pages_by_volume_with_metadata_over_the_range() {
+        let repos = swaps_repos().await;
+        let (status, first) = get(&repos, "/swaps-history?pool=BTC.BTC&sort=-totalVolume&limit=3").await;
+        assert_eq!(status, StatusCode::OK, "{}", first);
+        assert_eq!(_volumes(&first), ["400", "300", "200"]);
 
-    let mut histories = Vec::new();
-    while let Some(result) = cursor.next().await {
-        match result {
-            Ok(raw_history) => {
-                let mut response = to_document(&raw_history).unwrap();
-                response.remove("_id");
-                histories.push(response);
-            }
-            Err(e) => {
-                eprintln!("Error processing data: {:?}", e);
-                return HttpResponse::InternalServerError().json("Error processing data");
-            }
+        let cursor = first["next_cursor"].as_str().unwrap();
+        let (_, second) = get(&repos, &format!("/swaps-history?pool=BTC.BTC&sort=-totalVolume&limit=3&cursor={}", cursor)).await;
+        assert_eq!((_volumes(&second), &second["next_cursor"]), (vec!["100"], &Value::Null));
+
+        for page in [&first, &second] {
+            let meta = &page["meta"];
+            assert_eq!((&meta["totalCount"], &meta["totalVolume"]), (&json!("8"), &json!("1000")));
+            // Slips weigh by volume: (4 * 100 + 300 + 200 + 400) / 1000.
+            assert_eq!(meta["averageSlip"], "1.3");
+            assert_eq!((&meta["startTime"], &meta["endTime"]), (&json!("0"), &json!("14400")));
         }
     }
 
-    if histories.is_empty() {
-        HttpResponse::NotFound().json("No data found")
-    } else {
-        HttpResponse::Ok().json(histories)
+    #[actix_web::test]
+    async fn filters_rows_and_metadata_to_the_range() {
+        let repos = swaps_repos().await;
+        let (_, body) = get(&repos, "/swaps-history?pool=BTC.BTC&from=7200&include_total=true").await;
+        assert_eq!((_volumes(&body), &body["total"]), (vec!["400", "300"], &json!(2)));
+        let meta = &body["meta"];
+        assert_eq!((&meta["totalVolume"], &meta["averageSlip"], &meta["startTime"]), (&json!("700"), &json!("1"), &json!("7200")));
+
+        let (_, empty) = get(&repos, "/swaps-history?pool=BTC.BTC&to=3599").await;
+        assert_eq!((_volumes(&empty), &empty["meta"]), (vec![], &Value::Null));
     }
 }
@@ -0,0 +1,283 @@
+use actix_web::{web, HttpResponse};
+use bson::Bson;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use crate::models::depth_history::DepthHistory;
+use crate::models::earnings_history::EarningsHistory;
+use crate::models::runepool_history::RunePoolHistory;
+use crate::models::swaps_history::SwapHistory;
+use crate::api::error::ApiError;
+use crate::api::query;
+use crate::repository::{stored_document, Buckets, EarningsRepository, HistoryFilter, HistoryQuery, HistoryRecord, HistoryRepository, RepoResult, SortKey, SortOrder};
+use crate::services::intervals::Interval;
+
+/// The range's `interval` buckets in time order, or one bucket for the whole range, with
+/// its `[startTime, endTime)`.
+async fn _intervals<T: HistoryRecord + Default, R: HistoryRepository<T> + ?Sized>(
+    repository: &R,
+    params: &query::HistoryQuery,
+    pool: Option<String>,
+    interval: Option<Interval>,
+) -> Result<(Vec<T>, i64, i64), ApiError> {
+    let (from, to) = (params.filter.from, params.filter.to);
+    let query = HistoryQuery {
+        filter: HistoryFilter { pool, ..params.filter.clone() },
+        sort: vec![SortKey { field: "startTime".to_string(), order: SortOrder::Asc }],
+        skip: 0,
+        limit: None,
+        after: None,
+        fields: None,
+        buckets: Some(Buckets { interval, per_pool: false }),
+    };
+    let buckets = repository.find(&query).await?;
+
+    let (Some(first), Some(last)) = (buckets.first(), buckets.last()) else {
+        return Ok((Vec::new(), from.unwrap_or_default(), to.unwrap_or_default()));
+    };
+    let start_time = from.unwrap_or(first.start_time());
+    let end_time = to.unwrap_or(last.end_time());
+    let buckets = match interval {
+        Some(interval) => _every_bucket(buckets, interval, start_time, end_time)?,
+        None => buckets,
+    };
+    Ok((buckets, start_time, end_time))
+}
+
+/// Every `interval` bucket of the range, as Midgard lists them. Empty ones are zero but
+/// keep the levels, such as depths and units, of the bucket before.
+fn _every_bucket<T: HistoryRecord + Default>(stored: Vec<T>, interval: Interval, start_time: i64, end_time: i64) -> RepoResult<Vec<T>> {
+    let zero = stored_document(&T::default())?;
+    let mut stored = stored.into_iter().peekable();
+    let mut previous = T::default();
+    let mut buckets = Vec::new();
+    let mut start = start_time;
+    while start < end_time {
+        let end = interval.step(start, 1);
+        let bucket = match stored.next_if(|bucket| bucket.start_time() == start) {
+            Some(bucket) => bucket,
+            None => {
+                let mut empty = stored_document(&previous)?;
+                for (field, _) in T::MERGES {
+                    empty.insert(*field, zero.get(*field).cloned().unwrap_or(Bson::Null));
+                }
+                empty.insert("startTime", start);
+                empty.insert("endTime", end);
+                bson::from_document(empty)?
+            }
+        };
+        previous = bucket.clone();
+        buckets.push(bucket);
+        start = end;
+    }
+    Ok(buckets)
+}
+
+/// Midgard encodes every number as a string and leaves out our storage fields.
+fn _midgard_value(value: Value) -> Value {
+    match value {
+        Value::Number(number) => Value::String(number.to_string()),
+        Value::Array(values) => Value::Array(values.into_iter().map(_midgard_value).collect()),
+        Value::Object(fields) => Value::Object(
+            fields
+                .into_iter()
+                .filter(|(field, _)| field != "_id")
+                .map(|(field, value)| (field, _midgard_value(value)))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+/// A record as a Midgard interval, dropping the top-level `pool` the request already names.
+fn _midgard_interval<T: Serialize>(record: &T) -> Value {
+    let mut value = _midgard_value(serde_json::to_value(record).unwrap_or_default());
+    if let Value::Object(fields) = &mut value {
+        fields.remove("pool");
+    }
+    value
+}
+
+/// The whole range's bucket as Midgard's `meta`, spanning `[start_time, end_time)`.
+fn _range_meta<T: Serialize>(range: &T, start_time: i64, end_time: i64) -> Value {
+    let mut meta = _midgard_interval(range);
+    if let Value::Object(fields) = &mut meta {
+        fields.insert("startTime".to_string(), Value::String(start_time.to_string()));
+        fields.insert("endTime".to_string(), Value::String(end_time.to_string()));
+    }
+    meta
+}
+
+/// Buckets as Midgard intervals; without an `interval` the one bucket spans the range.
+fn _midgard_intervals<T: Serialize>(buckets: &[T], interval: Option<Interval>, start_time: i64, end_time: i64) -> Vec<Value> {
+    match interval {
+        Some(_) => buckets.iter().map(_midgard_interval).collect(),
+        None => buckets.iter().map(|bucket| _range_meta(bucket, start_time, end_time)).collect(),
+    }
+}
+
+/// An empty range is an empty list of intervals with null metadata.
+fn _response(meta: Value, intervals: Vec<Value>) -> Result<HttpResponse, ApiError> {
+    let mut body = Map::new();
+    body.insert("meta".to_string(), meta);
+    body.insert("intervals".to_string(), Value::Array(intervals));
+    Ok(HttpResponse::Ok().json(body))
+}
+
+/// `GET /v2/history/depths/{pool}`
+#[utoipa::path(
+    get,
+    path = "/v2/history/depths/{pool}",
+    tag = "midgard",
+    params(("pool" = String, Path, description = "Pool, as `CHAIN.SYMBOL`"), query::HistoryParams),
+    responses(
+        (status = 200, description = "Midgard-shaped `meta` and `intervals`, every number as a string; paging, sorting and field parameters are ignored", body = Object),
+        (status = 400, description = "Invalid parameters", body = ErrorBody),
+        (status = 500, description = "Storage failed", body = ErrorBody),
+    )
+)]
+pub async fn depths_route(
+    pool: web::Path<String>,
+    params: query::HistoryQuery,
+    repository: web::Data<dyn HistoryRepository<DepthHistory>>,
+) -> Result<HttpResponse, ApiError> {
+    let (buckets, start_time, end_time) = _intervals(&**repository, &params, Some(pool.into_inner()), params.interval).await?;
+    let (Some(first), Some(last)) = (buckets.first(), buckets.last()) else {
+        return _response(Value::Null, Vec::new());
+    };
+
+    // Impermanent loss from the price moving by `ratio`, and the change in liquidity unit value.
+    let ratio = last.asset_price.to_f64() / first.asset_price.to_f64();
+    let price_shift_loss = 2.0 * ratio.sqrt() / (1.0 + ratio);
+    let luvi_increase = last.luvi.to_f64() / first.luvi.to_f64();
+    let finite = |value: f64| if value.is_finite() { value } else { 0.0 };
+
+    let meta = json!({
+        "startTime": start_time.to_string(),
+        "endTime": end_time.to_string(),
+        "priceShiftLoss": finite(price_shift_loss).to_string(),
+        "luviIncrease": finite(luvi_increase).to_string(),
+        "startAssetDepth": first.asset_depth.to_string(),
+        "startRuneDepth": first.rune_depth.to_string(),
+        "startLPUnits": first.liquidity_units.to_string(),
+        "startMemberCount": first.members_count.to_string(),
+        "startSynthUnits": first.synth_units.to_string(),
+        "endAssetDepth": last.asset_depth.to_string(),
+        "endRuneDepth": last.rune_depth.to_string(),
+        "endLPUnits": last.liquidity_units.to_string(),
+        "endMemberCount": last.members_count.to_string(),
+        "endSynthUnits": last.synth_units.to_string(),
+    });
+    _response(meta, _midgard_intervals(&buckets, params.interval, start_time, end_time))
+}
+
+/// `GET /v2/history/swaps`, for one pool or summed across all of them.
+#[utoipa::path(
+    get,
+    path = "/v2/history/swaps",
+    tag = "midgard",
+    params(query::HistoryParams),
+    responses(
+        (status = 200, description = "Midgard-shaped `meta` and `intervals`, every number as a string; paging, sorting and field parameters are ignored", body = Object),
+        (status = 400, description = "Invalid parameters", body = ErrorBody),
+        (status = 500, description = "Storage failed", body = ErrorBody),
+    )
+)]
+pub async fn swaps_route(
+    params: query::HistoryQuery,
+    repository: web::Data<dyn HistoryRepository<SwapHistory>>,
+) -> Result<HttpResponse, ApiError> {
+    if params.pools.is_some() && params.single_pool().is_none() {
+        return Err(ApiError::bad_request("pool names a single pool on this route"));
+    }
+    let (buckets, start_time, end_time) = _intervals(&**repository, &params, params.single_pool(), params.interval).await?;
+    let (range, _, _) = _intervals::<SwapHistory, _>(&**repository, &params, params.single_pool(), None).await?;
+    let Some(range) = range.first() else {
+        return _response(Value::Null, Vec::new());
+    };
+
+    let meta = _range_meta(range, start_time, end_time);
+    _response(meta, _midgard_intervals(&buckets, params.interval, start_time, end_time))
+}
+
+/// `GET /v2/history/runepool`
+#[utoipa::path(
+    get,
+    path = "/v2/history/runepool",
+    tag = "midgard",
+    params(query::HistoryParams),
+    responses(
+        (status = 200, description = "Midgard-shaped `meta` and `intervals`, every number as a string; paging, sorting and field parameters are ignored", body = Object),
+        (status = 400, description = "Invalid parameters", body = ErrorBody),
+        (status = 500, description = "Storage failed", body = ErrorBody),
+    )
+)]
+pub async fn runepool_route(
+    params: query::HistoryQuery,
+    repository: web::Data<dyn HistoryRepository<RunePoolHistory>>,
+) -> Result<HttpResponse, ApiError> {
+    params.reject_pools()?;
+    let (buckets, start_time, end_time) = _intervals(&**repository, &params, None, params.interval).await?;
+    let (Some(first), Some(last)) = (buckets.first(), buckets.last()) else {
+        return _response(Value::Null, Vec::new());
+    };
+
+    let meta = json!({
+        "startTime": start_time.to_string(),
+        "endTime": end_time.to_string(),
+        "startCount": first.count.to_string(),
+        "endCount": last.count.to_string(),
+        "startUnits": first.units.to_string(),
+        "endUnits": last.units.to_string(),
+    });
+    _response(meta, _midgard_intervals(&buckets, params.interval, start_time, end_time))
+}
+
+/// `GET /v2/history/earnings`, with each interval's per-pool breakdown.
+#[utoipa::path(
+    get,
+    path = "/v2/history/earnings",
+    tag = "midgard",
+    params(query::HistoryParams),
+    responses(
+        (status = 200, description = "Midgard-shaped `meta` and `intervals`, every number as a string; paging, sorting and field parameters are ignored", body = Object),
+        (status = 400, description = "Invalid parameters", body = ErrorBody),
+        (status = 500, description = "Storage failed", body = ErrorBody),
+    )
+)]
+pub async fn earnings_route(
+    params: query::HistoryQuery,
+    repository: web::Data<dyn EarningsRepository>,
+) -> Result<HttpResponse, ApiError> {
+    let (buckets, start_time, end_time) = _intervals(&**repository, &params, None, params.interval).await?;
+    let (range, _, _) = _intervals::<EarningsHistory, _>(&**repository, &params, None, None).await?;
+    let Some(range) = range.first() else {
+        return _response(Value::Null, Vec::new());
+    };
+
+    let meta = _range_meta(range, start_time, end_time);
+    _response(meta, _midgard_intervals(&buckets, params.interval, start_time, end_time))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use crate::api::testing::{gapped_repos, get};
+
+    fn _field(body: &Value, field: &str) -> Vec<Value> {
+        body["intervals"].as_array().unwrap().iter().map(|interval| interval[field].clone()).collect()
+    }
+
+    #[actix_web::test]
+    async fn lists_every_interval_of_a_range_with_a_hole() {
+        let repos = gapped_repos().await;
+        let (_, depths) = get(&repos, "/v2/history/depths/BTC.BTC?interval=day&count=4&from=0").await;
+        assert_eq!(_field(&depths, "startTime"), [json!("0"), json!("86400"), json!("172800"), json!("259200")]);
+        // The empty days keep the depth of the day before.
+        assert_eq!(_field(&depths, "assetDepth"), [json!("100"), json!("100"), json!("200"), json!("200")]);
+
+        let (_, swaps) = get(&repos, "/v2/history/swaps?pool=BTC.BTC&interval=day&count=4&from=0").await;
+        assert_eq!(_field(&swaps, "endTime"), [json!("86400"), json!("172800"), json!("259200"), json!("345600")]);
+        assert_eq!(_field(&swaps, "totalCount"), [json!("3"), json!("0"), json!("3"), json!("0")]);
+        assert_eq!((&swaps["meta"]["totalCount"], &swaps["meta"]["endTime"]), (&json!("6"), &json!("345600")));
+    }
+}
@@ -0,0 +1,245 @@
+use std::error::Error;
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand};
+use crate::data_fetcher::{_fetch_and_store_all_data, _resolve_pools, fetch_and_store_dataset, fetch_and_store_range};
+use crate::models::ingestion_state::Dataset;
+use crate::repository::{Accumulator, HistoryFilter, Repositories};
+use crate::services::gaps::{backfill_gaps, find_gaps};
+use crate::services::midgard_client::MidgardClient;
+
+/// Midgard history ingester and API server.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Serve the HTTP API, with the ingestion scheduler in the background (the default).
+    Serve {
+        /// Address to listen on.
+        #[arg(long, default_value = "0.0.0.0:3030")]
+        bind: String,
+        /// Serve the API only, without scheduled ingestion.
+        #[arg(long)]
+        no_ingest: bool,
+    },
+    /// Ingest one dataset, from its checkpoint or over an explicit range.
+    Ingest {
+        /// depth, runepool, swaps or earnings.
+        #[arg(long)]
+        dataset: Dataset,
+        /// Only ingest this pool (depth and swaps); defaults to every resolved pool.
+        #[arg(long)]
+        pool: Option<String>,
+        /// Unix timestamp to fetch from instead of resuming from the checkpoint, which an
+        /// explicit range leaves unchanged.
+        #[arg(long)]
+        from: Option<i64>,
+        /// Unix timestamp to fetch up to (exclusive); defaults to now. Requires --from.
+        #[arg(long, requires = "from")]
+        to: Option<i64>,
+    },
+    /// Bring every dataset up to date from its checkpoint.
+    BackfillAll,
+    /// Report missing intervals in the stored series.
+    VerifyGaps {
+        /// Only scan this dataset.
+        #[arg(long)]
+        dataset: Option<Dataset>,
+        /// Only scan this pool (depth and swaps).
+        #[arg(long)]
+        pool: Option<String>,
+        /// Also report a missing head of the series from this unix timestamp.
+        #[arg(long)]
+        from: Option<i64>,
+        /// Also report a missing tail of the series up to this unix timestamp.
+        #[arg(long)]
+        to: Option<i64>,
+        /// Re-fetch every gap found.
+        #[arg(long)]
+        backfill: bool,
+    },
+    /// Create missing indexes and report index drift.
+    Reindex {
+        /// Delete all but the newest copy of rows sharing a natural key, so their unique
+        /// index can be created. Every deleted copy is logged.
+        #[arg(long)]
+        remove_duplicates: bool,
+    },
+    /// Embed legacy `pools_history` rows into their earnings intervals.
+    MigrateEarnings {
+        /// Drop `pools_history` once every interval has been migrated.
+        #[arg(long)]
+        drop_legacy: bool,
+    },
+    /// Print row counts, covered ranges and ingestion checkpoints.
+    Stats,
+}
+
+pub async fn ingest(
+    repos: &Repositories,
+    midgard: &MidgardClient,
+    dataset: Dataset,
+    pool: Option<&str>,
+    from: Option<i64>,
+    to: Option<i64>,
+) -> Result<(), Box<dyn Error>> {
+    let Some(from) = from else {
+        println!("Ingesting {} history from its checkpoint", dataset);
+        return fetch_and_store_dataset(repos, midgard, dataset, pool).await;
+    };
+    let to = to.unwrap_or_else(|| Utc::now().timestamp());
+
+    let pools = match (dataset.per_pool(), pool) {
+        (false, _) => vec![None],
+        (true, Some(pool)) => vec![Some(pool.to_string())],
+        (true, None) => _resolve_pools(midgard).await?.into_iter().map(Some).collect(),
+    };
+
+    let mut failed = Vec::new();
+    for pool in pools {
+        let label = _label(dataset, pool.as_deref());
+        println!("Ingesting {} from {} to {}", label, _format_time(from), _format_time(to));
+        if let Err(e) = fetch_and_store_range(repos, midgard, dataset, pool.as_deref(), from, to).await {
+            eprintln!("Error ingesting {}: {}", label, e);
+            failed.push(label);
+        }
+    }
+    if !failed.is_empty() {
+        return Err(format!("Ingestion failed for: {}", failed.join(", ")).into());
+    }
+
+    Ok(())
+}
+
+pub async fn backfill_all(repos: &Repositories, midgard: &MidgardClient) -> Result<(), Box<dyn Error>> {
+    _fetch_and_store_all_data(repos, midgard).await
+}
+
+/// Reports every gap and, with `backfill`, re-fetches them. Fails if any gap is left
+/// unfilled.
+pub async fn verify_gaps(
+    repos: &Repositories,
+    midgard: &MidgardClient,
+    dataset: Option<Dataset>,
+    pool: Option<&str>,
+    from: Option<i64>,
+    to: Option<i64>,
+    backfill: bool,
+) -> Result<(), Box<dyn Error>> {
+    let datasets = match dataset {
+        Some(dataset) => vec![dataset],
+        None => Dataset::ALL.to_vec(),
+    };
+
+    let mut gaps = Vec::new();
+    for dataset in datasets {
+        gaps.extend(find_gaps(repos, dataset, pool, from, to).await?);
+    }
+    for gap in &gaps {
+        println!(
+            "Gap in {} history{}: {} - {} ({} hours)",
+            gap.dataset,
+            gap.pool.as_deref().map(|pool| format!(" for {}", pool)).unwrap_or_default(),
+            _format_time(gap.start_time),
+            _format_time(gap.end_time),
+            (gap.end_time - gap.start_time) / 3600
+        );
+    }
+    println!("Found {} gaps", gaps.len());
+
+    if gaps.is_empty() {
+        return Ok(());
+    }
+    if !backfill {
+        return Err(format!("{} gaps found, rerun with --backfill to fill them", gaps.len()).into());
+    }
+
+    let failed = backfill_gaps(repos, midgard, gaps)
+        .await
+        .into_iter()
+        .filter(|outcome| outcome.error.is_some())
+        .count();
+    if failed > 0 {
+        return Err(format!("{} gaps could not be backfilled", failed).into());
+    }
+
+    println!("All gaps backfilled");
+    Ok(())
+}
+
+/// Fails if an expected index could not be created because of a conflicting one or of
+/// duplicate rows.
+pub async fn reindex(db: &mongodb::Client, remove_duplicates: bool) -> Result<(), Box<dyn Error>> {
+    let report = crate::db::indexes::ensure_indexes(db, remove_duplicates).await?;
+    report.log();
+    println!(
+        "{} indexes created, {} conflicting, {} unexpected, {} blocked by duplicates",
+        report.created.len(),
+        report.conflicting.len(),
+        report.unexpected.len(),
+        report.duplicated.len()
+    );
+
+    if !report.conflicting.is_empty() {
+        return Err(format!("Conflicting indexes need to be dropped by hand: {}", report.conflicting.join(", ")).into());
+    }
+    if !report.duplicated.is_empty() {
+        return Err(format!("Duplicate rows block {}; rerun with --remove-duplicates", report.duplicated.join(", ")).into());
+    }
+    Ok(())
+}
+
+pub async fn migrate_earnings(db: &mongodb::Client, drop_legacy: bool) -> Result<(), Box<dyn Error>> {
+    let migrated = crate::db::migrate_earnings::_embed_pools_history(db, drop_legacy).await?;
+    println!("Embedded pools into {} earnings intervals", migrated);
+    if drop_legacy {
+        println!("Dropped pools_history");
+    }
+    Ok(())
+}
+
+pub async fn stats(repos: &Repositories) -> Result<(), Box<dyn Error>> {
+    for dataset in Dataset::ALL {
+        let values = repos
+            .aggregate(dataset, &HistoryFilter::default(), &[Accumulator::Count, Accumulator::Min("startTime"), Accumulator::Max("endTime")])
+            .await?;
+        let rows = values[0].as_i64().unwrap_or(0);
+        let range = match (values[1].as_i64(), values[2].as_i64()) {
+            (Some(first), Some(last)) => format!("{} - {}", _format_time(first), _format_time(last)),
+            _ => "empty".to_string(),
+        };
+        println!("{:<10} {:>10} rows  {}", dataset.name(), rows, range);
+    }
+
+    println!();
+    println!("Checkpoints:");
+    for state in repos.checkpoints.list().await? {
+        println!(
+            "  {:<40} {:<8} up to {}  {} rows{}",
+            format!("{}{}", state.dataset, state.pool.as_deref().map(|pool| format!(" {}", pool)).unwrap_or_default()),
+            state.status,
+            _format_time(state.last_end_time),
+            state.total_rows,
+            state.error.map(|e| format!("  error: {}", e)).unwrap_or_default()
+        );
+    }
+
+    Ok(())
+}
+
+fn _label(dataset: Dataset, pool: Option<&str>) -> String {
+    match pool {
+        Some(pool) => format!("{} history for {}", dataset, pool),
+        None => format!("{} history", dataset),
+    }
+}
+
+fn _format_time(timestamp: i64) -> String {
+    DateTime::<Utc>::from_timestamp(timestamp, 0)
+        .map(|time| time.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
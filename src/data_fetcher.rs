@@ -1,63 +1,181 @@
 use std::env;
-use futures::StreamExt;
-use mongodb::{Collection, bson::doc};
-use crate::models::{depth_history::DepthHistory, runepool_history::RunePoolHistory, swaps_history::SwapHistory, earnings_history::EarningsHistory, pools_history::PoolHistory};
+use crate::models::ingestion_state::{Dataset, STATUS_FAILED, STATUS_OK, STATUS_RUNNING};
+use crate::repository::{Accumulator, CheckpointRepository, HistoryFilter, HistoryRecord, HistoryRepository, Repositories};
 use crate::services::{fetch_depth::_fetch_and_store_data, fetch_runepool::_fetch_and_store_runepool_data, fetch_swaps::_fetch_and_store_swaps_data, fetch_earnings::_fetch_and_store_earnings_and_pools};
+use crate::services::midgard_client::MidgardClient;
 use chrono::Utc;
 
-pub async fn _fetch_and_store_all_data(db: &mongodb::Client) -> Result<(), Box<dyn std::error::Error>> {
-    let pool = env::var("POOL").expect("POOL must be set in .env");
-    let target_timestamp = Utc::now().timestamp();
-    
-    let depth_collection = db.database("historical_db").collection::<DepthHistory>("depth_history");
-    let mut from_timestamp = _get_last_end_time::<DepthHistory>(&depth_collection).await?;
-    _fetch_and_store_data(pool.clone(), &depth_collection, from_timestamp, target_timestamp).await?;
+/// Brings every dataset up to date, carrying on past a failed dataset.
+pub async fn _fetch_and_store_all_data(repos: &Repositories, midgard: &MidgardClient) -> Result<(), Box<dyn std::error::Error>> {
+    let mut failed = Vec::new();
+    for dataset in Dataset::ALL {
+        if let Err(e) = fetch_and_store_dataset(repos, midgard, dataset, None).await {
+            log::error!("Error ingesting {} data: {}", dataset, e);
+            failed.push(dataset.name());
+        }
+    }
+    if !failed.is_empty() {
+        return Err(format!("Ingestion failed for: {}", failed.join(", ")).into());
+    }
 
-    let rune_collection = db.database("historical_db").collection::<RunePoolHistory>("runepool_history");
-    from_timestamp = _get_last_end_time::<RunePoolHistory>(&rune_collection).await?;
-    _fetch_and_store_runepool_data(&rune_collection, from_timestamp, target_timestamp).await?;
+    Ok(())
+}
 
-    let swap_collection = db.database("historical_db").collection::<SwapHistory>("swaps_history");
-    from_timestamp = _get_last_end_time::<SwapHistory>(&swap_collection).await?;
-    _fetch_and_store_swaps_data(pool.clone(), &swap_collection, from_timestamp, target_timestamp).await?;
+/// Brings one dataset up to date from its checkpoint, per pool for depth and swaps, or for
+/// `pool` alone.
+pub async fn fetch_and_store_dataset(repos: &Repositories, midgard: &MidgardClient, dataset: Dataset, pool: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let target_timestamp = Utc::now().timestamp();
+    let checkpoints = repos.checkpoints.as_ref();
 
-    let earnings_collection = db.database("historical_db").collection::<EarningsHistory>("earnings_history");
-    let pools_collection = db.database("historical_db").collection::<PoolHistory>("pools_history");
-    from_timestamp = _get_last_end_time::<EarningsHistory>(&earnings_collection).await?;
-    _fetch_and_store_earnings_and_pools(&earnings_collection, &pools_collection, from_timestamp, target_timestamp).await?;
+    match dataset {
+        Dataset::Depth => {
+            let pools = _pools_for(midgard, pool).await?;
+            let mut failed_pools = Vec::new();
+            for pool in pools {
+                let from_timestamp = _resume_from(checkpoints, repos.depth.as_ref(), dataset, Some(&pool)).await?;
+                let result = _fetch_and_store_data(midgard, pool.clone(), repos.depth.as_ref(), true, from_timestamp, target_timestamp)
+                    .await
+                    .map_err(|e| e.to_string());
+                if !_record_outcome(checkpoints, dataset, Some(&pool), result).await? {
+                    failed_pools.push(pool);
+                }
+            }
+            if !failed_pools.is_empty() {
+                return Err(format!("Depth history failed for pools: {}", failed_pools.join(", ")).into());
+            }
+        }
+        Dataset::RunePool => {
+            let from_timestamp = _resume_from(checkpoints, repos.runepool.as_ref(), dataset, None).await?;
+            let result = _fetch_and_store_runepool_data(midgard, repos.runepool.as_ref(), true, from_timestamp, target_timestamp)
+                .await
+                .map_err(|e| e.to_string());
+            if !_record_outcome(checkpoints, dataset, None, result).await? {
+                return Err("Runepool history failed".into());
+            }
+        }
+        Dataset::Swaps => {
+            let pools = _pools_for(midgard, pool).await?;
+            let mut failed_pools = Vec::new();
+            for pool in pools {
+                let from_timestamp = _resume_from(checkpoints, repos.swaps.as_ref(), dataset, Some(&pool)).await?;
+                let result = _fetch_and_store_swaps_data(midgard, pool.clone(), repos.swaps.as_ref(), true, from_timestamp, target_timestamp)
+                    .await
+                    .map_err(|e| e.to_string());
+                if !_record_outcome(checkpoints, dataset, Some(&pool), result).await? {
+                    failed_pools.push(pool);
+                }
+            }
+            if !failed_pools.is_empty() {
+                return Err(format!("Swaps history failed for pools: {}", failed_pools.join(", ")).into());
+            }
+        }
+        Dataset::Earnings => {
+            let from_timestamp = _resume_from(checkpoints, repos.earnings.as_ref(), dataset, None).await?;
+            let result = _fetch_and_store_earnings_and_pools(midgard, repos.earnings.as_ref(), true, from_timestamp, target_timestamp)
+                .await
+                .map_err(|e| e.to_string());
+            if !_record_outcome(checkpoints, dataset, None, result).await? {
+                return Err("Earnings history failed".into());
+            }
+        }
+    }
 
     Ok(())
 }
 
-async fn _get_last_end_time<T>(collection: &Collection<T>) -> Result<i64, Box<dyn std::error::Error>>
-where
-T: serde::de::DeserializeOwned + serde::Serialize + Unpin + Send + Sync,
-{
-    let filter = doc! {};
+/// Fetches `[from_timestamp, target_timestamp)` of one dataset, e.g. to fill a gap, leaving
+/// the checkpoint alone. `pool` is required for the per-pool datasets.
+pub async fn fetch_and_store_range(
+    repos: &Repositories,
+    midgard: &MidgardClient,
+    dataset: Dataset,
+    pool: Option<&str>,
+    from_timestamp: i64,
+    target_timestamp: i64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if from_timestamp >= target_timestamp {
+        return Err(format!("Invalid range {}..{}", from_timestamp, target_timestamp).into());
+    }
+    let pool = match (dataset.per_pool(), pool) {
+        (true, Some(pool)) => pool.to_string(),
+        (true, None) => return Err(format!("A pool is required to fetch {} history", dataset).into()),
+        (false, _) => String::new(),
+    };
 
-    let mut cursor = collection.find(filter).await?;
+    match dataset {
+        Dataset::Depth => _fetch_and_store_data(midgard, pool, repos.depth.as_ref(), false, from_timestamp, target_timestamp).await,
+        Dataset::RunePool => _fetch_and_store_runepool_data(midgard, repos.runepool.as_ref(), false, from_timestamp, target_timestamp).await,
+        Dataset::Swaps => _fetch_and_store_swaps_data(midgard, pool, repos.swaps.as_ref(), false, from_timestamp, target_timestamp).await,
+        Dataset::Earnings => _fetch_and_store_earnings_and_pools(midgard, repos.earnings.as_ref(), false, from_timestamp, target_timestamp).await,
+    }
+}
 
-    let mut documents: Vec<T> = Vec::new();
-    while let Some(result) = cursor.next().await {
-        match result {
-            Ok(doc) => documents.push(doc),
-            Err(err) => {
-                eprintln!("Error fetching document: {:?}", err);
-                continue;
-            }
+/// Returns the checkpoint to resume from, seeding it from the stored data the first time
+/// a dataset runs against a database that predates `ingestion_state`.
+async fn _resume_from<T: HistoryRecord>(
+    checkpoints: &dyn CheckpointRepository,
+    repository: &dyn HistoryRepository<T>,
+    dataset: Dataset,
+    pool: Option<&str>,
+) -> Result<i64, Box<dyn std::error::Error>> {
+    checkpoints.record_status(dataset, pool, STATUS_RUNNING, None).await?;
+
+    if let Some(state) = checkpoints.get(dataset, pool).await? {
+        if state.last_end_time > 0 {
+            return Ok(state.last_end_time);
         }
     }
 
-    documents.sort_by_key(|doc| {
-        let bson_doc = bson::to_document(doc).unwrap_or_else(|_| doc! {});
-        -bson_doc.get_i64("endTime").unwrap_or(0)
-    });
+    let filter = HistoryFilter { pool: pool.map(|pool| pool.to_string()), ..HistoryFilter::default() };
+    let last_end_time = repository.aggregate(&filter, &[Accumulator::Max("endTime")]).await?;
+    Ok(last_end_time.first().and_then(|end_time| end_time.as_i64()).unwrap_or(0))
+}
 
-    if let Some(doc) = documents.first() {
-        let bson_doc = bson::to_document(doc).unwrap_or_else(|_| doc! {});
-        let end_time = bson_doc.get_i64("endTime").unwrap_or(0);
-        Ok(end_time)
-    } else {
-        Ok(0)
+/// Stores the outcome of a run on its checkpoint and reports whether it succeeded.
+async fn _record_outcome(
+    checkpoints: &dyn CheckpointRepository,
+    dataset: Dataset,
+    pool: Option<&str>,
+    result: Result<(), String>,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    match result {
+        Ok(()) => {
+            checkpoints.record_status(dataset, pool, STATUS_OK, None).await?;
+            Ok(true)
+        }
+        Err(e) => {
+            log::error!("Error fetching {} history{}: {}", dataset, pool.map(|pool| format!(" for {}", pool)).unwrap_or_default(), e);
+            checkpoints.record_status(dataset, pool, STATUS_FAILED, Some(e)).await?;
+            Ok(false)
+        }
     }
 }
+
+/// The pools to ingest: `POOL_ALLOWLIST` (or the legacy `POOL`), else every Midgard pool,
+/// less `POOL_DENYLIST`.
+pub async fn _resolve_pools(midgard: &MidgardClient) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let allowlist = env::var("POOL_ALLOWLIST").or_else(|_| env::var("POOL")).ok();
+    let denylist = _parse_pool_list(&env::var("POOL_DENYLIST").unwrap_or_default());
+
+    let pools = match allowlist {
+        Some(list) => _parse_pool_list(&list),
+        None => midgard.pools().await?,
+    };
+
+    Ok(pools.into_iter().filter(|pool| !denylist.contains(pool)).collect())
+}
+
+async fn _pools_for(midgard: &MidgardClient, pool: Option<&str>) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    match pool {
+        Some(pool) => Ok(vec![pool.to_string()]),
+        None => _resolve_pools(midgard).await,
+    }
+}
+
+fn _parse_pool_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|pool| pool.trim())
+        .filter(|pool| !pool.is_empty())
+        .map(|pool| pool.to_string())
+        .collect()
+}
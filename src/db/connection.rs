@@ -1,9 +1,26 @@
 use mongodb::{options::ClientOptions, Client};
 use std::env;
+use crate::db::indexes::ensure_indexes;
 
+/// Connects to `MONGO_URI` and bootstraps the indexes unless `MONGO_BOOTSTRAP=false`.
+/// Bootstrap problems are logged, not fatal.
 pub async fn get_db() -> mongodb::error::Result<Client> {
+    let client = connect().await?;
+
+    if env::var("MONGO_BOOTSTRAP").map(|value| value != "false").unwrap_or(true) {
+        match ensure_indexes(&client, false).await {
+            Ok(report) => report.log(),
+            Err(e) => log::error!("Error bootstrapping indexes: {:?}", e),
+        }
+    }
+
+    Ok(client)
+}
+
+/// Connects to `MONGO_URI` without touching the schema.
+pub async fn connect() -> mongodb::error::Result<Client> {
     let client_uri = env::var("MONGO_URI").unwrap();
     let client_options = ClientOptions::parse(client_uri).await?;
     let client = Client::with_options(client_options)?;
     Ok(client)
-}
\ No newline at end of file
+}
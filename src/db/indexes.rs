@@ -0,0 +1,209 @@
+use futures::StreamExt;
+use mongodb::bson::{doc, oid::ObjectId, Bson, Document};
+use mongodb::error::ErrorKind;
+use mongodb::options::IndexOptions;
+use mongodb::{Client, Collection, IndexModel};
+
+/// An index every deployment of `historical_db` is expected to have.
+struct IndexSpec {
+    collection: &'static str,
+    keys: &'static [&'static str],
+    unique: bool,
+}
+
+impl IndexSpec {
+    fn name(&self) -> String {
+        let name = self.keys.join("_");
+        if self.unique {
+            format!("{}_unique", name)
+        } else {
+            name
+        }
+    }
+
+    fn key_document(&self) -> Document {
+        let mut keys = Document::new();
+        for field in self.keys {
+            keys.insert(*field, 1);
+        }
+        keys
+    }
+}
+
+/// Each collection's unique natural key, plus the indexes the routes' range filters and sorts use.
+const INDEXES: [IndexSpec; 10] = [
+    IndexSpec { collection: "depth_history", keys: &["pool", "startTime"], unique: true },
+    IndexSpec { collection: "depth_history", keys: &["startTime", "endTime"], unique: false },
+    IndexSpec { collection: "swaps_history", keys: &["pool", "startTime"], unique: true },
+    IndexSpec { collection: "swaps_history", keys: &["startTime", "endTime"], unique: false },
+    IndexSpec { collection: "runepool_history", keys: &["startTime"], unique: true },
+    IndexSpec { collection: "runepool_history", keys: &["endTime"], unique: false },
+    IndexSpec { collection: "earnings_history", keys: &["startTime"], unique: true },
+    IndexSpec { collection: "earnings_history", keys: &["endTime"], unique: false },
+    IndexSpec { collection: "earnings_history", keys: &["pools.pool", "startTime"], unique: false },
+    IndexSpec { collection: "ingestion_state", keys: &["dataset", "pool"], unique: true },
+];
+
+/// What `ensure_indexes` created and how the existing indexes differ from `INDEXES`, each
+/// entry as `collection.index_name`.
+#[derive(Debug, Default)]
+pub struct IndexReport {
+    pub created: Vec<String>,
+    /// Expected indexes blocked by an existing index with the same name or keys.
+    pub conflicting: Vec<String>,
+    /// Indexes that exist but are not expected.
+    pub unexpected: Vec<String>,
+    /// Unique indexes blocked by duplicate rows that were not removed.
+    pub duplicated: Vec<String>,
+}
+
+impl IndexReport {
+    pub fn log(&self) {
+        for name in &self.created {
+            log::info!("Created index {}", name);
+        }
+        for name in &self.conflicting {
+            log::warn!("Index drift: {} conflicts with an existing index", name);
+        }
+        for name in &self.unexpected {
+            log::warn!("Index drift: unexpected index {}", name);
+        }
+        for name in &self.duplicated {
+            log::warn!("Index drift: {} is missing because of duplicate rows; run `reindex --remove-duplicates`", name);
+        }
+    }
+}
+
+/// Brings every collection's indexes in line with `INDEXES`. With `remove_duplicates`,
+/// rows blocking a unique index are collapsed first; other drift is only reported.
+pub async fn ensure_indexes(db: &Client, remove_duplicates: bool) -> mongodb::error::Result<IndexReport> {
+    let database = db.database("historical_db");
+    let mut report = IndexReport::default();
+
+    let mut collections: Vec<&str> = INDEXES.iter().map(|spec| spec.collection).collect();
+    collections.dedup();
+
+    for collection_name in collections {
+        let collection = database.collection::<Document>(collection_name);
+        let specs: Vec<&IndexSpec> = INDEXES.iter().filter(|spec| spec.collection == collection_name).collect();
+        let existing = _existing_indexes(&collection).await?;
+
+        for spec in &specs {
+            let name = spec.name();
+            let keys = spec.key_document();
+            match existing.iter().find(|index| index.name == name || index.keys == keys) {
+                Some(index) if index.name == name && index.keys == keys && index.unique == spec.unique => continue,
+                Some(_) => {
+                    report.conflicting.push(format!("{}.{}", collection_name, name));
+                    continue;
+                }
+                None => {}
+            }
+
+            if spec.unique && remove_duplicates {
+                let removed = _remove_duplicates(&collection, spec.keys).await?;
+                if !removed.is_empty() {
+                    log::warn!("Removed {} duplicate documents from {}", removed.len(), collection_name);
+                }
+                if collection_name == "earnings_history" && !removed.is_empty() {
+                    let legacy = database
+                        .collection::<Document>("pools_history")
+                        .delete_many(doc! { "earnings_id": { "$in": &removed } })
+                        .await?;
+                    log::warn!("Removed {} pools_history rows of the removed earnings intervals", legacy.deleted_count);
+                }
+            }
+
+            let options = IndexOptions::builder().unique(spec.unique).name(name.clone()).build();
+            match collection.create_index(IndexModel::builder().keys(keys).options(options).build()).await {
+                Ok(_) => report.created.push(format!("{}.{}", collection_name, name)),
+                // DuplicateKey: rows the index would reject are still stored.
+                Err(e) if matches!(*e.kind, ErrorKind::Command(ref command) if command.code == 11000) => {
+                    report.duplicated.push(format!("{}.{}", collection_name, name));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        for index in existing {
+            if index.name != "_id_" && !specs.iter().any(|spec| spec.name() == index.name) {
+                report.unexpected.push(format!("{}.{}", collection_name, index.name));
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+struct ExistingIndex {
+    name: String,
+    keys: Document,
+    unique: bool,
+}
+
+async fn _existing_indexes(collection: &Collection<Document>) -> mongodb::error::Result<Vec<ExistingIndex>> {
+    let mut indexes = Vec::new();
+    let mut cursor = match collection.list_indexes().await {
+        Ok(cursor) => cursor,
+        // NamespaceNotFound: the collection has not been created yet.
+        Err(e) if matches!(*e.kind, ErrorKind::Command(ref command) if command.code == 26) => return Ok(indexes),
+        Err(e) => return Err(e),
+    };
+
+    while let Some(index) = cursor.next().await {
+        let index = index?;
+        let options = index.options.unwrap_or_default();
+        indexes.push(ExistingIndex {
+            name: options.name.unwrap_or_default(),
+            keys: index.keys.into_iter().map(|(field, direction)| (field, _normalise_direction(direction))).collect(),
+            unique: options.unique.unwrap_or(false),
+        });
+    }
+
+    Ok(indexes)
+}
+
+/// Index directions may come back as any numeric type; compare them as `Int32`.
+fn _normalise_direction(direction: Bson) -> Bson {
+    match direction {
+        Bson::Int64(direction) => Bson::Int32(direction as i32),
+        Bson::Double(direction) => Bson::Int32(direction as i32),
+        other => other,
+    }
+}
+
+/// Deletes every copy but the most recently inserted of each `fields` key, logging each
+/// one, and returns the ids deleted.
+async fn _remove_duplicates(collection: &Collection<Document>, fields: &[&str]) -> mongodb::error::Result<Vec<ObjectId>> {
+    let mut group_key = Document::new();
+    for field in fields {
+        group_key.insert(*field, format!("${}", field));
+    }
+
+    let pipeline = vec![
+        doc! { "$sort": { "_id": 1 } },
+        doc! { "$group": { "_id": group_key, "ids": { "$push": "$_id" }, "count": { "$sum": 1 } } },
+        doc! { "$match": { "count": { "$gt": 1 } } },
+    ];
+
+    let mut cursor = collection.aggregate(pipeline).allow_disk_use(true).await?;
+    let mut stale_ids: Vec<ObjectId> = Vec::new();
+    while let Some(group) = cursor.next().await {
+        let group = group?;
+        if let Ok(ids) = group.get_array("ids") {
+            let ids: Vec<ObjectId> = ids.iter().filter_map(|id| id.as_object_id()).collect();
+            if let Some((kept, older)) = ids.split_last() {
+                for id in older {
+                    log::warn!("Removing duplicate {} {} ({}), keeping {}", collection.name(), group.get("_id").unwrap_or(&Bson::Null), id, kept);
+                }
+                stale_ids.extend_from_slice(older);
+            }
+        }
+    }
+
+    if !stale_ids.is_empty() {
+        collection.delete_many(doc! { "_id": { "$in": &stale_ids } }).await?;
+    }
+
+    Ok(stale_ids)
+}
@@ -0,0 +1,60 @@
+use chrono::Utc;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::{ClientSession, Collection};
+use mongodb::error::Result;
+use crate::models::ingestion_state::{IngestionState, STATUS_RUNNING};
+
+fn _state_filter(dataset: &str, pool: Option<&str>) -> Document {
+    doc! { "dataset": dataset, "pool": pool.map_or(Bson::Null, |pool| Bson::String(pool.to_string())) }
+}
+
+pub async fn _get_checkpoint(
+    collection: &Collection<IngestionState>,
+    dataset: &str,
+    pool: Option<&str>,
+) -> Result<Option<IngestionState>> {
+    collection.find_one(_state_filter(dataset, pool)).await
+}
+
+/// Advances the checkpoint inside the transaction that writes its batch; `$max` keeps it
+/// from ever moving backwards.
+pub async fn _record_batch(
+    collection: &Collection<IngestionState>,
+    dataset: &str,
+    pool: Option<&str>,
+    end_time: i64,
+    rows: i64,
+    session: &mut ClientSession,
+) -> Result<()> {
+    let update = doc! {
+        "$max": { "lastEndTime": end_time },
+        "$inc": { "totalRows": rows },
+        "$set": {
+            "status": STATUS_RUNNING,
+            "error": Bson::Null,
+            "lastBatchRows": rows,
+            "updatedAt": Utc::now().timestamp(),
+        },
+    };
+    collection.update_one(_state_filter(dataset, pool), update).upsert(true).session(session).await?;
+    Ok(())
+}
+
+pub async fn _record_status(
+    collection: &Collection<IngestionState>,
+    dataset: &str,
+    pool: Option<&str>,
+    status: &str,
+    error: Option<String>,
+) -> Result<()> {
+    let update = doc! {
+        "$set": {
+            "status": status,
+            "error": error,
+            "updatedAt": Utc::now().timestamp(),
+        },
+        "$setOnInsert": { "lastEndTime": 0_i64, "lastBatchRows": 0_i64, "totalRows": 0_i64 },
+    };
+    collection.update_one(_state_filter(dataset, pool), update).upsert(true).await?;
+    Ok(())
+}
@@ -0,0 +1,29 @@
+use mongodb::bson::doc;
+use mongodb::bson::Document;
+use mongodb::Client;
+
+/// Embeds legacy `pools_history` rows into their `earnings_history` intervals, returning how
+/// many were migrated. `drop_legacy` drops `pools_history` afterwards.
+pub async fn _embed_pools_history(db: &Client, drop_legacy: bool) -> mongodb::error::Result<u64> {
+    let database = db.database("historical_db");
+    let earnings = database.collection::<Document>("earnings_history");
+    let unmigrated = doc! { "pools": { "$exists": false } };
+
+    let pending = earnings.count_documents(unmigrated.clone()).await?;
+    if pending > 0 {
+        let pipeline = vec![
+            doc! { "$match": unmigrated.clone() },
+            doc! { "$lookup": { "from": "pools_history", "localField": "_id", "foreignField": "earnings_id", "as": "pools" } },
+            doc! { "$project": { "pools": 1 } },
+            doc! { "$unset": ["pools._id", "pools.earnings_id"] },
+            doc! { "$merge": { "into": "earnings_history", "on": "_id", "whenMatched": "merge", "whenNotMatched": "discard" } },
+        ];
+        earnings.aggregate(pipeline).allow_disk_use(true).await?;
+    }
+
+    if drop_legacy && earnings.count_documents(unmigrated).await? == 0 {
+        database.collection::<Document>("pools_history").drop().await?;
+    }
+
+    Ok(pending)
+}
@@ -1,6 +1,5 @@
 pub mod connection;
-pub mod insert_depth;
-pub mod insert_runepool;
-pub mod insert_swap;
-pub mod insert_earnings;
-pub mod insert_pools;
\ No newline at end of file
+pub mod migrate_earnings;
+pub mod indexes;
+pub mod ingestion_state;
+pub mod sql;
@@ -0,0 +1,46 @@
+use std::error::Error;
+use sqlx::any::AnyPoolOptions;
+use sqlx::migrate::Migrator;
+use sqlx::AnyPool;
+
+static SQLITE_MIGRATIONS: Migrator = sqlx::migrate!("migrations/sqlite");
+static POSTGRES_MIGRATIONS: Migrator = sqlx::migrate!("migrations/postgres");
+
+/// The SQL flavours the relational store runs on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    Sqlite,
+    Postgres,
+}
+
+impl Dialect {
+    pub fn from_url(url: &str) -> Result<Self, String> {
+        if url.starts_with("sqlite:") {
+            Ok(Dialect::Sqlite)
+        } else if url.starts_with("postgres:") || url.starts_with("postgresql:") {
+            Ok(Dialect::Postgres)
+        } else {
+            Err(format!("Unsupported DATABASE_URL {:?}, expected sqlite: or postgres:", url))
+        }
+    }
+}
+
+/// Connects to `url` and applies pending migrations. SQLite gets one connection, as each
+/// `sqlite::memory:` connection would see its own database.
+pub async fn connect(url: &str) -> Result<(AnyPool, Dialect), Box<dyn Error>> {
+    sqlx::any::install_default_drivers();
+    let dialect = Dialect::from_url(url)?;
+
+    let mut options = AnyPoolOptions::new();
+    if dialect == Dialect::Sqlite {
+        options = options.max_connections(1);
+    }
+    let pool = options.connect(url).await?;
+
+    match dialect {
+        Dialect::Sqlite => SQLITE_MIGRATIONS.run(&pool).await?,
+        Dialect::Postgres => POSTGRES_MIGRATIONS.run(&pool).await?,
+    }
+
+    Ok((pool, dialect))
+}
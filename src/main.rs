@@ -1,73 +1,89 @@
-// use std::env;
-// use crate::models::{depth_history::DepthHistory, runepool_history::RunePoolHistory, swaps_history::SwapHistory, earnings_history::EarningsHistory, pools_history::PoolHistory};
-// use services::{fetch_depth::_fetch_and_store_data, fetch_runepool::_fetch_and_store_runepool_data, fetch_swaps::_fetch_and_store_swaps_data, fetch_earnings::_fetch_and_store_earnings_and_pools};
-
-use api::{depth_history::depth_history_route, earnings::earnings_with_pools_route, runepool::runepool_history_route, swaps::swaps_history_route};
+use api::error::with_request_id;
+use api::openapi::ApiDoc;
+use clap::Parser;
+use cli::{Cli, Command};
 use dotenv::dotenv;
+use repository::Repositories;
+use services::midgard_client::MidgardClient;
 use std::error::Error;
+use std::process::ExitCode;
+use std::sync::Arc;
+use actix_web::middleware::from_fn;
 use actix_web::{web, App, HttpServer};
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
 
 mod api;
+mod cli;
 mod db;
 mod models;
+mod repository;
 mod services;
 mod data_fetcher;
+mod scheduler;
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+async fn main() -> ExitCode {
     dotenv().ok();
+    // `RUST_LOG` narrows or widens it; errors and warnings show by default.
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+    let cli = Cli::parse();
 
-    let db = db::connection::get_db().await?;
-    // data_fetcher::_fetch_and_store_all_data(&db).await?;
-    
-    // let pool = env::var("POOL").expect("POOL must be set in .env");
-    // let from_timestamp: i64 = env::var("START_TIME").expect("START_TIME must be set in .env").parse::<i64>().expect("Invalid START_TIME format");
-    // let target_timestamp: i64 = env::var("END_TIME").expect("END_TIME must be set in .env").parse::<i64>().expect("Invalid END_TIME format");
+    match run(cli.command.unwrap_or(Command::Serve { bind: "0.0.0.0:3030".to_string(), no_ingest: false })).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            log::error!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
 
-    // let collection = db.database("historical_db").collection::<DepthHistory>("depth_history");
-    // _fetch_and_store_data(pool.clone(), &collection, from_timestamp, target_timestamp).await?;
-    // let collection = db.database("historical_db").collection::<RunePoolHistory>("runepool_history");
-    // _fetch_and_store_runepool_data(&collection, from_timestamp, target_timestamp).await?;
-    // let collection = db.database("historical_db").collection::<SwapHistory>("swaps_history");
-    // _fetch_and_store_swaps_data(pool.clone(), &collection, from_timestamp, target_timestamp).await?;
-    // let earnings_collection = db.database("historical_db").collection::<EarningsHistory>("earnings_history");
-    // let pools_collection = db.database("historical_db").collection::<PoolHistory>("pools_history");
-    // _fetch_and_store_earnings_and_pools(&earnings_collection, &pools_collection, from_timestamp, target_timestamp).await?;
+async fn run(command: Command) -> Result<(), Box<dyn Error>> {
+    match command {
+        Command::Serve { bind, no_ingest } => serve(Repositories::from_env().await?, Arc::new(MidgardClient::from_env()?), &bind, !no_ingest).await,
+        Command::Ingest { dataset, pool, from, to } => {
+            cli::ingest(&Repositories::from_env().await?, &MidgardClient::from_env()?, dataset, pool.as_deref(), from, to).await
+        }
+        Command::BackfillAll => cli::backfill_all(&Repositories::from_env().await?, &MidgardClient::from_env()?).await,
+        Command::VerifyGaps { dataset, pool, from, to, backfill } => {
+            cli::verify_gaps(&Repositories::from_env().await?, &MidgardClient::from_env()?, dataset, pool.as_deref(), from, to, backfill).await
+        }
+        Command::Stats => cli::stats(&Repositories::from_env().await?).await,
+        // Schema maintenance is Mongo-only and reports on the schema as found, so it
+        // connects without the startup bootstrap.
+        Command::Reindex { remove_duplicates } => cli::reindex(&db::connection::connect().await?, remove_duplicates).await,
+        Command::MigrateEarnings { drop_legacy } => cli::migrate_earnings(&db::connection::connect().await?, drop_legacy).await,
+    }
+}
 
+async fn serve(repos: Repositories, midgard: Arc<MidgardClient>, bind: &str, ingest: bool) -> Result<(), Box<dyn Error>> {
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let ingestion = if ingest {
+        scheduler::spawn(repos.clone(), midgard.clone(), scheduler::SchedulerConfig::from_env()?, shutdown_rx)
+    } else {
+        Vec::new()
+    };
 
     // Routes
+    let openapi = ApiDoc::openapi();
 
-    let runepool_collection = db
-        .database("historical_db")
-        .collection::<models::runepool_history::RunePoolHistory>("runepool_history");
-    let depth_collection = db
-        .database("historical_db")
-        .collection::<models::depth_history::DepthHistory>("depth_history");
-    let swap_collection = db
-        .database("historical_db")
-        .collection::<models::swaps_history::SwapHistory>("swaps_history");
-    let earnings_collection = db
-        .database("historical_db")
-        .collection::<models::earnings_history::EarningsHistory>("earnings_history");
-    let pools_collection = db
-        .database("historical_db")
-        .collection::<models::pools_history::PoolHistory>("pools_history");
-
-    println!("Starting the server on port 3030...");
-    HttpServer::new(move || {
+    log::info!("Starting the server on {}...", bind);
+    let server = HttpServer::new(move || {
         App::new()
-            .app_data(web::Data::new(runepool_collection.clone()))
-            .app_data(web::Data::new(depth_collection.clone()))
-            .app_data(web::Data::new(swap_collection.clone()))
-            .app_data(web::Data::new(earnings_collection.clone()))
-            .app_data(web::Data::new(pools_collection.clone()))
-            .route("/depth-history", web::get().to(depth_history_route))
-            .route("/runepool-history", web::get().to(runepool_history_route))
-            .route("/earnings", web::get().to(earnings_with_pools_route))
-            .route("/swaps-history", web::get().to(swaps_history_route))
+            .wrap(from_fn(with_request_id))
+            .app_data(web::Data::from(midgard.clone()))
+            .service(SwaggerUi::new("/swagger-ui/{_:.*}").url("/api-docs/openapi.json", openapi.clone()))
+            .configure(|cfg| api::configure_app(cfg, &repos))
     })
-    .bind("0.0.0.0:3030")?
+    .bind(bind)?
     .run()
-    .await?;
+    .await;
+
+    let _ = shutdown_tx.send(true);
+    for handle in ingestion {
+        let _ = handle.await;
+    }
+
+    server?;
     Ok(())
-}
\ No newline at end of file
+}
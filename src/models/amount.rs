@@ -0,0 +1,249 @@
+use std::fmt;
+use std::str::FromStr;
+use bson::{Bson, Decimal128};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use utoipa::openapi::{ObjectBuilder, RefOr, Schema, SchemaType};
+use utoipa::ToSchema;
+
+/// A 1e8-scaled Midgard integer, which can exceed `i64`. Stored as `Decimal128` (older
+/// documents hold `Int64`) and written to JSON as a string.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Amount(pub i128);
+
+/// A fractional Midgard value (prices, slips, `luvi`, node counts), kept as its exact
+/// decimal text rather than rounded through `f64`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Decimal(String);
+
+impl Amount {
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0.checked_add(other.0).map(Amount)
+    }
+}
+
+/// Zero rather than empty text, which no store could read back.
+impl Default for Decimal {
+    fn default() -> Self {
+        Decimal("0".to_string())
+    }
+}
+
+impl Decimal {
+    /// Approximate value, for weighting and averaging.
+    pub fn to_f64(&self) -> f64 {
+        self.0.parse().unwrap_or_default()
+    }
+
+    /// A computed value, rounded to 12 places to drop `f64` noise; non-finite values
+    /// become zero.
+    pub fn from_f64(value: f64) -> Decimal {
+        if !value.is_finite() {
+            return Decimal("0".to_string());
+        }
+        let rounded = format!("{:.12}", value);
+        let trimmed = rounded.trim_end_matches('0').trim_end_matches('.');
+        Decimal(if trimmed == "-0" { "0".to_string() } else { trimmed.to_string() })
+    }
+}
+
+/// Both are JSON strings, so clients never round them through a float.
+impl<'s> ToSchema<'s> for Amount {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        let schema = ObjectBuilder::new()
+            .schema_type(SchemaType::String)
+            .description(Some("A 1e8-scaled integer, as a string since it can exceed 64 bits"))
+            .example(Some("123450000".into()));
+        ("Amount", schema.into())
+    }
+}
+
+impl<'s> ToSchema<'s> for Decimal {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        let schema = ObjectBuilder::new()
+            .schema_type(SchemaType::String)
+            .description(Some("A fractional value as its exact decimal text"))
+            .example(Some("1.2345".into()));
+        ("Decimal", schema.into())
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for Amount {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        _parse_integral(s).map(Amount).ok_or_else(|| format!("invalid integer amount {:?}", s))
+    }
+}
+
+/// Midgard writes decimals as plain `[-]digits[.digits]`; exponents, `NaN` and infinities
+/// could not be stored as `Decimal128`.
+impl FromStr for Decimal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let unsigned = s.strip_prefix('-').unwrap_or(s);
+        let (whole, fraction) = match unsigned.split_once('.') {
+            Some((whole, fraction)) => (whole, Some(fraction)),
+            None => (unsigned, None),
+        };
+        let digits = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+        if !digits(whole) || !fraction.is_none_or(digits) {
+            return Err(format!("invalid decimal {:?}", s));
+        }
+        Ok(Decimal(s.to_string()))
+    }
+}
+
+/// Writes `Decimal128` exponent notation plainly: `1.5E-7` becomes `0.00000015`.
+pub fn plain_notation(text: &str) -> String {
+    let Some((mantissa, exponent)) = text.split_once(['E', 'e']) else {
+        return text.to_string();
+    };
+    let Ok(exponent) = exponent.parse::<i32>() else {
+        return text.to_string();
+    };
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(mantissa) => ("-", mantissa),
+        None => ("", mantissa.strip_prefix('+').unwrap_or(mantissa)),
+    };
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let digits = format!("{}{}", whole, fraction);
+    // Where the decimal point falls among `digits`.
+    let point = whole.len() as i64 + exponent as i64;
+    let plain = if point <= 0 {
+        format!("0.{}{}", "0".repeat(point.unsigned_abs() as usize), digits)
+    } else if point as usize >= digits.len() {
+        format!("{}{}", digits, "0".repeat(point as usize - digits.len()))
+    } else {
+        format!("{}.{}", &digits[..point as usize], &digits[point as usize..])
+    };
+    format!("{}{}", sign, plain)
+}
+
+/// Parses `123`, `-4`, `1.20E+3` or `5.0` as an exact integer; fractional values are rejected.
+fn _parse_integral(s: &str) -> Option<i128> {
+    let s = s.trim();
+    let (mantissa, exponent) = match s.find(['e', 'E']) {
+        Some(index) => (&s[..index], s[index + 1..].parse::<i32>().ok()?),
+        None => (s, 0),
+    };
+    let (negative, mantissa) = match mantissa.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, mantissa.strip_prefix('+').unwrap_or(mantissa)),
+    };
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        return None;
+    }
+
+    let mut digits = format!("{}{}", whole, fraction);
+    let mut exponent = exponent - fraction.len() as i32;
+    while exponent < 0 {
+        if !digits.ends_with('0') {
+            return None;
+        }
+        digits.pop();
+        exponent += 1;
+    }
+    if digits.is_empty() {
+        digits.push('0');
+    }
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let mut value = digits.parse::<i128>().ok()?;
+    for _ in 0..exponent {
+        value = value.checked_mul(10)?;
+    }
+    Some(if negative { -value } else { value })
+}
+
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.0.to_string())
+        } else {
+            Decimal128::from_str(&self.0.to_string())
+                .map_err(serde::ser::Error::custom)?
+                .serialize(serializer)
+        }
+    }
+}
+
+impl Serialize for Decimal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.0)
+        } else {
+            Decimal128::from_str(&self.0)
+                .map_err(serde::ser::Error::custom)?
+                .serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Bson::deserialize(deserializer)? {
+            Bson::Decimal128(value) => value.to_string().parse().map_err(de::Error::custom),
+            Bson::String(value) => value.parse().map_err(de::Error::custom),
+            Bson::Int64(value) => Ok(Amount(value as i128)),
+            Bson::Int32(value) => Ok(Amount(value as i128)),
+            Bson::Double(value) if value.fract() == 0.0 => Ok(Amount(value as i128)),
+            other => Err(de::Error::custom(format!("invalid amount {}", other))),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Bson::deserialize(deserializer)? {
+            Bson::Decimal128(value) => plain_notation(&value.to_string()).parse().map_err(de::Error::custom),
+            Bson::String(value) => value.parse().map_err(de::Error::custom),
+            Bson::Double(value) if value.is_finite() => Ok(Decimal(value.to_string())),
+            Bson::Int64(value) => Ok(Decimal(value.to_string())),
+            Bson::Int32(value) => Ok(Decimal(value.to_string())),
+            other => Err(de::Error::custom(format!("invalid decimal {}", other))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decimals_are_plain_digits() {
+        for text in ["0", "-1.25", "123456789012345678901234567890.5", "0.00000001"] {
+            assert_eq!(text.parse::<Decimal>().map(|decimal| decimal.to_string()), Ok(text.to_string()));
+        }
+        for text in ["", "-", "NaN", "nan", "inf", "-infinity", "1e400", "1E-7", "+1", "1.", ".5", "1.2.3", "0x10", "1 000"] {
+            assert!(text.parse::<Decimal>().is_err(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn stored_decimals_read_back_in_plain_notation() {
+        let read = |text: &str| bson::from_bson::<Decimal>(Bson::Decimal128(text.parse().unwrap())).unwrap().to_string();
+        assert_eq!(read("1E-8"), "0.00000001");
+        assert_eq!(read("-1.5E-7"), "-0.00000015");
+        assert_eq!(read("2E+3"), "2000");
+        assert_eq!(read("1.2345"), "1.2345");
+        assert!(bson::from_bson::<Decimal>(Bson::Double(f64::NAN)).is_err());
+        assert!(bson::from_bson::<Decimal>(Bson::String("Infinity".to_string())).is_err());
+    }
+}
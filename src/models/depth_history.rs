@@ -1,9 +1,12 @@
+use crate::models::amount::{Amount, Decimal};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use mongodb::bson::oid::ObjectId;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default, ToSchema)]
 pub struct DepthHistory {
     #[serde(skip_serializing_if = "Option::is_none", rename = "_id")]
+    #[schema(value_type = Option<String>)]
     pub id: Option<ObjectId>,
     pub pool: String,
     #[serde(rename = "startTime")]
@@ -11,28 +14,29 @@ pub struct DepthHistory {
     #[serde(rename = "endTime")]
     pub end_time: i64,
     #[serde(rename = "assetDepth")]
-    pub asset_depth: i64,
+    pub asset_depth: Amount,
     #[serde(rename = "assetPrice")]
-    pub asset_price: f64,
+    pub asset_price: Decimal,
     #[serde(rename = "assetPriceUSD")]
-    pub asset_price_usd: f64,
+    pub asset_price_usd: Decimal,
     #[serde(rename = "liquidityUnits")]
-    pub liquidity_units: i64,
+    pub liquidity_units: Amount,
     #[serde(rename = "membersCount")]
     pub members_count: i64,
     #[serde(rename = "runeDepth")]
-    pub rune_depth: i64,
+    pub rune_depth: Amount,
     #[serde(rename = "synthSupply")]
-    pub synth_supply: i64,
+    pub synth_supply: Amount,
     #[serde(rename = "synthUnits")]
-    pub synth_units: i64,
+    pub synth_units: Amount,
     #[serde(rename = "units")]
-    pub units: i64,
+    pub units: Amount,
     #[serde(rename = "luvi")]
-    pub luvi: f64,
+    pub luvi: Decimal,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = DepthHistoryMetadata)]
 pub struct Metadata {
     #[serde(rename = "startTime")]
     pub start_time: String,
@@ -1,25 +1,68 @@
+use crate::models::amount::{Amount, Decimal};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use bson::oid::ObjectId;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// One hourly earnings interval, with its pools embedded so they are written together.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, ToSchema)]
 pub struct EarningsHistory {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
     pub id: Option<ObjectId>,
     #[serde(rename = "avgNodeCount")]
-    pub avg_node_count: f64,
+    pub avg_node_count: Decimal,
     #[serde(rename = "blockRewards")]
-    pub block_rewards: i64,
+    pub block_rewards: Amount,
     #[serde(rename = "bondingEarnings")]
-    pub bonding_earnings: i64,
-    pub earnings: i64,
+    pub bonding_earnings: Amount,
+    pub earnings: Amount,
     #[serde(rename = "endTime")]
     pub end_time: i64,
     #[serde(rename = "liquidityEarnings")]
-    pub liquidity_earnings: i64,
+    pub liquidity_earnings: Amount,
     #[serde(rename = "liquidityFees")]
-    pub liquidity_fees: i64,
+    pub liquidity_fees: Amount,
     #[serde(rename = "runePriceUSD")]
-    pub rune_price_usd: f64,
+    pub rune_price_usd: Decimal,
     #[serde(rename = "startTime")]
     pub start_time: i64,
+    pub pools: Vec<PoolEarnings>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, ToSchema)]
+pub struct PoolEarnings {
+    pub pool: String,
+    #[serde(rename = "assetLiquidityFees")]
+    pub asset_liquidity_fees: Amount,
+    pub earnings: Amount,
+    pub rewards: Amount,
+    #[serde(rename = "runeLiquidityFees")]
+    pub rune_liquidity_fees: Amount,
+    #[serde(rename = "saverEarning")]
+    pub saver_earning: Amount,
+    #[serde(rename = "totalLiquidityFeesRune")]
+    pub total_liquidity_fees_rune: Amount,
+}
+
+/// Earnings summed over a range, with `avgNodeCount` averaged and the last `runePriceUSD`.
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = EarningsHistoryMetadata)]
+pub struct Metadata {
+    #[serde(rename = "startTime")]
+    pub start_time: String,
+    #[serde(rename = "endTime")]
+    pub end_time: String,
+    #[serde(rename = "avgNodeCount")]
+    pub avg_node_count: String,
+    #[serde(rename = "blockRewards")]
+    pub block_rewards: String,
+    #[serde(rename = "bondingEarnings")]
+    pub bonding_earnings: String,
+    pub earnings: String,
+    #[serde(rename = "liquidityEarnings")]
+    pub liquidity_earnings: String,
+    #[serde(rename = "liquidityFees")]
+    pub liquidity_fees: String,
+    #[serde(rename = "runePriceUSD")]
+    pub rune_price_usd: String,
 }
@@ -0,0 +1,81 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use mongodb::bson::oid::ObjectId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dataset {
+    Depth,
+    RunePool,
+    Swaps,
+    Earnings,
+}
+
+impl Dataset {
+    pub const ALL: [Dataset; 4] = [Dataset::Depth, Dataset::RunePool, Dataset::Swaps, Dataset::Earnings];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Dataset::Depth => "depth",
+            Dataset::RunePool => "runepool",
+            Dataset::Swaps => "swaps",
+            Dataset::Earnings => "earnings",
+        }
+    }
+
+    /// Collection the dataset's intervals are stored in.
+    pub fn collection(&self) -> &'static str {
+        match self {
+            Dataset::Depth => "depth_history",
+            Dataset::RunePool => "runepool_history",
+            Dataset::Swaps => "swaps_history",
+            Dataset::Earnings => "earnings_history",
+        }
+    }
+
+    /// Depth and swaps are stored (and checkpointed) per pool.
+    pub fn per_pool(&self) -> bool {
+        matches!(self, Dataset::Depth | Dataset::Swaps)
+    }
+}
+
+impl fmt::Display for Dataset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Dataset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Dataset::ALL
+            .into_iter()
+            .find(|dataset| dataset.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("unknown dataset {:?}, expected one of depth, runepool, swaps, earnings", s))
+    }
+}
+
+pub const STATUS_RUNNING: &str = "running";
+pub const STATUS_OK: &str = "ok";
+pub const STATUS_FAILED: &str = "failed";
+
+/// Resume checkpoint and last-run bookkeeping for one dataset (and pool, where the
+/// dataset is stored per pool).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IngestionState {
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_id")]
+    pub id: Option<ObjectId>,
+    pub dataset: String,
+    pub pool: Option<String>,
+    #[serde(rename = "lastEndTime")]
+    pub last_end_time: i64,
+    pub status: String,
+    pub error: Option<String>,
+    #[serde(rename = "lastBatchRows")]
+    pub last_batch_rows: i64,
+    #[serde(rename = "totalRows")]
+    pub total_rows: i64,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: i64,
+}
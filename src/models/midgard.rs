@@ -0,0 +1,332 @@
+use serde::Deserialize;
+use crate::models::amount::{Amount, Decimal};
+use serde_with::{serde_as, DisplayFromStr};
+use crate::models::{depth_history::DepthHistory, earnings_history::{EarningsHistory, PoolEarnings}, runepool_history::RunePoolHistory, swaps_history::SwapHistory};
+
+/// Body of every `/v2/history/*` endpoint, whose numbers Midgard encodes as strings.
+#[derive(Debug, Deserialize)]
+pub struct HistoryResponseDto<I> {
+    pub meta: HistoryMetaDto,
+    pub intervals: Vec<I>,
+}
+
+/// The part of `meta` the ingester pages on; the per-endpoint summaries are ignored.
+#[serde_as]
+#[derive(Debug, Deserialize)]
+pub struct HistoryMetaDto {
+    #[serde(rename = "endTime")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub end_time: i64,
+}
+
+/// One interval of `/v2/history/depths/{pool}`.
+#[serde_as]
+#[derive(Debug, Deserialize)]
+pub struct DepthIntervalDto {
+    #[serde(rename = "startTime")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub start_time: i64,
+    #[serde(rename = "endTime")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub end_time: i64,
+    #[serde(rename = "assetDepth")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub asset_depth: Amount,
+    #[serde(rename = "assetPrice")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub asset_price: Decimal,
+    #[serde(rename = "assetPriceUSD")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub asset_price_usd: Decimal,
+    #[serde(rename = "liquidityUnits")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub liquidity_units: Amount,
+    #[serde(rename = "membersCount")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub members_count: i64,
+    #[serde(rename = "runeDepth")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub rune_depth: Amount,
+    #[serde(rename = "synthSupply")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub synth_supply: Amount,
+    #[serde(rename = "synthUnits")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub synth_units: Amount,
+    #[serde_as(as = "DisplayFromStr")]
+    pub units: Amount,
+    #[serde_as(as = "DisplayFromStr")]
+    pub luvi: Decimal,
+}
+
+/// One interval of `/v2/history/swaps`.
+#[serde_as]
+#[derive(Debug, Deserialize)]
+pub struct SwapIntervalDto {
+    #[serde(rename = "averageSlip")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub average_slip: Decimal,
+    #[serde(rename = "endTime")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub end_time: i64,
+    #[serde(rename = "runePriceUSD")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub rune_price_usd: Decimal,
+    #[serde(rename = "startTime")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub start_time: i64,
+    #[serde(rename = "synthMintAverageSlip")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub synth_mint_average_slip: Decimal,
+    #[serde(rename = "synthMintCount")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub synth_mint_count: i64,
+    #[serde(rename = "synthMintFees")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub synth_mint_fees: Amount,
+    #[serde(rename = "synthMintVolume")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub synth_mint_volume: Amount,
+    #[serde(rename = "synthMintVolumeUSD")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub synth_mint_volume_usd: Decimal,
+    #[serde(rename = "synthRedeemAverageSlip")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub synth_redeem_average_slip: Decimal,
+    #[serde(rename = "synthRedeemCount")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub synth_redeem_count: i64,
+    #[serde(rename = "synthRedeemFees")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub synth_redeem_fees: Amount,
+    #[serde(rename = "synthRedeemVolume")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub synth_redeem_volume: Amount,
+    #[serde(rename = "synthRedeemVolumeUSD")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub synth_redeem_volume_usd: Decimal,
+    #[serde(rename = "toAssetAverageSlip")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub to_asset_average_slip: Decimal,
+    #[serde(rename = "toAssetCount")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub to_asset_count: i64,
+    #[serde(rename = "toAssetFees")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub to_asset_fees: Amount,
+    #[serde(rename = "toAssetVolume")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub to_asset_volume: Amount,
+    #[serde(rename = "toAssetVolumeUSD")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub to_asset_volume_usd: Decimal,
+    #[serde(rename = "toRuneAverageSlip")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub to_rune_average_slip: Decimal,
+    #[serde(rename = "toRuneCount")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub to_rune_count: i64,
+    #[serde(rename = "toRuneFees")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub to_rune_fees: Amount,
+    #[serde(rename = "toRuneVolume")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub to_rune_volume: Amount,
+    #[serde(rename = "toRuneVolumeUSD")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub to_rune_volume_usd: Decimal,
+    #[serde(rename = "totalCount")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub total_count: i64,
+    #[serde(rename = "totalFees")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub total_fees: Amount,
+    #[serde(rename = "totalVolume")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub total_volume: Amount,
+    #[serde(rename = "totalVolumeUSD")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub total_volume_usd: Decimal,
+}
+
+/// One interval of `/v2/history/runepool`.
+#[serde_as]
+#[derive(Debug, Deserialize)]
+pub struct RunePoolIntervalDto {
+    #[serde_as(as = "DisplayFromStr")]
+    pub count: i64,
+    #[serde(rename = "startTime")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub start_time: i64,
+    #[serde(rename = "endTime")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub end_time: i64,
+    #[serde_as(as = "DisplayFromStr")]
+    pub units: Amount,
+}
+
+/// One interval of `/v2/history/earnings`.
+#[serde_as]
+#[derive(Debug, Deserialize)]
+pub struct EarningsIntervalDto {
+    #[serde(rename = "avgNodeCount")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub avg_node_count: Decimal,
+    #[serde(rename = "blockRewards")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub block_rewards: Amount,
+    #[serde(rename = "bondingEarnings")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub bonding_earnings: Amount,
+    #[serde_as(as = "DisplayFromStr")]
+    pub earnings: Amount,
+    #[serde(rename = "endTime")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub end_time: i64,
+    #[serde(rename = "liquidityEarnings")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub liquidity_earnings: Amount,
+    #[serde(rename = "liquidityFees")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub liquidity_fees: Amount,
+    #[serde(rename = "runePriceUSD")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub rune_price_usd: Decimal,
+    #[serde(rename = "startTime")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub start_time: i64,
+    #[serde(default)]
+    pub pools: Vec<PoolEarningsDto>,
+}
+
+/// Per-pool breakdown inside an earnings interval.
+#[serde_as]
+#[derive(Debug, Deserialize)]
+pub struct PoolEarningsDto {
+    #[serde(rename = "assetLiquidityFees")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub asset_liquidity_fees: Amount,
+    #[serde_as(as = "DisplayFromStr")]
+    pub earnings: Amount,
+    pub pool: String,
+    #[serde_as(as = "DisplayFromStr")]
+    pub rewards: Amount,
+    #[serde(rename = "runeLiquidityFees")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub rune_liquidity_fees: Amount,
+    #[serde(rename = "saverEarning")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub saver_earning: Amount,
+    #[serde(rename = "totalLiquidityFeesRune")]
+    #[serde_as(as = "DisplayFromStr")]
+    pub total_liquidity_fees_rune: Amount,
+}
+
+/// One entry of `/v2/pools`.
+#[derive(Debug, Deserialize)]
+pub struct PoolDto {
+    pub asset: String,
+}
+
+impl DepthIntervalDto {
+    pub fn into_model(self, pool: &str) -> DepthHistory {
+        DepthHistory {
+            id: None,
+            pool: pool.to_string(),
+            start_time: self.start_time,
+            end_time: self.end_time,
+            asset_depth: self.asset_depth,
+            asset_price: self.asset_price,
+            asset_price_usd: self.asset_price_usd,
+            liquidity_units: self.liquidity_units,
+            members_count: self.members_count,
+            rune_depth: self.rune_depth,
+            synth_supply: self.synth_supply,
+            synth_units: self.synth_units,
+            units: self.units,
+            luvi: self.luvi,
+        }
+    }
+}
+
+impl SwapIntervalDto {
+    pub fn into_model(self, pool: &str) -> SwapHistory {
+        SwapHistory {
+            id: None,
+            pool: pool.to_string(),
+            average_slip: self.average_slip,
+            end_time: self.end_time,
+            rune_price_usd: self.rune_price_usd,
+            start_time: self.start_time,
+            synth_mint_average_slip: self.synth_mint_average_slip,
+            synth_mint_count: self.synth_mint_count,
+            synth_mint_fees: self.synth_mint_fees,
+            synth_mint_volume: self.synth_mint_volume,
+            synth_mint_volume_usd: self.synth_mint_volume_usd,
+            synth_redeem_average_slip: self.synth_redeem_average_slip,
+            synth_redeem_count: self.synth_redeem_count,
+            synth_redeem_fees: self.synth_redeem_fees,
+            synth_redeem_volume: self.synth_redeem_volume,
+            synth_redeem_volume_usd: self.synth_redeem_volume_usd,
+            to_asset_average_slip: self.to_asset_average_slip,
+            to_asset_count: self.to_asset_count,
+            to_asset_fees: self.to_asset_fees,
+            to_asset_volume: self.to_asset_volume,
+            to_asset_volume_usd: self.to_asset_volume_usd,
+            to_rune_average_slip: self.to_rune_average_slip,
+            to_rune_count: self.to_rune_count,
+            to_rune_fees: self.to_rune_fees,
+            to_rune_volume: self.to_rune_volume,
+            to_rune_volume_usd: self.to_rune_volume_usd,
+            total_count: self.total_count,
+            total_fees: self.total_fees,
+            total_volume: self.total_volume,
+            total_volume_usd: self.total_volume_usd,
+        }
+    }
+}
+
+impl From<RunePoolIntervalDto> for RunePoolHistory {
+    fn from(dto: RunePoolIntervalDto) -> Self {
+        RunePoolHistory {
+            id: None,
+            count: dto.count,
+            start_time: dto.start_time,
+            end_time: dto.end_time,
+            units: dto.units,
+        }
+    }
+}
+
+impl From<EarningsIntervalDto> for EarningsHistory {
+    fn from(dto: EarningsIntervalDto) -> Self {
+        let pools = dto.pools.into_iter().map(PoolEarnings::from).collect();
+        EarningsHistory {
+            id: None,
+            avg_node_count: dto.avg_node_count,
+            block_rewards: dto.block_rewards,
+            bonding_earnings: dto.bonding_earnings,
+            earnings: dto.earnings,
+            end_time: dto.end_time,
+            liquidity_earnings: dto.liquidity_earnings,
+            liquidity_fees: dto.liquidity_fees,
+            rune_price_usd: dto.rune_price_usd,
+            start_time: dto.start_time,
+            pools,
+        }
+    }
+}
+
+impl From<PoolEarningsDto> for PoolEarnings {
+    fn from(dto: PoolEarningsDto) -> Self {
+        PoolEarnings {
+            asset_liquidity_fees: dto.asset_liquidity_fees,
+            earnings: dto.earnings,
+            pool: dto.pool,
+            rewards: dto.rewards,
+            rune_liquidity_fees: dto.rune_liquidity_fees,
+            saver_earning: dto.saver_earning,
+            total_liquidity_fees_rune: dto.total_liquidity_fees_rune,
+        }
+    }
+}
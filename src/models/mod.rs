@@ -2,4 +2,7 @@ pub mod depth_history;
 pub mod runepool_history;
 pub mod swaps_history;
 pub mod earnings_history;
-pub mod pools_history;
\ No newline at end of file
+pub mod ingestion_state;
+pub mod midgard;
+pub mod amount;
+pub mod fields;
@@ -1,9 +1,12 @@
+use crate::models::amount::Amount;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use mongodb::bson::oid::ObjectId;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default, ToSchema)]
 pub struct RunePoolHistory {
     #[serde(skip_serializing_if = "Option::is_none", rename = "_id")]
+    #[schema(value_type = Option<String>)]
     pub id: Option<ObjectId>,
     #[serde(rename = "count")]
     pub count: i64,
@@ -12,5 +15,26 @@ pub struct RunePoolHistory {
     #[serde(rename = "endTime")]
     pub end_time: i64,
     #[serde(rename = "units")]
-    pub units: i64,
-}
\ No newline at end of file
+    pub units: Amount,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = RunePoolHistoryMetadata)]
+pub struct Metadata {
+    #[serde(rename = "startTime")]
+    pub start_time: String,
+    #[serde(rename = "endTime")]
+    pub end_time: String,
+    #[serde(rename = "startCount")]
+    pub start_count: String,
+    #[serde(rename = "endCount")]
+    pub end_count: String,
+    #[serde(rename = "avgCount")]
+    pub avg_count: String,
+    #[serde(rename = "startUnits")]
+    pub start_units: String,
+    #[serde(rename = "endUnits")]
+    pub end_units: String,
+    #[serde(rename = "avgUnits")]
+    pub avg_units: String,
+}
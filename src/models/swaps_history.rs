@@ -1,70 +1,74 @@
 use bson::oid::ObjectId;
+use crate::models::amount::{Amount, Decimal};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default, ToSchema)]
 pub struct SwapHistory {
     #[serde(skip_serializing_if = "Option::is_none", rename = "_id")]
+    #[schema(value_type = Option<String>)]
     pub id: Option<ObjectId>,
     pub pool: String,
     #[serde(rename = "averageSlip")]
-    pub average_slip: f64,
+    pub average_slip: Decimal,
     #[serde(rename = "endTime")]
     pub end_time: i64,
     #[serde(rename = "runePriceUSD")]
-    pub rune_price_usd: f64,
+    pub rune_price_usd: Decimal,
     #[serde(rename = "startTime")]
     pub start_time: i64,
     #[serde(rename = "synthMintAverageSlip")]
-    pub synth_mint_average_slip: f64,
+    pub synth_mint_average_slip: Decimal,
     #[serde(rename = "synthMintCount")]
     pub synth_mint_count: i64,
     #[serde(rename = "synthMintFees")]
-    pub synth_mint_fees: i64,
+    pub synth_mint_fees: Amount,
     #[serde(rename = "synthMintVolume")]
-    pub synth_mint_volume: i64,
+    pub synth_mint_volume: Amount,
     #[serde(rename = "synthMintVolumeUSD")]
-    pub synth_mint_volume_usd: f64,
+    pub synth_mint_volume_usd: Decimal,
     #[serde(rename = "synthRedeemAverageSlip")]
-    pub synth_redeem_average_slip: f64,
+    pub synth_redeem_average_slip: Decimal,
     #[serde(rename = "synthRedeemCount")]
     pub synth_redeem_count: i64,
     #[serde(rename = "synthRedeemFees")]
-    pub synth_redeem_fees: i64,
+    pub synth_redeem_fees: Amount,
     #[serde(rename = "synthRedeemVolume")]
-    pub synth_redeem_volume: i64,
+    pub synth_redeem_volume: Amount,
     #[serde(rename = "synthRedeemVolumeUSD")]
-    pub synth_redeem_volume_usd: f64,
+    pub synth_redeem_volume_usd: Decimal,
     #[serde(rename = "toAssetAverageSlip")]
-    pub to_asset_average_slip: f64,
+    pub to_asset_average_slip: Decimal,
     #[serde(rename = "toAssetCount")]
     pub to_asset_count: i64,
     #[serde(rename = "toAssetFees")]
-    pub to_asset_fees: i64,
+    pub to_asset_fees: Amount,
     #[serde(rename = "toAssetVolume")]
-    pub to_asset_volume: i64,
+    pub to_asset_volume: Amount,
     #[serde(rename = "toAssetVolumeUSD")]
-    pub to_asset_volume_usd: f64,
+    pub to_asset_volume_usd: Decimal,
     #[serde(rename = "toRuneAverageSlip")]
-    pub to_rune_average_slip: f64,
+    pub to_rune_average_slip: Decimal,
     #[serde(rename = "toRuneCount")]
     pub to_rune_count: i64,
     #[serde(rename = "toRuneFees")]
-    pub to_rune_fees: i64,
+    pub to_rune_fees: Amount,
     #[serde(rename = "toRuneVolume")]
-    pub to_rune_volume: i64,
+    pub to_rune_volume: Amount,
     #[serde(rename = "toRuneVolumeUSD")]
-    pub to_rune_volume_usd: f64,
+    pub to_rune_volume_usd: Decimal,
     #[serde(rename = "totalCount")]
     pub total_count: i64,
     #[serde(rename = "totalFees")]
-    pub total_fees: i64,
+    pub total_fees: Amount,
     #[serde(rename = "totalVolume")]
-    pub total_volume: i64,
+    pub total_volume: Amount,
     #[serde(rename = "totalVolumeUSD")]
-    pub total_volume_usd: f64,
+    pub total_volume_usd: Decimal,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = SwapHistoryMetadata)]
 pub struct Metadata {
     #[serde(rename = "averageSlip")]
     pub average_slip: String,
@@ -0,0 +1,685 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use async_trait::async_trait;
+use chrono::Utc;
+use mongodb::bson::{self, Bson, Decimal128, Document};
+use crate::models::amount::{plain_notation, Amount};
+use crate::models::earnings_history::{EarningsHistory, PoolEarnings};
+use crate::models::ingestion_state::{Dataset, IngestionState, STATUS_RUNNING};
+use crate::repository::{merged_decimal, Accumulator, BatchCheckpoint, Buckets, CheckpointRepository, EarningsRepository, HistoryFilter, HistoryQuery, HistoryRecord, HistoryRepository, Merge, RepoResult, RepositoryError, SortKey, SortOrder, stored_document};
+
+/// Keeps records in process, keyed on `(pool, startTime)` like the Mongo unique indexes.
+pub struct MemoryRepository<T> {
+    records: Mutex<BTreeMap<(Option<String>, i64), T>>,
+    checkpoints: Arc<MemoryCheckpoints>,
+}
+
+impl<T> MemoryRepository<T> {
+    /// Batches advance checkpoints in `checkpoints`.
+    pub fn new(checkpoints: Arc<MemoryCheckpoints>) -> Self {
+        MemoryRepository { records: Mutex::new(BTreeMap::new()), checkpoints }
+    }
+}
+
+impl<T> Default for MemoryRepository<T> {
+    fn default() -> Self {
+        MemoryRepository::new(Arc::default())
+    }
+}
+
+impl<T: HistoryRecord> MemoryRepository<T> {
+    /// Matching records in `startTime` order.
+    fn matching(&self, filter: &HistoryFilter) -> Vec<T> {
+        let records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        let mut matching: Vec<T> = records.values().filter(|record| filter.matches(*record)).cloned().collect();
+        matching.sort_by_key(|record| record.start_time());
+        matching
+    }
+
+    /// Stored forms of the records `query` matches, grouped into its buckets if it has any.
+    fn documents(&self, query: &HistoryQuery) -> RepoResult<Vec<Document>> {
+        let documents = self.matching(&query.filter).iter().map(stored_document).collect::<RepoResult<_>>()?;
+        match query.buckets {
+            Some(buckets) => _bucketed(documents, buckets, T::MERGES),
+            None => Ok(documents),
+        }
+    }
+}
+
+impl MemoryRepository<EarningsHistory> {
+    /// The pools' rows of the intervals, or buckets, `query` matches, for its pool if it
+    /// names one.
+    fn pool_rows(&self, query: &HistoryQuery) -> RepoResult<Vec<Document>> {
+        let mut rows = Vec::new();
+        for earnings in self.matching(&query.filter) {
+            for pool in earnings.pools {
+                if query.filter.pool.as_ref().is_some_and(|wanted| *wanted != pool.pool) {
+                    continue;
+                }
+                let mut document = stored_document(&pool)?;
+                document.insert("startTime", earnings.start_time);
+                document.insert("endTime", earnings.end_time);
+                rows.push(document);
+            }
+        }
+
+        let Some(buckets) = query.buckets else {
+            return Ok(rows);
+        };
+        let template = stored_document(&PoolEarnings::default())?;
+        let merges: Vec<(&str, Merge)> = template.keys().filter(|field| *field != "pool").map(|field| (field.as_str(), Merge::Sum)).collect();
+        _bucketed(rows, Buckets { per_pool: true, ..buckets }, &merges)
+    }
+}
+
+fn _as_f64(value: &Bson) -> Option<f64> {
+    match value {
+        Bson::Int32(value) => Some(*value as f64),
+        Bson::Int64(value) => Some(*value as f64),
+        Bson::Double(value) => Some(*value),
+        Bson::Decimal128(value) => value.to_string().parse().ok(),
+        _ => None,
+    }
+}
+
+/// A number's sign and digits, so amounts past 2^53 compare exactly.
+#[derive(PartialEq, Eq)]
+struct Exact {
+    negative: bool,
+    whole: String,
+    fraction: String,
+}
+
+impl Exact {
+    fn of(value: &Bson) -> Option<Exact> {
+        let text = match value {
+            Bson::Int32(value) => value.to_string(),
+            Bson::Int64(value) => value.to_string(),
+            Bson::Double(value) if value.is_finite() => value.to_string(),
+            Bson::Decimal128(value) => plain_notation(&value.to_string()),
+            _ => return None,
+        };
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text.as_str()),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if !whole.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let (whole, fraction) = (whole.trim_start_matches('0'), fraction.trim_end_matches('0'));
+        Some(Exact { negative: negative && !(whole.is_empty() && fraction.is_empty()), whole: whole.to_string(), fraction: fraction.to_string() })
+    }
+}
+
+impl Ord for Exact {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let magnitude = self.whole.len().cmp(&other.whole.len()).then_with(|| self.whole.cmp(&other.whole)).then_with(|| self.fraction.cmp(&other.fraction));
+        match (self.negative, other.negative) {
+            (false, false) => magnitude,
+            (true, true) => magnitude.reverse(),
+            (negative, _) => if negative { Ordering::Less } else { Ordering::Greater },
+        }
+    }
+}
+
+impl PartialOrd for Exact {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Where Mongo sorts each BSON type among the others.
+fn _type_order(value: &Bson) -> u8 {
+    match value {
+        Bson::MinKey => 0,
+        Bson::Null | Bson::Undefined => 1,
+        Bson::Int32(_) | Bson::Int64(_) | Bson::Double(_) | Bson::Decimal128(_) => 2,
+        Bson::String(_) | Bson::Symbol(_) => 3,
+        Bson::Document(_) => 4,
+        Bson::Array(_) => 5,
+        Bson::Binary(_) => 6,
+        Bson::ObjectId(_) => 7,
+        Bson::Boolean(_) => 8,
+        Bson::DateTime(_) => 9,
+        Bson::Timestamp(_) => 10,
+        Bson::RegularExpression(_) => 11,
+        _ => 12,
+    }
+}
+
+/// Compares values the way Mongo sorts them.
+fn _compare(a: &Bson, b: &Bson) -> Ordering {
+    match (Exact::of(a), Exact::of(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        _ => match (a, b) {
+            (Bson::String(a), Bson::String(b)) => a.cmp(b),
+            (Bson::Boolean(a), Bson::Boolean(b)) => a.cmp(b),
+            (Bson::Double(a), Bson::Double(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+            _ => _type_order(a).cmp(&_type_order(b)),
+        },
+    }
+}
+
+/// Sorts documents by `sort`, keeping the existing order for ties.
+fn _sort_by_keys(documents: &mut [Document], sort: &[SortKey]) {
+    documents.sort_by(|a, b| {
+        for key in sort {
+            let ordering = _compare(a.get(&key.field).unwrap_or(&Bson::Null), b.get(&key.field).unwrap_or(&Bson::Null));
+            let ordering = if key.order == SortOrder::Asc { ordering } else { ordering.reverse() };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    });
+}
+
+/// Whether `document` sorts strictly after `values` under `sort`.
+fn _after(document: &Document, sort: &[SortKey], values: &[Bson]) -> bool {
+    for (key, value) in sort.iter().zip(values) {
+        let ordering = _compare(document.get(&key.field).unwrap_or(&Bson::Null), value);
+        let ordering = if key.order == SortOrder::Asc { ordering } else { ordering.reverse() };
+        if ordering != Ordering::Equal {
+            return ordering == Ordering::Greater;
+        }
+    }
+    false
+}
+
+/// Sorts `documents` and applies the query's keyset position, skip, limit and projection.
+pub fn find_in(mut documents: Vec<Document>, query: &HistoryQuery) -> Vec<Document> {
+    _sort_by_keys(&mut documents, &query.sort);
+    let projection = query.projection();
+    let documents = documents
+        .into_iter()
+        .filter(|document| query.after.as_ref().is_none_or(|values| _after(document, &query.sort, values)))
+        .skip(query.skip as usize)
+        .take(query.limit.filter(|limit| *limit > 0).map_or(usize::MAX, |limit| limit as usize));
+    match projection {
+        Some(projection) => documents.map(|document| document.into_iter().filter(|(field, _)| projection.contains(&field.as_str())).collect()).collect(),
+        None => documents.collect(),
+    }
+}
+
+/// Sums integers or amounts exactly, failing rather than saturating once the sum no
+/// longer fits.
+fn _exact_sum(field: &str, values: &[&Bson]) -> RepoResult<Bson> {
+    let overflow = || RepositoryError(format!("{} overflows when summed", field));
+    if !values.iter().any(|value| matches!(value, Bson::Decimal128(_))) {
+        let mut total = 0_i64;
+        for value in values {
+            let value = match value {
+                Bson::Int32(value) => *value as i64,
+                Bson::Int64(value) => *value,
+                _ => 0,
+            };
+            total = total.checked_add(value).ok_or_else(overflow)?;
+        }
+        return Ok(Bson::Int64(total));
+    }
+
+    let mut total = Amount::default();
+    for value in values {
+        total = total.checked_add(bson::from_bson((*value).clone())?).ok_or_else(overflow)?;
+    }
+    Ok(Bson::Decimal128(Decimal128::from_str(&total.to_string()).map_err(|e| RepositoryError(e.to_string()))?))
+}
+
+/// Each pool's rows embedded under `field`, summed, in the order the pools first appear.
+fn _merge_pools(hours: &[Document], field: &str) -> RepoResult<Bson> {
+    let mut pools: Vec<Vec<&Document>> = Vec::new();
+    for row in hours.iter().filter_map(|hour| hour.get_array(field).ok()).flatten() {
+        let Bson::Document(row) = row else {
+            continue;
+        };
+        match pools.iter_mut().find(|rows| rows[0].get("pool") == row.get("pool")) {
+            Some(rows) => rows.push(row),
+            None => pools.push(vec![row]),
+        }
+    }
+
+    let mut merged = Vec::with_capacity(pools.len());
+    for rows in pools {
+        let mut pool = rows[0].clone();
+        for (name, _) in rows[0].iter().filter(|(name, _)| *name != "pool") {
+            let values: Vec<&Bson> = rows.iter().filter_map(|row| row.get(name)).collect();
+            pool.insert(name, _exact_sum(name, &values)?);
+        }
+        merged.push(Bson::Document(pool));
+    }
+    Ok(Bson::Array(merged))
+}
+
+/// One bucket of `hours`, in time order, spanning `[start_time, end_time)`.
+fn _merge(hours: &[Document], start_time: i64, end_time: i64, merges: &[(&str, Merge)]) -> RepoResult<Document> {
+    let mut merged = hours[hours.len() - 1].clone();
+    merged.remove("_id");
+    merged.insert("startTime", start_time);
+    merged.insert("endTime", end_time);
+    for (field, merge) in merges {
+        let values: Vec<&Bson> = hours.iter().filter_map(|hour| hour.get(*field)).filter(|value| !matches!(value, Bson::Null)).collect();
+        let mean = || values.iter().filter_map(|value| _as_f64(value)).sum::<f64>() / values.len() as f64;
+        let value = match merge {
+            Merge::Sum => _exact_sum(field, &values)?,
+            Merge::SumDecimal => merged_decimal(values.iter().filter_map(|value| _as_f64(value)).sum()),
+            Merge::Mean => merged_decimal(mean()),
+            Merge::Weighted(weight) => {
+                let number = |hour: &Document, field: &str| hour.get(field).and_then(_as_f64).unwrap_or_default();
+                let total_weight: f64 = hours.iter().map(|hour| number(hour, weight)).sum();
+                if total_weight > 0.0 {
+                    merged_decimal(hours.iter().map(|hour| number(hour, field) * number(hour, weight)).sum::<f64>() / total_weight)
+                } else {
+                    merged_decimal(mean())
+                }
+            }
+            Merge::Pools => _merge_pools(hours, field)?,
+        };
+        merged.insert(*field, value);
+    }
+    Ok(merged)
+}
+
+/// Groups hourly `documents` into `buckets`, taking their hours in `startTime` and then
+/// pool order like the other backends.
+fn _bucketed(mut documents: Vec<Document>, buckets: Buckets, merges: &[(&str, Merge)]) -> RepoResult<Vec<Document>> {
+    let time_order = ["startTime", "pool"].map(|field| SortKey { field: field.to_string(), order: SortOrder::Asc });
+    _sort_by_keys(&mut documents, &time_order);
+
+    let mut groups: BTreeMap<(i64, Option<String>), Vec<Document>> = BTreeMap::new();
+    for document in documents {
+        let start_time = document.get_i64("startTime").unwrap_or_default();
+        let pool = document.get_str("pool").ok().filter(|_| buckets.per_pool).map(str::to_string);
+        groups.entry((buckets.interval.map_or(0, |interval| interval.start_of(start_time)), pool)).or_default().push(document);
+    }
+
+    groups
+        .into_iter()
+        .map(|((start_time, _), hours)| {
+            let (start_time, end_time) = match buckets.interval {
+                Some(interval) => (start_time, interval.step(start_time, 1)),
+                None => (
+                    hours[0].get_i64("startTime").unwrap_or_default(),
+                    hours.iter().filter_map(|hour| hour.get_i64("endTime").ok()).max().unwrap_or_default(),
+                ),
+            };
+            _merge(&hours, start_time, end_time, merges)
+        })
+        .collect()
+}
+
+/// Sums like `$sum`: exact for integral values, `Decimal128` in and out for amounts.
+fn _sum(values: &[&Bson]) -> Bson {
+    let decimal = values.iter().any(|value| matches!(value, Bson::Decimal128(_)));
+    let integral: Option<i128> = values
+        .iter()
+        .map(|value| match value {
+            Bson::Int32(value) => Some(*value as i128),
+            Bson::Int64(value) => Some(*value as i128),
+            Bson::Decimal128(value) => Amount::from_str(&value.to_string()).ok().map(|amount| amount.0),
+            _ => None,
+        })
+        .try_fold(0_i128, |total, value| value.and_then(|value| total.checked_add(value)));
+
+    match (integral, decimal) {
+        (Some(total), true) => Decimal128::from_str(&total.to_string()).map(Bson::Decimal128).unwrap_or(Bson::Null),
+        (Some(total), false) => Bson::Int64(total as i64),
+        (None, _) => _float(values.iter().filter_map(|value| _as_f64(value)).sum(), decimal),
+    }
+}
+
+fn _float(value: f64, decimal: bool) -> Bson {
+    if decimal {
+        Decimal128::from_str(&value.to_string()).map(Bson::Decimal128).unwrap_or(Bson::Double(value))
+    } else {
+        Bson::Double(value)
+    }
+}
+
+/// Sums `value * weight` like `$sum` of a `$multiply`, at `f64` precision.
+fn _sum_product(documents: &[Document], value: &str, weight: &str) -> Bson {
+    let mut decimal = false;
+    let mut total = 0.0;
+    for document in documents {
+        if let (Some(value), Some(weight)) = (document.get(value), document.get(weight)) {
+            decimal |= matches!(value, Bson::Decimal128(_)) || matches!(weight, Bson::Decimal128(_));
+            total += _as_f64(value).unwrap_or_default() * _as_f64(weight).unwrap_or_default();
+        }
+    }
+    _float(total, decimal)
+}
+
+fn _accumulate(accumulator: &Accumulator, documents: &[Document]) -> Bson {
+    let values = |field: &str| -> Vec<&Bson> {
+        documents
+            .iter()
+            .filter_map(|document| document.get(field))
+            .filter(|value| !matches!(value, Bson::Null))
+            .collect()
+    };
+
+    match accumulator {
+        Accumulator::Count => Bson::Int64(documents.len() as i64),
+        Accumulator::First(field) => documents.first().and_then(|document| document.get(*field)).cloned().unwrap_or(Bson::Null),
+        Accumulator::Last(field) => documents.last().and_then(|document| document.get(*field)).cloned().unwrap_or(Bson::Null),
+        Accumulator::Sum(field) => {
+            let values = values(field);
+            if values.is_empty() && documents.is_empty() {
+                Bson::Null
+            } else {
+                _sum(&values)
+            }
+        }
+        Accumulator::SumProduct(value, weight) if !documents.is_empty() => _sum_product(documents, value, weight),
+        Accumulator::SumProduct(..) => Bson::Null,
+        Accumulator::Min(field) => values(field).into_iter().min_by(|a, b| _compare(a, b)).cloned().unwrap_or(Bson::Null),
+        Accumulator::Max(field) => values(field).into_iter().max_by(|a, b| _compare(a, b)).cloned().unwrap_or(Bson::Null),
+    }
+}
+
+#[async_trait]
+impl<T: HistoryRecord> HistoryRepository<T> for MemoryRepository<T> {
+    async fn find_documents(&self, query: &HistoryQuery) -> RepoResult<Vec<Document>> {
+        Ok(find_in(self.documents(query)?, query))
+    }
+
+    async fn count(&self, query: &HistoryQuery) -> RepoResult<u64> {
+        Ok(self.documents(query)?.len() as u64)
+    }
+
+    async fn aggregate(&self, filter: &HistoryFilter, accumulators: &[Accumulator]) -> RepoResult<Vec<Bson>> {
+        let documents = self
+            .matching(filter)
+            .iter()
+            .map(stored_document)
+            .collect::<RepoResult<Vec<Document>>>()?;
+        Ok(accumulators.iter().map(|accumulator| _accumulate(accumulator, &documents)).collect())
+    }
+
+    /// The records stay locked until the checkpoint has moved too.
+    async fn upsert(&self, records: Vec<T>, checkpoint: Option<&BatchCheckpoint<'_>>) -> RepoResult<()> {
+        let mut stored = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        for record in records {
+            stored.insert((record.pool().map(|pool| pool.to_string()), record.start_time()), record);
+        }
+        if let Some(checkpoint) = checkpoint {
+            self.checkpoints.record_batch(checkpoint);
+        }
+        Ok(())
+    }
+
+    async fn pools(&self) -> RepoResult<Vec<String>> {
+        let records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        let pools: BTreeSet<String> = records.values().flat_map(|record| record.pool_names()).map(|pool| pool.to_string()).collect();
+        Ok(pools.into_iter().collect())
+    }
+
+    async fn intervals(&self, filter: &HistoryFilter) -> RepoResult<Vec<(i64, i64)>> {
+        Ok(self
+            .matching(filter)
+            .iter()
+            .map(|record| (record.start_time(), record.end_time()))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl EarningsRepository for MemoryRepository<EarningsHistory> {
+    async fn find_pool_rows(&self, query: &HistoryQuery) -> RepoResult<Vec<Document>> {
+        Ok(find_in(self.pool_rows(query)?, query))
+    }
+
+    async fn count_pool_rows(&self, query: &HistoryQuery) -> RepoResult<u64> {
+        Ok(self.pool_rows(query)?.len() as u64)
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryCheckpoints {
+    states: Mutex<HashMap<(Dataset, Option<String>), IngestionState>>,
+}
+
+impl MemoryCheckpoints {
+    fn update(&self, dataset: Dataset, pool: Option<&str>, apply: impl FnOnce(&mut IngestionState)) {
+        let mut states = self.states.lock().unwrap_or_else(|e| e.into_inner());
+        let state = states.entry((dataset, pool.map(|pool| pool.to_string()))).or_insert_with(|| IngestionState {
+            id: None,
+            dataset: dataset.name().to_string(),
+            pool: pool.map(|pool| pool.to_string()),
+            last_end_time: 0,
+            status: STATUS_RUNNING.to_string(),
+            error: None,
+            last_batch_rows: 0,
+            total_rows: 0,
+            updated_at: 0,
+        });
+        apply(state);
+        state.updated_at = Utc::now().timestamp();
+    }
+
+    fn record_batch(&self, checkpoint: &BatchCheckpoint<'_>) {
+        self.update(checkpoint.dataset, checkpoint.pool, |state| {
+            state.last_end_time = state.last_end_time.max(checkpoint.end_time);
+            state.total_rows += checkpoint.rows;
+            state.status = STATUS_RUNNING.to_string();
+            state.error = None;
+            state.last_batch_rows = checkpoint.rows;
+        });
+    }
+}
+
+#[async_trait]
+impl CheckpointRepository for MemoryCheckpoints {
+    async fn get(&self, dataset: Dataset, pool: Option<&str>) -> RepoResult<Option<IngestionState>> {
+        let states = self.states.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(states.get(&(dataset, pool.map(|pool| pool.to_string()))).cloned())
+    }
+
+    async fn record_status(&self, dataset: Dataset, pool: Option<&str>, status: &str, error: Option<String>) -> RepoResult<()> {
+        self.update(dataset, pool, |state| {
+            state.status = status.to_string();
+            state.error = error;
+        });
+        Ok(())
+    }
+
+    async fn list(&self) -> RepoResult<Vec<IngestionState>> {
+        let states = self.states.lock().unwrap_or_else(|e| e.into_inner());
+        let mut list: Vec<IngestionState> = states.values().cloned().collect();
+        list.sort_by(|a, b| (&a.dataset, &a.pool).cmp(&(&b.dataset, &b.pool)));
+        Ok(list)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use crate::models::amount::{Amount, Decimal};
+    use crate::models::depth_history::DepthHistory;
+    use crate::models::runepool_history::RunePoolHistory;
+    use crate::models::swaps_history::SwapHistory;
+    use crate::services::intervals::Interval;
+
+    fn _at(year: i32, month: u32, day: u32, hour: u32) -> i64 {
+        Utc.with_ymd_and_hms(year, month, day, hour, 0, 0).unwrap().timestamp()
+    }
+
+    fn _depth(pool: &str, start_time: i64, asset_depth: i128) -> DepthHistory {
+        DepthHistory { pool: pool.to_string(), start_time, end_time: start_time + 3600, asset_depth: Amount(asset_depth), ..DepthHistory::default() }
+    }
+
+    fn _bucketed(interval: Option<Interval>, per_pool: bool, sort: &[&str]) -> HistoryQuery {
+        HistoryQuery {
+            sort: sort.iter().map(|field| SortKey { field: field.to_string(), order: SortOrder::Asc }).collect(),
+            buckets: Some(Buckets { interval, per_pool }),
+            ..HistoryQuery::default()
+        }
+    }
+
+    fn _decimal(text: &str) -> Bson {
+        Bson::Decimal128(Decimal128::from_str(text).unwrap())
+    }
+
+    #[test]
+    fn compares_numbers_exactly_across_types() {
+        // 2^53 + 1 is the same `f64` as 2^53.
+        assert_eq!(_compare(&_decimal("9007199254740993"), &_decimal("9007199254740992")), Ordering::Greater);
+        assert_eq!(_compare(&Bson::Int64(9007199254740993), &_decimal("9007199254740992")), Ordering::Greater);
+        assert_eq!(_compare(&_decimal("100000000000000000001"), &_decimal("1.00000000000000000000E+20")), Ordering::Greater);
+        assert_eq!(_compare(&_decimal("2E+3"), &Bson::Int32(2000)), Ordering::Equal);
+        assert_eq!(_compare(&_decimal("-0.5"), &_decimal("-0.45")), Ordering::Less);
+        assert_eq!(_compare(&_decimal("0.00"), &_decimal("-0")), Ordering::Equal);
+        assert_eq!(_compare(&Bson::Double(1.5), &_decimal("1.25")), Ordering::Greater);
+    }
+
+    #[test]
+    fn orders_other_types_as_mongo_does() {
+        assert_eq!(_compare(&Bson::Null, &Bson::Int64(-1)), Ordering::Less);
+        assert_eq!(_compare(&Bson::Int64(5), &Bson::String("4".to_string())), Ordering::Less);
+        assert_eq!(_compare(&Bson::String("b".to_string()), &Bson::String("a".to_string())), Ordering::Greater);
+        assert_eq!(_compare(&Bson::Boolean(true), &Bson::String("z".to_string())), Ordering::Greater);
+    }
+
+    #[tokio::test]
+    async fn sorts_and_resumes_past_f64_precision() {
+        let repository = MemoryRepository::default();
+        let depths = ["9007199254740993", "9007199254740992", "9007199254740994"];
+        let rows = depths
+            .iter()
+            .enumerate()
+            .map(|(hour, depth)| DepthHistory {
+                pool: "BTC.BTC".to_string(),
+                start_time: hour as i64 * 3600,
+                end_time: hour as i64 * 3600 + 3600,
+                asset_depth: depth.parse().unwrap(),
+                ..DepthHistory::default()
+            })
+            .collect();
+        repository.upsert(rows, None).await.unwrap();
+
+        let sort = vec![SortKey { field: "assetDepth".to_string(), order: SortOrder::Asc }];
+        let query = HistoryQuery { sort, ..HistoryQuery::default() };
+        let depths = |rows: Vec<DepthHistory>| rows.iter().map(|row| row.asset_depth.to_string()).collect::<Vec<_>>();
+        assert_eq!(depths(repository.find(&query).await.unwrap()), ["9007199254740992", "9007199254740993", "9007199254740994"]);
+        let after = HistoryQuery { after: Some(vec![_decimal("9007199254740992")]), ..query };
+        assert_eq!(depths(repository.find(&after).await.unwrap()), ["9007199254740993", "9007199254740994"]);
+    }
+
+    #[tokio::test]
+    async fn levels_take_the_last_hour_per_pool() {
+        let repository = MemoryRepository::default();
+        let day = _at(2024, 3, 1, 0);
+        let rows = vec![_depth("BTC.BTC", day + 7200, 30), _depth("BTC.BTC", day, 10), _depth("ETH.ETH", day + 3600, 7), _depth("BTC.BTC", day + 86400, 40)];
+        repository.upsert(rows, None).await.unwrap();
+
+        let buckets: Vec<(String, i64, i64, i128)> = repository
+            .find(&_bucketed(Some(Interval::Day), true, &["pool", "startTime"]))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|row| (row.pool, row.start_time, row.end_time, row.asset_depth.0))
+            .collect();
+        assert_eq!(
+            buckets,
+            [
+                ("BTC.BTC".to_string(), day, day + 86400, 30),
+                ("BTC.BTC".to_string(), day + 86400, day + 2 * 86400, 40),
+                ("ETH.ETH".to_string(), day, day + 86400, 7),
+            ]
+        );
+
+        let repository = MemoryRepository::default();
+        let runepool = |start_time: i64, count: i64| RunePoolHistory { start_time, end_time: start_time + 3600, count, units: Amount(count as i128 * 10), ..RunePoolHistory::default() };
+        repository.upsert(vec![runepool(day + 3600, 5), runepool(day, 3)], None).await.unwrap();
+        let merged = repository.find(&_bucketed(Some(Interval::Month), true, &[])).await.unwrap();
+        assert_eq!(merged.len(), 1);
+        assert_eq!((merged[0].start_time, merged[0].end_time), (day, _at(2024, 4, 1, 0)));
+        assert_eq!((merged[0].count, merged[0].units), (5, Amount(50)));
+    }
+
+    #[tokio::test]
+    async fn swaps_add_up_and_weight_slips_by_volume() {
+        let swap = |start_time: i64, volume: i128, slip: &str| SwapHistory {
+            pool: "BTC.BTC".to_string(),
+            start_time,
+            end_time: start_time + 3600,
+            total_count: 2,
+            total_fees: Amount(volume / 100),
+            total_volume: Amount(volume),
+            average_slip: slip.parse().unwrap(),
+            to_rune_average_slip: slip.parse().unwrap(),
+            ..SwapHistory::default()
+        };
+        let repository = MemoryRepository::default();
+        let day = _at(2024, 3, 1, 0);
+        repository.upsert(vec![swap(day, 100, "1"), swap(day + 3600, 300, "5")], None).await.unwrap();
+        let merged = repository.find(&_bucketed(Some(Interval::Day), false, &[])).await.unwrap();
+
+        assert_eq!(merged.len(), 1);
+        assert_eq!((merged[0].total_count, merged[0].total_fees, merged[0].total_volume), (4, Amount(4), Amount(400)));
+        assert_eq!(merged[0].average_slip.to_string(), "4");
+        // No to-RUNE volume to weight by, so the plain mean.
+        assert_eq!(merged[0].to_rune_average_slip.to_string(), "3");
+    }
+
+    #[tokio::test]
+    async fn earnings_add_up_per_pool() {
+        let pool = |pool: &str, earnings: i128| PoolEarnings { pool: pool.to_string(), earnings: Amount(earnings), ..PoolEarnings::default() };
+        let hour = |start_time: i64, nodes: &str, price: &str, pools: Vec<PoolEarnings>| EarningsHistory {
+            start_time,
+            end_time: start_time + 3600,
+            earnings: Amount(pools.iter().map(|pool| pool.earnings.0).sum()),
+            avg_node_count: nodes.parse::<Decimal>().unwrap(),
+            rune_price_usd: price.parse().unwrap(),
+            pools,
+            ..EarningsHistory::default()
+        };
+        let repository = MemoryRepository::default();
+        let day = _at(2024, 3, 1, 0);
+        let rows = vec![
+            hour(day, "10", "1.5", vec![pool("BTC.BTC", 3), pool("ETH.ETH", 1)]),
+            hour(day + 3600, "20", "2.5", vec![pool("ETH.ETH", 4), pool("BNB.BNB", 2)]),
+        ];
+        repository.upsert(rows, None).await.unwrap();
+        let merged = repository.find(&_bucketed(Some(Interval::Week), false, &[])).await.unwrap();
+
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].earnings, Amount(10));
+        assert_eq!((merged[0].avg_node_count.to_string(), merged[0].rune_price_usd.to_string()), ("15".to_string(), "2.5".to_string()));
+        let pools: Vec<(&str, i128)> = merged[0].pools.iter().map(|pool| (pool.pool.as_str(), pool.earnings.0)).collect();
+        assert_eq!(pools, [("BTC.BTC", 3), ("ETH.ETH", 5), ("BNB.BNB", 2)]);
+    }
+
+    #[tokio::test]
+    async fn pages_and_counts_the_buckets_of_a_range() {
+        let repository = MemoryRepository::default();
+        let day = _at(2024, 3, 1, 0);
+        repository.upsert((0..72).map(|hour| _depth("BTC.BTC", day + hour * 3600, hour as i128)).collect(), None).await.unwrap();
+        // Only the hours in range are bucketed, so the last bucket ends past `to`.
+        let filter = HistoryFilter { from: Some(day), to: Some(day + 36 * 3600), ..HistoryFilter::default() };
+        let query = HistoryQuery {
+            filter,
+            sort: vec![SortKey { field: "startTime".to_string(), order: SortOrder::Desc }],
+            limit: Some(1),
+            buckets: Some(Buckets { interval: Some(Interval::Day), per_pool: true }),
+            ..HistoryQuery::default()
+        };
+
+        assert_eq!(repository.count(&query).await.unwrap(), 2);
+        let page = repository.find(&query).await.unwrap();
+        assert_eq!((page.len(), page[0].start_time, page[0].asset_depth), (1, day + 86400, Amount(35)));
+    }
+
+    #[tokio::test]
+    async fn sums_fail_rather_than_overflow() {
+        let swap = |start_time: i64| SwapHistory { start_time, end_time: start_time + 3600, total_count: i64::MAX, ..SwapHistory::default() };
+        let repository = MemoryRepository::default();
+        repository.upsert(vec![swap(0), swap(3600)], None).await.unwrap();
+
+        let error = repository.find(&_bucketed(None, false, &[])).await.unwrap_err();
+        assert!(error.to_string().contains("totalCount"), "{}", error);
+    }
+}
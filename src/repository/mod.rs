@@ -0,0 +1,498 @@
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use async_trait::async_trait;
+use mongodb::bson::{self, Bson, Decimal128, Document};
+use serde::{de::DeserializeOwned, Serialize};
+use crate::models::{amount::{Amount, Decimal}, depth_history::DepthHistory, earnings_history::EarningsHistory, ingestion_state::{Dataset, IngestionState}, runepool_history::RunePoolHistory, swaps_history::SwapHistory};
+use crate::services::intervals::Interval;
+
+pub mod memory;
+pub mod mongo;
+pub mod sql;
+
+#[derive(Debug)]
+pub struct RepositoryError(String);
+
+impl fmt::Display for RepositoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for RepositoryError {}
+
+impl From<mongodb::error::Error> for RepositoryError {
+    fn from(e: mongodb::error::Error) -> Self {
+        RepositoryError(e.to_string())
+    }
+}
+
+impl From<mongodb::bson::ser::Error> for RepositoryError {
+    fn from(e: mongodb::bson::ser::Error) -> Self {
+        RepositoryError(e.to_string())
+    }
+}
+
+impl From<mongodb::bson::de::Error> for RepositoryError {
+    fn from(e: mongodb::bson::de::Error) -> Self {
+        RepositoryError(e.to_string())
+    }
+}
+
+impl From<mongodb::bson::raw::Error> for RepositoryError {
+    fn from(e: mongodb::bson::raw::Error) -> Self {
+        RepositoryError(e.to_string())
+    }
+}
+
+impl From<sqlx::Error> for RepositoryError {
+    fn from(e: sqlx::Error) -> Self {
+        RepositoryError(e.to_string())
+    }
+}
+
+pub type RepoResult<T> = Result<T, RepositoryError>;
+
+/// A stored hourly interval, keyed on its pool (where it has one) and `startTime`.
+pub trait HistoryRecord: Serialize + DeserializeOwned + Clone + Send + Sync + Unpin + 'static {
+    /// Field a pool filter applies to, if the record can be filtered by pool.
+    const POOL_FIELD: Option<&'static str>;
+    /// How buckets combine the hours' fields. Unlisted fields take the last hour's value.
+    const MERGES: &'static [(&'static str, Merge)];
+
+    fn pool(&self) -> Option<&str>;
+    fn start_time(&self) -> i64;
+    fn end_time(&self) -> i64;
+
+    fn has_pool(&self, pool: &str) -> bool {
+        self.pool() == Some(pool)
+    }
+
+    /// Every pool the record covers, for records that embed several.
+    fn pool_names(&self) -> Vec<&str> {
+        self.pool().into_iter().collect()
+    }
+}
+
+/// How a record's `[startTime, endTime)` has to sit against a filter's `[from, to)`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RangeMatch {
+    /// `startTime >= from` and `endTime <= to`: the record lies wholly inside the range.
+    #[default]
+    Contained,
+    /// `endTime > from` and `startTime < to`: the record shares some time with the range.
+    Overlapping,
+}
+
+/// Records in `[from, to)`, contained in it or overlapping it, optionally for one pool.
+#[derive(Debug, Clone, Default)]
+pub struct HistoryFilter {
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub range: RangeMatch,
+    pub pool: Option<String>,
+}
+
+impl HistoryFilter {
+    pub fn matches<T: HistoryRecord>(&self, record: &T) -> bool {
+        let (start, end) = (record.start_time(), record.end_time());
+        let in_range = match self.range {
+            RangeMatch::Contained => self.from.is_none_or(|from| start >= from) && self.to.is_none_or(|to| end <= to),
+            RangeMatch::Overlapping => self.from.is_none_or(|from| end > from) && self.to.is_none_or(|to| start < to),
+        };
+        in_range
+            && match (&self.pool, T::POOL_FIELD) {
+                (Some(pool), Some(_)) => record.has_pool(pool),
+                _ => true,
+            }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Clone)]
+pub struct SortKey {
+    pub field: String,
+    pub order: SortOrder,
+}
+
+/// How a bucket combines one field of its hours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Merge {
+    /// Exact sum of integers or amounts; a sum that no longer fits is an error.
+    Sum,
+    /// Sum of decimals, rounded to 12 places.
+    SumDecimal,
+    /// Mean over the hours, rounded to 12 places.
+    Mean,
+    /// Mean weighted by the named amount field, or the plain mean when nothing carries weight.
+    Weighted(&'static str),
+    /// Embedded pool rows, each pool's amounts summed, in the order the pools first appear.
+    Pools,
+}
+
+/// Hourly records grouped into longer intervals, per pool or across pools.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Buckets {
+    /// `None` merges the whole range into one bucket, from its first `startTime` to its
+    /// last `endTime`.
+    pub interval: Option<Interval>,
+    pub per_pool: bool,
+}
+
+/// A filtered, sorted page of records.
+#[derive(Debug, Clone, Default)]
+pub struct HistoryQuery {
+    pub filter: HistoryFilter,
+    pub sort: Vec<SortKey>,
+    pub skip: u64,
+    pub limit: Option<i64>,
+    /// Keyset position: only records sorting strictly after these values, one per sort key.
+    pub after: Option<Vec<Bson>>,
+    /// The fields to read besides the sort keys, or `None` for whole records.
+    pub fields: Option<Vec<String>>,
+    /// Groups the hours `filter` matches before they are sorted and paged.
+    pub buckets: Option<Buckets>,
+}
+
+impl HistoryQuery {
+    /// `fields` and then any sort key they leave out, so a cursor can be taken from every
+    /// row read.
+    pub fn projection(&self) -> Option<Vec<&str>> {
+        let mut projection: Vec<&str> = self.fields.as_ref()?.iter().map(String::as_str).collect();
+        for key in &self.sort {
+            if !projection.contains(&key.field.as_str()) {
+                projection.push(&key.field);
+            }
+        }
+        Some(projection)
+    }
+}
+
+/// An aggregate over the records matching a filter, in `startTime` and then pool order.
+#[derive(Debug, Clone, Copy)]
+pub enum Accumulator {
+    Count,
+    First(&'static str),
+    Last(&'static str),
+    Sum(&'static str),
+    /// The sum of `value * weight`, for weighted averages.
+    SumProduct(&'static str, &'static str),
+    Min(&'static str),
+    Max(&'static str),
+}
+
+/// Stored form of a value, so sorting, aggregating and cursors see what Mongo would.
+pub fn stored_document<T: Serialize>(value: &T) -> RepoResult<Document> {
+    // The raw serializer is the one the driver writes with, so it is not human readable.
+    Ok(Document::try_from(bson::to_raw_document_buf(value)?.as_ref())?)
+}
+
+/// Renders an aggregate value the way the API writes numbers, null as zero.
+pub fn aggregate_string(value: &Bson) -> String {
+    match value {
+        Bson::Int32(value) => value.to_string(),
+        Bson::Int64(value) => value.to_string(),
+        Bson::Double(value) => Decimal::from_f64(*value).to_string(),
+        Bson::Decimal128(value) => {
+            let text = value.to_string();
+            match text.parse::<Amount>() {
+                Ok(amount) => amount.to_string(),
+                Err(_) if text.contains(['E', 'e']) => Decimal::from_f64(text.parse().unwrap_or_default()).to_string(),
+                Err(_) => text,
+            }
+        }
+        Bson::String(value) => value.clone(),
+        _ => "0".to_string(),
+    }
+}
+
+/// A merged decimal as every backend writes it: rounded to 12 places like
+/// `Decimal::from_f64`.
+pub fn merged_decimal(value: f64) -> Bson {
+    Decimal128::from_str(&Decimal::from_f64(value).to_string()).map_or(Bson::Null, Bson::Decimal128)
+}
+
+/// Attaches per-pool buckets of embedded rows under `field` of the buckets they belong to,
+/// in the order their pools first appear.
+pub fn embed_buckets(documents: &mut [Document], rows: Vec<Document>, field: &str, buckets: Buckets) {
+    // A whole-range bucket holds every row; others the rows starting with them.
+    let key = |document: &Document| buckets.interval.map_or(0, |_| document.get_i64("startTime").unwrap_or_default());
+    let mut grouped: HashMap<i64, Vec<Document>> = HashMap::new();
+    for row in rows {
+        grouped.entry(key(&row)).or_default().push(row);
+    }
+    for document in documents.iter_mut() {
+        let mut embedded = grouped.remove(&key(document)).unwrap_or_default();
+        embedded.sort_by_key(|row| row.get_i64("position").unwrap_or_default());
+        let embedded: Vec<Bson> = embedded
+            .into_iter()
+            .map(|mut row| {
+                for owned in ["startTime", "endTime", "position"] {
+                    row.remove(owned);
+                }
+                Bson::Document(row)
+            })
+            .collect();
+        document.insert(field, embedded);
+    }
+}
+
+/// Where a fetched batch leaves its dataset's checkpoint. `end_time` never moves the
+/// checkpoint backwards.
+pub struct BatchCheckpoint<'a> {
+    pub dataset: Dataset,
+    pub pool: Option<&'a str>,
+    pub end_time: i64,
+    pub rows: i64,
+}
+
+#[async_trait]
+pub trait HistoryRepository<T: HistoryRecord>: Send + Sync {
+    /// Matching records in their stored form, holding only the query's `projection`.
+    async fn find_documents(&self, query: &HistoryQuery) -> RepoResult<Vec<Document>>;
+
+    async fn find(&self, query: &HistoryQuery) -> RepoResult<Vec<T>> {
+        let query = HistoryQuery { fields: None, ..query.clone() };
+        let documents = self.find_documents(&query).await?;
+        Ok(documents.into_iter().map(bson::from_document).collect::<Result<_, _>>()?)
+    }
+
+    /// Number of records, or buckets, `find_documents` would return for `query` without
+    /// its cursor, skip and limit.
+    async fn count(&self, query: &HistoryQuery) -> RepoResult<u64>;
+
+    /// One value per accumulator over the records matching `filter`, `Null` (or 0 for `Count`)
+    /// when nothing matches.
+    async fn aggregate(&self, filter: &HistoryFilter, accumulators: &[Accumulator]) -> RepoResult<Vec<Bson>>;
+
+    /// Inserts or replaces each record on its natural key. With a `checkpoint`, the records
+    /// and the checkpoint they reach are committed together or not at all.
+    async fn upsert(&self, records: Vec<T>, checkpoint: Option<&BatchCheckpoint<'_>>) -> RepoResult<()>;
+
+    /// Distinct pools with stored records, including pools embedded under `POOL_FIELD`, sorted.
+    async fn pools(&self) -> RepoResult<Vec<String>>;
+
+    /// `(startTime, endTime)` of every matching record in `startTime` order.
+    async fn intervals(&self, filter: &HistoryFilter) -> RepoResult<Vec<(i64, i64)>>;
+}
+
+#[async_trait]
+pub trait EarningsRepository: HistoryRepository<EarningsHistory> {
+    /// One row per pool per interval: the pool's fields beside the interval's times.
+    async fn find_pool_rows(&self, query: &HistoryQuery) -> RepoResult<Vec<Document>>;
+
+    /// Number of rows `find_pool_rows` would return for `query` without its cursor, skip
+    /// and limit.
+    async fn count_pool_rows(&self, query: &HistoryQuery) -> RepoResult<u64>;
+}
+
+#[async_trait]
+pub trait CheckpointRepository: Send + Sync {
+    async fn get(&self, dataset: Dataset, pool: Option<&str>) -> RepoResult<Option<IngestionState>>;
+
+    async fn record_status(&self, dataset: Dataset, pool: Option<&str>, status: &str, error: Option<String>) -> RepoResult<()>;
+
+    async fn list(&self) -> RepoResult<Vec<IngestionState>>;
+}
+
+/// Every store the API and ingestion work against.
+#[derive(Clone)]
+pub struct Repositories {
+    pub depth: Arc<dyn HistoryRepository<DepthHistory>>,
+    pub swaps: Arc<dyn HistoryRepository<SwapHistory>>,
+    pub runepool: Arc<dyn HistoryRepository<RunePoolHistory>>,
+    pub earnings: Arc<dyn EarningsRepository>,
+    pub checkpoints: Arc<dyn CheckpointRepository>,
+}
+
+impl Repositories {
+    /// Selects the store with `STORAGE`: `mongo` (the default), `sql` at `DATABASE_URL`, or
+    /// `memory`.
+    pub async fn from_env() -> Result<Self, Box<dyn Error>> {
+        match env::var("STORAGE").as_deref().unwrap_or("mongo") {
+            "mongo" => Ok(Repositories::mongo(&crate::db::connection::get_db().await?)),
+            "sql" => {
+                let url = env::var("DATABASE_URL").map_err(|_| "STORAGE=sql requires DATABASE_URL")?;
+                let (pool, dialect) = crate::db::sql::connect(&url).await?;
+                Ok(Repositories::sql(pool, dialect))
+            }
+            "memory" => Ok(Repositories::in_memory()),
+            other => Err(format!("Unknown STORAGE {:?}, expected mongo, sql or memory", other).into()),
+        }
+    }
+
+    pub fn mongo(client: &mongodb::Client) -> Self {
+        let database = client.database("historical_db");
+        Repositories {
+            depth: Arc::new(mongo::MongoRepository::new(database.collection(Dataset::Depth.collection()))),
+            swaps: Arc::new(mongo::MongoRepository::new(database.collection(Dataset::Swaps.collection()))),
+            runepool: Arc::new(mongo::MongoRepository::new(database.collection(Dataset::RunePool.collection()))),
+            earnings: Arc::new(mongo::MongoRepository::new(database.collection(Dataset::Earnings.collection()))),
+            checkpoints: Arc::new(mongo::MongoCheckpoints::new(database.collection("ingestion_state"))),
+        }
+    }
+
+    pub fn sql(pool: sqlx::AnyPool, dialect: crate::db::sql::Dialect) -> Self {
+        Repositories {
+            depth: Arc::new(sql::SqlRepository::new(pool.clone(), dialect)),
+            swaps: Arc::new(sql::SqlRepository::new(pool.clone(), dialect)),
+            runepool: Arc::new(sql::SqlRepository::new(pool.clone(), dialect)),
+            earnings: Arc::new(sql::SqlRepository::new(pool.clone(), dialect)),
+            checkpoints: Arc::new(sql::SqlCheckpoints::new(pool)),
+        }
+    }
+
+    pub fn in_memory() -> Self {
+        let checkpoints = Arc::new(memory::MemoryCheckpoints::default());
+        Repositories {
+            depth: Arc::new(memory::MemoryRepository::new(checkpoints.clone())),
+            swaps: Arc::new(memory::MemoryRepository::new(checkpoints.clone())),
+            runepool: Arc::new(memory::MemoryRepository::new(checkpoints.clone())),
+            earnings: Arc::new(memory::MemoryRepository::new(checkpoints.clone())),
+            checkpoints,
+        }
+    }
+
+    /// Dataset-agnostic access for tools that walk every dataset.
+    pub async fn pools(&self, dataset: Dataset) -> RepoResult<Vec<String>> {
+        match dataset {
+            Dataset::Depth => self.depth.pools().await,
+            Dataset::Swaps => self.swaps.pools().await,
+            Dataset::RunePool => self.runepool.pools().await,
+            Dataset::Earnings => self.earnings.pools().await,
+        }
+    }
+
+    pub async fn intervals(&self, dataset: Dataset, filter: &HistoryFilter) -> RepoResult<Vec<(i64, i64)>> {
+        match dataset {
+            Dataset::Depth => self.depth.intervals(filter).await,
+            Dataset::Swaps => self.swaps.intervals(filter).await,
+            Dataset::RunePool => self.runepool.intervals(filter).await,
+            Dataset::Earnings => self.earnings.intervals(filter).await,
+        }
+    }
+
+    pub async fn aggregate(&self, dataset: Dataset, filter: &HistoryFilter, accumulators: &[Accumulator]) -> RepoResult<Vec<Bson>> {
+        match dataset {
+            Dataset::Depth => self.depth.aggregate(filter, accumulators).await,
+            Dataset::Swaps => self.swaps.aggregate(filter, accumulators).await,
+            Dataset::RunePool => self.runepool.aggregate(filter, accumulators).await,
+            Dataset::Earnings => self.earnings.aggregate(filter, accumulators).await,
+        }
+    }
+}
+
+/// Depths, units, member counts and prices are levels, so a bucket takes its last hour's.
+impl HistoryRecord for DepthHistory {
+    const POOL_FIELD: Option<&'static str> = Some("pool");
+    const MERGES: &'static [(&'static str, Merge)] = &[];
+
+    fn pool(&self) -> Option<&str> {
+        Some(&self.pool)
+    }
+    fn start_time(&self) -> i64 {
+        self.start_time
+    }
+    fn end_time(&self) -> i64 {
+        self.end_time
+    }
+}
+
+/// Counts, fees and volumes add up; slips are weighted by their direction's volume and
+/// the overall slip and RUNE price by the total volume.
+impl HistoryRecord for SwapHistory {
+    const POOL_FIELD: Option<&'static str> = Some("pool");
+    const MERGES: &'static [(&'static str, Merge)] = &[
+        ("averageSlip", Merge::Weighted("totalVolume")),
+        ("runePriceUSD", Merge::Weighted("totalVolume")),
+        ("synthMintAverageSlip", Merge::Weighted("synthMintVolume")),
+        ("synthMintCount", Merge::Sum),
+        ("synthMintFees", Merge::Sum),
+        ("synthMintVolume", Merge::Sum),
+        ("synthMintVolumeUSD", Merge::SumDecimal),
+        ("synthRedeemAverageSlip", Merge::Weighted("synthRedeemVolume")),
+        ("synthRedeemCount", Merge::Sum),
+        ("synthRedeemFees", Merge::Sum),
+        ("synthRedeemVolume", Merge::Sum),
+        ("synthRedeemVolumeUSD", Merge::SumDecimal),
+        ("toAssetAverageSlip", Merge::Weighted("toAssetVolume")),
+        ("toAssetCount", Merge::Sum),
+        ("toAssetFees", Merge::Sum),
+        ("toAssetVolume", Merge::Sum),
+        ("toAssetVolumeUSD", Merge::SumDecimal),
+        ("toRuneAverageSlip", Merge::Weighted("toRuneVolume")),
+        ("toRuneCount", Merge::Sum),
+        ("toRuneFees", Merge::Sum),
+        ("toRuneVolume", Merge::Sum),
+        ("toRuneVolumeUSD", Merge::SumDecimal),
+        ("totalCount", Merge::Sum),
+        ("totalFees", Merge::Sum),
+        ("totalVolume", Merge::Sum),
+        ("totalVolumeUSD", Merge::SumDecimal),
+    ];
+
+    fn pool(&self) -> Option<&str> {
+        Some(&self.pool)
+    }
+    fn start_time(&self) -> i64 {
+        self.start_time
+    }
+    fn end_time(&self) -> i64 {
+        self.end_time
+    }
+}
+
+impl HistoryRecord for RunePoolHistory {
+    const POOL_FIELD: Option<&'static str> = None;
+    const MERGES: &'static [(&'static str, Merge)] = &[];
+
+    fn pool(&self) -> Option<&str> {
+        None
+    }
+    fn start_time(&self) -> i64 {
+        self.start_time
+    }
+    fn end_time(&self) -> i64 {
+        self.end_time
+    }
+}
+
+/// Earnings add up, per pool as well; the node count is averaged over the hours and the
+/// RUNE price is the last hour's.
+impl HistoryRecord for EarningsHistory {
+    const POOL_FIELD: Option<&'static str> = Some("pools.pool");
+    const MERGES: &'static [(&'static str, Merge)] = &[
+        ("avgNodeCount", Merge::Mean),
+        ("blockRewards", Merge::Sum),
+        ("bondingEarnings", Merge::Sum),
+        ("earnings", Merge::Sum),
+        ("liquidityEarnings", Merge::Sum),
+        ("liquidityFees", Merge::Sum),
+        ("pools", Merge::Pools),
+    ];
+
+    fn pool(&self) -> Option<&str> {
+        None
+    }
+    fn start_time(&self) -> i64 {
+        self.start_time
+    }
+    fn end_time(&self) -> i64 {
+        self.end_time
+    }
+    fn has_pool(&self, pool: &str) -> bool {
+        self.pools.iter().any(|earnings| earnings.pool == pool)
+    }
+    fn pool_names(&self) -> Vec<&str> {
+        self.pools.iter().map(|earnings| earnings.pool.as_str()).collect()
+    }
+}
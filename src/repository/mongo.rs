@@ -0,0 +1,488 @@
+use std::str::FromStr;
+use async_trait::async_trait;
+use futures::StreamExt;
+use mongodb::bson::{doc, Bson, Decimal128, Document};
+use mongodb::Collection;
+use crate::db::ingestion_state::{_get_checkpoint, _record_batch, _record_status};
+use crate::models::amount::plain_notation;
+use crate::models::earnings_history::{EarningsHistory, PoolEarnings};
+use crate::models::ingestion_state::{Dataset, IngestionState};
+use crate::repository::{embed_buckets, stored_document, Accumulator, BatchCheckpoint, Buckets, CheckpointRepository, EarningsRepository, HistoryFilter, HistoryQuery, HistoryRecord, HistoryRepository, Merge, RangeMatch, RepoResult, RepositoryError, SortKey, SortOrder};
+use crate::services::intervals::Interval;
+
+pub struct MongoRepository<T: Send + Sync> {
+    collection: Collection<T>,
+    checkpoints: Collection<IngestionState>,
+}
+
+impl<T: Send + Sync> MongoRepository<T> {
+    /// Batches advance checkpoints in the `ingestion_state` collection of the same database.
+    pub fn new(collection: Collection<T>) -> Self {
+        let checkpoints = collection.client().database(&collection.namespace().db).collection("ingestion_state");
+        MongoRepository { collection, checkpoints }
+    }
+}
+
+impl<T: HistoryRecord> MongoRepository<T> {
+    /// Stages grouping the hours `filter` matches into `buckets`, in no particular order.
+    fn bucket_pipeline(&self, filter: &HistoryFilter, buckets: Buckets) -> Vec<Document> {
+        let mut pipeline = vec![doc! { "$match": _filter_document::<T>(filter) }, doc! { "$sort": { "startTime": 1, "pool": 1 } }];
+        pipeline.extend(_bucket_stages(buckets, buckets.per_pool && T::POOL_FIELD == Some("pool"), T::MERGES, false));
+        pipeline
+    }
+}
+
+fn _filter_document<T: HistoryRecord>(filter: &HistoryFilter) -> Document {
+    let mut conditions = Vec::new();
+    let ((from_field, from_operator), (to_field, to_operator)) = match filter.range {
+        RangeMatch::Contained => (("startTime", "$gte"), ("endTime", "$lte")),
+        RangeMatch::Overlapping => (("endTime", "$gt"), ("startTime", "$lt")),
+    };
+    if let Some(from) = filter.from {
+        conditions.push(doc! { from_field: { from_operator: from } });
+    }
+    if let Some(to) = filter.to {
+        conditions.push(doc! { to_field: { to_operator: to } });
+    }
+    if let (Some(pool), Some(field)) = (&filter.pool, T::POOL_FIELD) {
+        conditions.push(doc! { field: pool });
+    }
+
+    if conditions.is_empty() {
+        doc! {}
+    } else {
+        doc! { "$and": conditions }
+    }
+}
+
+fn _sort_document(sort: &[SortKey]) -> Document {
+    let mut sort_doc = Document::new();
+    for key in sort {
+        sort_doc.insert(&key.field, if key.order == SortOrder::Asc { 1 } else { -1 });
+    }
+    sort_doc
+}
+
+/// Records sorting strictly after `values` under `sort`.
+fn _after_document(sort: &[SortKey], values: &[Bson]) -> Document {
+    let keys: Vec<(&SortKey, &Bson)> = sort.iter().zip(values).collect();
+    let mut branches = Vec::with_capacity(keys.len());
+    for (index, (key, value)) in keys.iter().enumerate() {
+        let mut branch = Document::new();
+        for (previous, value) in &keys[..index] {
+            branch.insert(&previous.field, (*value).clone());
+        }
+        let operator = if key.order == SortOrder::Asc { "$gt" } else { "$lt" };
+        branch.insert(&key.field, doc! { operator: (*value).clone() });
+        branches.push(branch);
+    }
+
+    if branches.is_empty() {
+        doc! {}
+    } else {
+        doc! { "$or": branches }
+    }
+}
+
+fn _query_document<T: HistoryRecord>(query: &HistoryQuery) -> Document {
+    let filter = _filter_document::<T>(&query.filter);
+    match &query.after {
+        Some(values) => doc! { "$and": [filter, _after_document(&query.sort, values)] },
+        None => filter,
+    }
+}
+
+fn _projection_document(query: &HistoryQuery) -> Option<Document> {
+    let mut projection = doc! { "_id": 0 };
+    for field in query.projection()? {
+        projection.insert(field, 1);
+    }
+    Some(projection)
+}
+
+/// Keyset position, sort, skip and limit stages of `query`, then its projection if `project`.
+fn _page_stages(query: &HistoryQuery, project: bool) -> Vec<Document> {
+    let mut stages = Vec::new();
+    if let Some(values) = &query.after {
+        stages.push(doc! { "$match": _after_document(&query.sort, values) });
+    }
+    if !query.sort.is_empty() {
+        stages.push(doc! { "$sort": _sort_document(&query.sort) });
+    }
+    stages.push(doc! { "$skip": query.skip as i64 });
+    if let Some(limit) = query.limit {
+        stages.push(doc! { "$limit": limit });
+    }
+    if let Some(projection) = _projection_document(query).filter(|_| project) {
+        stages.push(doc! { "$project": projection });
+    }
+    stages
+}
+
+fn _unit(interval: Interval) -> &'static str {
+    match interval {
+        Interval::Hour => "hour",
+        Interval::Day => "day",
+        Interval::Week => "week",
+        Interval::Month => "month",
+        Interval::Quarter => "quarter",
+        Interval::Year => "year",
+    }
+}
+
+/// Start, in seconds, of the `interval` bucket holding `startTime`.
+fn _bucket_start(interval: Interval) -> Document {
+    let mut truncate = doc! { "date": { "$toDate": { "$multiply": ["$startTime", 1000_i64] } }, "unit": _unit(interval) };
+    if interval == Interval::Week {
+        truncate.insert("startOfWeek", "monday");
+    }
+    doc! { "$toLong": { "$divide": [{ "$toLong": { "$dateTrunc": truncate } }, 1000_i64] } }
+}
+
+/// End, in seconds, of the `interval` bucket starting at `start`.
+fn _bucket_end(interval: Interval, start: &str) -> Document {
+    let end = doc! { "$dateAdd": { "startDate": { "$toDate": { "$multiply": [start, 1000_i64] } }, "unit": _unit(interval), "amount": 1 } };
+    doc! { "$toLong": { "$divide": [{ "$toLong": end }, 1000_i64] } }
+}
+
+/// Stages grouping hours, already in time order, into `buckets`, per `pool` when
+/// `per_pool`. With `ordered`, each bucket's `position` is the least `appearance` of its hours.
+fn _bucket_stages(buckets: Buckets, per_pool: bool, merges: &[(&str, Merge)], ordered: bool) -> Vec<Document> {
+    let start = buckets.interval.map_or(Bson::Null, |interval| Bson::Document(_bucket_start(interval)));
+    let pool = if per_pool { Bson::String("$pool".to_string()) } else { Bson::Null };
+    let mut group = doc! {
+        "_id": { "start": start, "pool": pool },
+        "last": { "$last": "$$ROOT" },
+        "startTime": { "$min": "$startTime" },
+        "endTime": { "$max": "$endTime" },
+    };
+    let mut merged = match buckets.interval {
+        Some(interval) => doc! { "startTime": "$_id.start", "endTime": _bucket_end(interval, "$_id.start") },
+        None => doc! { "startTime": "$startTime", "endTime": "$endTime" },
+    };
+    if ordered {
+        group.insert("position", doc! { "$min": "$appearance" });
+        merged.insert("position", "$position");
+    }
+
+    for (field, merge) in merges {
+        let value = format!("${}", field);
+        let rounded = |expression: Bson| doc! { "$round": [expression, 12] };
+        match merge {
+            Merge::Sum => {
+                group.insert(*field, doc! { "$sum": value.clone() });
+                merged.insert(*field, value);
+            }
+            Merge::SumDecimal => {
+                group.insert(*field, doc! { "$sum": value.clone() });
+                merged.insert(*field, rounded(value.into()));
+            }
+            Merge::Mean => {
+                group.insert(*field, doc! { "$avg": value.clone() });
+                merged.insert(*field, rounded(value.into()));
+            }
+            Merge::Weighted(weight) => {
+                let (weights, mean) = (format!("{}__weight", field), format!("{}__mean", field));
+                group.insert(*field, doc! { "$sum": { "$multiply": [value.clone(), format!("${}", weight)] } });
+                group.insert(&weights, doc! { "$sum": format!("${}", weight) });
+                group.insert(&mean, doc! { "$avg": value.clone() });
+                let weighted = doc! {
+                    "$cond": [{ "$gt": [format!("${}", weights), 0] }, { "$divide": [value, format!("${}", weights)] }, format!("${}", mean)]
+                };
+                merged.insert(*field, rounded(weighted.into()));
+            }
+            // Embedded rows are bucketed by `_embedded_bucket_stages` and attached afterwards.
+            Merge::Pools => {
+                merged.insert(*field, Bson::Array(Vec::new()));
+            }
+        }
+    }
+
+    vec![doc! { "$group": group }, doc! { "$replaceWith": { "$mergeObjects": ["$last", merged] } }, doc! { "$unset": "_id" }]
+}
+
+/// Stages turning matched earnings into per-pool buckets of the rows they embed under
+/// `field`, for `pool` if given, each pool's amounts summed.
+fn _embedded_bucket_stages(field: &str, pool: Option<&str>, buckets: Buckets) -> RepoResult<Vec<Document>> {
+    let template = stored_document(&PoolEarnings::default())?;
+    let merges: Vec<(&str, Merge)> = template.keys().filter(|field| *field != "pool").map(|field| (field.as_str(), Merge::Sum)).collect();
+    let embedded = format!("${}", field);
+    let mut stages = vec![
+        doc! { "$unwind": { "path": embedded.clone(), "includeArrayIndex": "position" } },
+        doc! { "$replaceWith": { "$mergeObjects": [embedded, { "startTime": "$startTime", "endTime": "$endTime", "position": "$position" }] } },
+    ];
+    if let Some(pool) = pool {
+        stages.push(doc! { "$match": { "pool": pool } });
+    }
+    stages.push(doc! { "$setWindowFields": { "sortBy": { "startTime": 1, "position": 1 }, "output": { "appearance": { "$documentNumber": {} } } } });
+    stages.extend(_bucket_stages(buckets, true, &merges, true));
+    Ok(stages)
+}
+
+/// `$round` keeps the zeros it pads to, which the other backends trim.
+fn _trim_decimals(document: &mut Document, merges: &[(&str, Merge)]) {
+    for (field, merge) in merges {
+        if !matches!(merge, Merge::SumDecimal | Merge::Mean | Merge::Weighted(_)) {
+            continue;
+        }
+        let Some(Bson::Decimal128(value)) = document.get(*field) else {
+            continue;
+        };
+        let text = plain_notation(&value.to_string());
+        let trimmed = if text.contains('.') { text.trim_end_matches('0').trim_end_matches('.') } else { text.as_str() };
+        if let Ok(value) = Decimal128::from_str(if trimmed == "-0" { "0" } else { trimmed }) {
+            document.insert(*field, value);
+        }
+    }
+}
+
+/// Number of documents `pipeline` ends with.
+async fn _count(collection: &Collection<Document>, mut pipeline: Vec<Document>) -> RepoResult<u64> {
+    pipeline.push(doc! { "$count": "rows" });
+    let mut cursor = collection.aggregate(pipeline).allow_disk_use(true).await?;
+    let result = match cursor.next().await {
+        Some(result) => result?,
+        None => return Ok(0),
+    };
+    // `$count` gives an int, or a long once it no longer fits.
+    Ok(match result.get("rows") {
+        Some(Bson::Int32(rows)) => *rows as u64,
+        Some(Bson::Int64(rows)) => *rows as u64,
+        _ => 0,
+    })
+}
+
+async fn _documents(collection: &Collection<Document>, pipeline: Vec<Document>) -> RepoResult<Vec<Document>> {
+    let mut cursor = collection.aggregate(pipeline).allow_disk_use(true).await?;
+    let mut documents = Vec::new();
+    while let Some(document) = cursor.next().await {
+        documents.push(document?);
+    }
+    Ok(documents)
+}
+
+fn _accumulator_expression(accumulator: &Accumulator) -> Document {
+    match accumulator {
+        Accumulator::Count => doc! { "$sum": 1_i64 },
+        Accumulator::First(field) => doc! { "$first": format!("${}", field) },
+        Accumulator::Last(field) => doc! { "$last": format!("${}", field) },
+        Accumulator::Sum(field) => doc! { "$sum": format!("${}", field) },
+        Accumulator::SumProduct(value, weight) => doc! { "$sum": { "$multiply": [format!("${}", value), format!("${}", weight)] } },
+        Accumulator::Min(field) => doc! { "$min": format!("${}", field) },
+        Accumulator::Max(field) => doc! { "$max": format!("${}", field) },
+    }
+}
+
+/// `update` reports failed writes in its reply rather than as a command error.
+fn _check_write(reply: Document) -> RepoResult<()> {
+    match (reply.get_array("writeErrors"), reply.get_document("writeConcernError")) {
+        (Ok(errors), _) if !errors.is_empty() => Err(RepositoryError(format!("upsert failed: {:?}", errors[0]))),
+        (_, Ok(error)) => Err(RepositoryError(format!("upsert failed: {}", error))),
+        _ => Ok(()),
+    }
+}
+
+#[async_trait]
+impl<T: HistoryRecord> HistoryRepository<T> for MongoRepository<T> {
+    /// Rows the buckets embed are bucketed by a second pipeline and attached afterwards.
+    async fn find_documents(&self, query: &HistoryQuery) -> RepoResult<Vec<Document>> {
+        let collection = self.collection.clone_with_type::<Document>();
+        if let Some(buckets) = query.buckets {
+            let embedded = T::MERGES.iter().find(|(_, merge)| *merge == Merge::Pools).map(|(field, _)| *field);
+            let mut pipeline = self.bucket_pipeline(&query.filter, buckets);
+            pipeline.extend(_page_stages(query, embedded.is_none()));
+            let mut documents = _documents(&collection, pipeline).await?;
+            for document in documents.iter_mut() {
+                _trim_decimals(document, T::MERGES);
+            }
+
+            if let Some(field) = embedded {
+                let mut pipeline = vec![doc! { "$match": _filter_document::<T>(&query.filter) }];
+                pipeline.extend(_embedded_bucket_stages(field, None, buckets)?);
+                embed_buckets(&mut documents, _documents(&collection, pipeline).await?, field, buckets);
+                if let Some(projection) = query.projection() {
+                    for document in documents.iter_mut() {
+                        *document = std::mem::take(document).into_iter().filter(|(field, _)| projection.contains(&field.as_str())).collect();
+                    }
+                }
+            }
+            return Ok(documents);
+        }
+
+        let mut find = collection
+            .find(_query_document::<T>(query))
+            .sort(_sort_document(&query.sort))
+            .skip(query.skip)
+            .limit(query.limit.unwrap_or(0));
+        if let Some(projection) = _projection_document(query) {
+            find = find.projection(projection);
+        }
+
+        let mut cursor = find.await?;
+        let mut documents = Vec::new();
+        while let Some(document) = cursor.next().await {
+            documents.push(document?);
+        }
+        Ok(documents)
+    }
+
+    async fn count(&self, query: &HistoryQuery) -> RepoResult<u64> {
+        match query.buckets {
+            Some(buckets) => _count(&self.collection.clone_with_type(), self.bucket_pipeline(&query.filter, buckets)).await,
+            None => Ok(self.collection.count_documents(_filter_document::<T>(&query.filter)).await?),
+        }
+    }
+
+    async fn aggregate(&self, filter: &HistoryFilter, accumulators: &[Accumulator]) -> RepoResult<Vec<Bson>> {
+        let mut group = doc! { "_id": Bson::Null };
+        for (index, accumulator) in accumulators.iter().enumerate() {
+            group.insert(format!("a{}", index), _accumulator_expression(accumulator));
+        }
+        let pipeline = vec![
+            doc! { "$match": _filter_document::<T>(filter) },
+            doc! { "$sort": { "startTime": 1, "pool": 1 } },
+            doc! { "$group": group },
+        ];
+
+        let mut cursor = self.collection.clone_with_type::<Document>().aggregate(pipeline).allow_disk_use(true).await?;
+        let result = match cursor.next().await {
+            Some(result) => Some(result?),
+            None => None,
+        };
+
+        Ok(accumulators
+            .iter()
+            .enumerate()
+            .map(|(index, accumulator)| match (&result, accumulator) {
+                (Some(result), Accumulator::Count) => Bson::Int64(result.get_i64(format!("a{}", index)).unwrap_or(0)),
+                (None, Accumulator::Count) => Bson::Int64(0),
+                (Some(result), _) => result.get(format!("a{}", index)).cloned().unwrap_or(Bson::Null),
+                (None, _) => Bson::Null,
+            })
+            .collect())
+    }
+
+    /// One `update` command; with a checkpoint, in a transaction, which needs a replica set.
+    async fn upsert(&self, records: Vec<T>, checkpoint: Option<&BatchCheckpoint<'_>>) -> RepoResult<()> {
+        let mut updates = Vec::with_capacity(records.len());
+        for record in &records {
+            let mut filter = doc! { "startTime": record.start_time() };
+            if let Some(pool) = record.pool() {
+                filter.insert("pool", pool);
+            }
+            updates.push(doc! { "q": filter, "u": stored_document(record)?, "upsert": true });
+        }
+        let command = doc! { "update": self.collection.name(), "updates": updates, "ordered": true };
+        let database = self.collection.client().database(&self.collection.namespace().db);
+
+        let Some(checkpoint) = checkpoint else {
+            if !records.is_empty() {
+                _check_write(database.run_command(command).await?)?;
+            }
+            return Ok(());
+        };
+        let mut session = self.collection.client().start_session().await?;
+        session.start_transaction().await?;
+        if !records.is_empty() {
+            _check_write(database.run_command(command).session(&mut session).await?)?;
+        }
+        _record_batch(&self.checkpoints, checkpoint.dataset.name(), checkpoint.pool, checkpoint.end_time, checkpoint.rows, &mut session).await?;
+        session.commit_transaction().await?;
+        Ok(())
+    }
+
+    async fn pools(&self) -> RepoResult<Vec<String>> {
+        let Some(field) = T::POOL_FIELD else {
+            return Ok(Vec::new());
+        };
+        let mut pools: Vec<String> = self
+            .collection
+            .distinct(field, doc! {})
+            .await?
+            .into_iter()
+            .filter_map(|pool| pool.as_str().map(|pool| pool.to_string()))
+            .collect();
+        pools.sort();
+        Ok(pools)
+    }
+
+    async fn intervals(&self, filter: &HistoryFilter) -> RepoResult<Vec<(i64, i64)>> {
+        let mut cursor = self
+            .collection
+            .clone_with_type::<Document>()
+            .find(_filter_document::<T>(filter))
+            .sort(doc! { "startTime": 1 })
+            .projection(doc! { "_id": 0, "startTime": 1, "endTime": 1 })
+            .await?;
+
+        let mut intervals = Vec::new();
+        while let Some(interval) = cursor.next().await {
+            let interval = interval?;
+            intervals.push((interval.get_i64("startTime").unwrap_or_default(), interval.get_i64("endTime").unwrap_or_default()));
+        }
+        Ok(intervals)
+    }
+}
+
+#[async_trait]
+impl EarningsRepository for MongoRepository<EarningsHistory> {
+    async fn find_pool_rows(&self, query: &HistoryQuery) -> RepoResult<Vec<Document>> {
+        let mut pipeline = self.pool_row_pipeline(query)?;
+        pipeline.extend(_page_stages(query, true));
+        _documents(&self.collection.clone_with_type(), pipeline).await
+    }
+
+    async fn count_pool_rows(&self, query: &HistoryQuery) -> RepoResult<u64> {
+        _count(&self.collection.clone_with_type(), self.pool_row_pipeline(query)?).await
+    }
+}
+
+impl MongoRepository<EarningsHistory> {
+    /// Stages producing every pool row of the matching intervals, or of their buckets.
+    fn pool_row_pipeline(&self, query: &HistoryQuery) -> RepoResult<Vec<Document>> {
+        let filter = &query.filter;
+        let mut pipeline = vec![doc! { "$match": _filter_document::<EarningsHistory>(filter) }];
+        if let Some(buckets) = query.buckets {
+            pipeline.extend(_embedded_bucket_stages("pools", filter.pool.as_deref(), buckets)?);
+            pipeline.push(doc! { "$unset": "position" });
+            return Ok(pipeline);
+        }
+
+        pipeline.push(doc! { "$unwind": "$pools" });
+        pipeline.push(doc! { "$replaceWith": { "$mergeObjects": ["$pools", { "startTime": "$startTime", "endTime": "$endTime" }] } });
+        if let Some(pool) = &filter.pool {
+            pipeline.push(doc! { "$match": { "pool": pool } });
+        }
+        Ok(pipeline)
+    }
+}
+
+pub struct MongoCheckpoints {
+    collection: Collection<IngestionState>,
+}
+
+impl MongoCheckpoints {
+    pub fn new(collection: Collection<IngestionState>) -> Self {
+        MongoCheckpoints { collection }
+    }
+}
+
+#[async_trait]
+impl CheckpointRepository for MongoCheckpoints {
+    async fn get(&self, dataset: Dataset, pool: Option<&str>) -> RepoResult<Option<IngestionState>> {
+        Ok(_get_checkpoint(&self.collection, dataset.name(), pool).await?)
+    }
+
+    async fn record_status(&self, dataset: Dataset, pool: Option<&str>, status: &str, error: Option<String>) -> RepoResult<()> {
+        Ok(_record_status(&self.collection, dataset.name(), pool, status, error).await?)
+    }
+
+    async fn list(&self) -> RepoResult<Vec<IngestionState>> {
+        let mut cursor = self.collection.find(doc! {}).sort(doc! { "dataset": 1, "pool": 1 }).await?;
+        let mut states = Vec::new();
+        while let Some(state) = cursor.next().await {
+            states.push(state?);
+        }
+        Ok(states)
+    }
+}
@@ -0,0 +1,1361 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::str::FromStr;
+use async_trait::async_trait;
+use chrono::Utc;
+use mongodb::bson::{self, Bson, Decimal128, Document};
+use sqlx::any::{AnyArguments, AnyRow};
+use sqlx::query::Query;
+use sqlx::{Any, AnyPool, Row};
+use crate::db::sql::Dialect;
+use crate::models::amount::Amount;
+use crate::models::depth_history::DepthHistory;
+use crate::models::earnings_history::EarningsHistory;
+use crate::models::ingestion_state::{Dataset, IngestionState, STATUS_RUNNING};
+use crate::models::runepool_history::RunePoolHistory;
+use crate::models::swaps_history::SwapHistory;
+use crate::repository::memory::find_in;
+use crate::repository::{embed_buckets, merged_decimal, Accumulator, BatchCheckpoint, Buckets, CheckpointRepository, EarningsRepository, HistoryFilter, HistoryQuery, HistoryRecord, HistoryRepository, Merge, RangeMatch, RepoResult, RepositoryError, SortKey, SortOrder};
+use crate::services::intervals::Interval;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Integer,
+    Text,
+    /// An `Amount`: NUMERIC(39, 0) in Postgres, decimal text in SQLite.
+    Amount,
+    /// A `Decimal`: NUMERIC in Postgres, decimal text in SQLite.
+    Decimal,
+}
+
+/// How a record type maps onto its table of snake_case columns.
+pub struct Table {
+    pub name: &'static str,
+    pub fields: &'static [(&'static str, Kind)],
+    /// Condition selecting rows for one pool, with `{}` standing for the bound pool.
+    pub pool_filter: Option<&'static str>,
+    /// Field each record embeds its child rows under, and the table holding them, keyed
+    /// by the owner's `start_time`.
+    pub children: Option<(&'static str, &'static Table)>,
+}
+
+impl Table {
+    fn kind(&self, field: &str) -> Option<Kind> {
+        self.fields.iter().find(|(name, _)| *name == field).map(|(_, kind)| *kind)
+    }
+
+    fn key(&self) -> &'static str {
+        if self.kind("pool").is_some() {
+            "pool, start_time"
+        } else {
+            "start_time"
+        }
+    }
+}
+
+/// `startTime` -> `start_time`, `assetPriceUSD` -> `asset_price_usd`.
+fn _column(field: &str) -> String {
+    let mut column = String::with_capacity(field.len() + 4);
+    let mut previous_lower = false;
+    for c in field.chars() {
+        if c.is_ascii_uppercase() && previous_lower {
+            column.push('_');
+        }
+        previous_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+        column.push(c.to_ascii_lowercase());
+    }
+    column
+}
+
+pub static DEPTH_TABLE: Table = Table {
+    name: "depth_history",
+    fields: &[
+        ("pool", Kind::Text),
+        ("startTime", Kind::Integer),
+        ("endTime", Kind::Integer),
+        ("assetDepth", Kind::Amount),
+        ("assetPrice", Kind::Decimal),
+        ("assetPriceUSD", Kind::Decimal),
+        ("liquidityUnits", Kind::Amount),
+        ("membersCount", Kind::Integer),
+        ("runeDepth", Kind::Amount),
+        ("synthSupply", Kind::Amount),
+        ("synthUnits", Kind::Amount),
+        ("units", Kind::Amount),
+        ("luvi", Kind::Decimal),
+    ],
+    pool_filter: Some("pool = {}"),
+    children: None,
+};
+
+pub static SWAPS_TABLE: Table = Table {
+    name: "swaps_history",
+    fields: &[
+        ("pool", Kind::Text),
+        ("startTime", Kind::Integer),
+        ("endTime", Kind::Integer),
+        ("averageSlip", Kind::Decimal),
+        ("runePriceUSD", Kind::Decimal),
+        ("synthMintAverageSlip", Kind::Decimal),
+        ("synthMintCount", Kind::Integer),
+        ("synthMintFees", Kind::Amount),
+        ("synthMintVolume", Kind::Amount),
+        ("synthMintVolumeUSD", Kind::Decimal),
+        ("synthRedeemAverageSlip", Kind::Decimal),
+        ("synthRedeemCount", Kind::Integer),
+        ("synthRedeemFees", Kind::Amount),
+        ("synthRedeemVolume", Kind::Amount),
+        ("synthRedeemVolumeUSD", Kind::Decimal),
+        ("toAssetAverageSlip", Kind::Decimal),
+        ("toAssetCount", Kind::Integer),
+        ("toAssetFees", Kind::Amount),
+        ("toAssetVolume", Kind::Amount),
+        ("toAssetVolumeUSD", Kind::Decimal),
+        ("toRuneAverageSlip", Kind::Decimal),
+        ("toRuneCount", Kind::Integer),
+        ("toRuneFees", Kind::Amount),
+        ("toRuneVolume", Kind::Amount),
+        ("toRuneVolumeUSD", Kind::Decimal),
+        ("totalCount", Kind::Integer),
+        ("totalFees", Kind::Amount),
+        ("totalVolume", Kind::Amount),
+        ("totalVolumeUSD", Kind::Decimal),
+    ],
+    pool_filter: Some("pool = {}"),
+    children: None,
+};
+
+pub static RUNEPOOL_TABLE: Table = Table {
+    name: "runepool_history",
+    fields: &[
+        ("startTime", Kind::Integer),
+        ("endTime", Kind::Integer),
+        ("count", Kind::Integer),
+        ("units", Kind::Amount),
+    ],
+    pool_filter: None,
+    children: None,
+};
+
+pub static POOL_EARNINGS_TABLE: Table = Table {
+    name: "pool_earnings",
+    fields: &[
+        ("pool", Kind::Text),
+        ("startTime", Kind::Integer),
+        ("endTime", Kind::Integer),
+        ("position", Kind::Integer),
+        ("assetLiquidityFees", Kind::Amount),
+        ("earnings", Kind::Amount),
+        ("rewards", Kind::Amount),
+        ("runeLiquidityFees", Kind::Amount),
+        ("saverEarning", Kind::Amount),
+        ("totalLiquidityFeesRune", Kind::Amount),
+    ],
+    pool_filter: Some("pool = {}"),
+    children: None,
+};
+
+pub static EARNINGS_TABLE: Table = Table {
+    name: "earnings_history",
+    fields: &[
+        ("startTime", Kind::Integer),
+        ("endTime", Kind::Integer),
+        ("avgNodeCount", Kind::Decimal),
+        ("blockRewards", Kind::Amount),
+        ("bondingEarnings", Kind::Amount),
+        ("earnings", Kind::Amount),
+        ("liquidityEarnings", Kind::Amount),
+        ("liquidityFees", Kind::Amount),
+        ("runePriceUSD", Kind::Decimal),
+    ],
+    pool_filter: Some(
+        "EXISTS (SELECT 1 FROM pool_earnings WHERE pool_earnings.start_time = earnings_history.start_time AND pool_earnings.pool = {})",
+    ),
+    children: Some(("pools", &POOL_EARNINGS_TABLE)),
+};
+
+/// A record stored as one row of `TABLE`, plus rows of `TABLE.children` where it has them.
+pub trait SqlRecord: HistoryRecord {
+    const TABLE: &'static Table;
+
+    /// The record's child rows, as documents holding the child table's fields.
+    fn child_rows(&self) -> RepoResult<Vec<Document>> {
+        Ok(Vec::new())
+    }
+}
+
+impl SqlRecord for DepthHistory {
+    const TABLE: &'static Table = &DEPTH_TABLE;
+}
+
+impl SqlRecord for SwapHistory {
+    const TABLE: &'static Table = &SWAPS_TABLE;
+}
+
+impl SqlRecord for RunePoolHistory {
+    const TABLE: &'static Table = &RUNEPOOL_TABLE;
+}
+
+impl SqlRecord for EarningsHistory {
+    const TABLE: &'static Table = &EARNINGS_TABLE;
+
+    fn child_rows(&self) -> RepoResult<Vec<Document>> {
+        let mut rows = Vec::with_capacity(self.pools.len());
+        for (position, pool) in self.pools.iter().enumerate() {
+            let mut row = bson::to_document(pool)?;
+            row.insert("startTime", self.start_time);
+            row.insert("endTime", self.end_time);
+            row.insert("position", position as i64);
+            rows.push(row);
+        }
+        Ok(rows)
+    }
+}
+
+/// Bound values of a statement under construction, numbered `$1`, `$2`, ...
+#[derive(Default)]
+struct Arguments(Vec<Bson>);
+
+impl Arguments {
+    fn push(&mut self, value: impl Into<Bson>) -> String {
+        self.0.push(value.into());
+        format!("${}", self.0.len())
+    }
+
+    fn bind<'q>(&self, sql: &'q str) -> Query<'q, Any, AnyArguments<'q>> {
+        let mut query = sqlx::query(sql);
+        for value in &self.0 {
+            query = match value {
+                Bson::Int64(value) => query.bind(*value),
+                Bson::Int32(value) => query.bind(*value as i64),
+                Bson::String(value) => query.bind(value.clone()),
+                Bson::Null => query.bind(None::<String>),
+                other => query.bind(other.to_string()),
+            };
+        }
+        query
+    }
+}
+
+/// Expression writing a value of `kind` bound at `placeholder`.
+fn _value(dialect: Dialect, kind: Kind, placeholder: &str) -> String {
+    match (dialect, kind) {
+        (Dialect::Postgres, Kind::Amount | Kind::Decimal) => format!("CAST({} AS NUMERIC)", placeholder),
+        _ => placeholder.to_string(),
+    }
+}
+
+/// Expression reading `column` in a form the `Any` driver can decode.
+fn _select(dialect: Dialect, kind: Kind, column: &str) -> String {
+    match (dialect, kind) {
+        (Dialect::Postgres, Kind::Amount | Kind::Decimal) => format!("CAST({} AS TEXT)", column),
+        _ => column.to_string(),
+    }
+}
+
+/// Terms ordering `expression` numerically, each with whether it runs against the key's
+/// direction. SQLite amounts are text, so they order by sign, length and then digits.
+fn _order_terms(dialect: Dialect, kind: Kind, expression: &str) -> Vec<(String, bool)> {
+    match (dialect, kind) {
+        (Dialect::Sqlite, Kind::Amount) => {
+            let negative = format!("substr({}, 1, 1) = '-'", expression);
+            vec![
+                (format!("CASE WHEN {0} THEN -length({1}) ELSE length({1}) END", negative, expression), false),
+                (format!("CASE WHEN {} THEN NULL ELSE {} END", negative, expression), false),
+                (format!("CASE WHEN {} THEN {} END", negative, expression), true),
+            ]
+        }
+        (Dialect::Sqlite, Kind::Decimal) => vec![(format!("CAST({} AS REAL)", expression), false)],
+        _ => vec![(expression.to_string(), false)],
+    }
+}
+
+/// `ORDER BY` terms sorting `column` in `order`.
+fn _order(dialect: Dialect, kind: Kind, column: &str, order: SortOrder) -> Vec<String> {
+    _order_terms(dialect, kind, column)
+        .into_iter()
+        .map(|(term, reversed)| {
+            let ascending = (order == SortOrder::Asc) != reversed;
+            format!("{} {}", term, if ascending { "ASC" } else { "DESC" })
+        })
+        .collect()
+}
+
+/// `ORDER BY` terms for time order, then pool where rows have one, like Mongo's `$sort`
+/// before `$first` / `$last`.
+fn _time_order(table: &Table, direction: &str) -> String {
+    match table.kind("pool") {
+        Some(_) => format!("start_time {0}, pool {0}", direction),
+        None => format!("start_time {}", direction),
+    }
+}
+
+fn _unmapped(table: &Table, field: &str) -> RepositoryError {
+    RepositoryError(format!("{} has no column for sort field {:?}", table.name, field))
+}
+
+fn _where(table: &Table, filter: &HistoryFilter, arguments: &mut Arguments) -> String {
+    let mut conditions = Vec::new();
+    let (from_condition, to_condition) = match filter.range {
+        RangeMatch::Contained => ("start_time >=", "end_time <="),
+        RangeMatch::Overlapping => ("end_time >", "start_time <"),
+    };
+    if let Some(from) = filter.from {
+        conditions.push(format!("{} {}", from_condition, arguments.push(from)));
+    }
+    if let Some(to) = filter.to {
+        conditions.push(format!("{} {}", to_condition, arguments.push(to)));
+    }
+    if let (Some(pool), Some(pool_filter)) = (&filter.pool, table.pool_filter) {
+        conditions.push(pool_filter.replace("{}", &arguments.push(pool.as_str())));
+    }
+
+    if conditions.is_empty() {
+        String::new()
+    } else {
+        format!(" WHERE {}", conditions.join(" AND "))
+    }
+}
+
+fn _order_by(dialect: Dialect, table: &Table, sort: &[SortKey]) -> RepoResult<String> {
+    let mut terms = Vec::new();
+    for key in sort {
+        let kind = table.kind(&key.field).ok_or_else(|| _unmapped(table, &key.field))?;
+        terms.extend(_order(dialect, kind, &_column(&key.field), key.order));
+    }
+
+    Ok(if terms.is_empty() { String::new() } else { format!(" ORDER BY {}", terms.join(", ")) })
+}
+
+/// Keyset condition for rows sorting strictly after `values`, one per sort key.
+fn _after(dialect: Dialect, table: &Table, sort: &[SortKey], values: &[Bson], arguments: &mut Arguments) -> RepoResult<Option<String>> {
+    let mut terms: Vec<(String, String, &str)> = Vec::new();
+    for (key, value) in sort.iter().zip(values) {
+        let kind = table.kind(&key.field).ok_or_else(|| _unmapped(table, &key.field))?;
+        // Cursors hold amounts as `Decimal128`; compare them in their stored text.
+        let value = match kind {
+            Kind::Amount => Bson::String(bson::from_bson::<Amount>(value.clone())?.to_string()),
+            _ => value.clone(),
+        };
+        let placeholder = _value(dialect, kind, &arguments.push(value));
+        let columns = _order_terms(dialect, kind, &_column(&key.field));
+        for ((column, reversed), (bound, _)) in columns.into_iter().zip(_order_terms(dialect, kind, &placeholder)) {
+            let ascending = (key.order == SortOrder::Asc) != reversed;
+            terms.push((column, bound, if ascending { ">" } else { "<" }));
+        }
+    }
+
+    // SQLite's amount terms are null for one sign, so equality has to hold between nulls.
+    let equals = if dialect == Dialect::Sqlite { "IS" } else { "=" };
+    let branches: Vec<String> = (0..terms.len())
+        .map(|index| {
+            let mut conditions: Vec<String> = terms[..index].iter().map(|(column, value, _)| format!("{} {} {}", column, equals, value)).collect();
+            let (column, value, operator) = &terms[index];
+            conditions.push(format!("{} {} {}", column, operator, value));
+            format!("({})", conditions.join(" AND "))
+        })
+        .collect();
+    Ok((!branches.is_empty()).then(|| format!("({})", branches.join(" OR "))))
+}
+
+fn _limit(dialect: Dialect, skip: u64, limit: Option<i64>) -> String {
+    match (limit.filter(|limit| *limit > 0), skip) {
+        (Some(limit), 0) => format!(" LIMIT {}", limit),
+        (Some(limit), skip) => format!(" LIMIT {} OFFSET {}", limit, skip),
+        (None, 0) => String::new(),
+        // SQLite only takes an OFFSET after a LIMIT.
+        (None, skip) if dialect == Dialect::Sqlite => format!(" LIMIT -1 OFFSET {}", skip),
+        (None, skip) => format!(" OFFSET {}", skip),
+    }
+}
+
+fn _select_list(dialect: Dialect, fields: &[(&'static str, Kind)]) -> String {
+    fields
+        .iter()
+        .map(|(field, kind)| _select(dialect, *kind, &_column(field)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Reads a row selected with `_select_list` back into the serialized field names, in the
+/// stored form Mongo would hold.
+fn _row_document(fields: &[(&'static str, Kind)], row: &AnyRow) -> RepoResult<Document> {
+    let mut document = Document::new();
+    for (index, (field, kind)) in fields.iter().enumerate() {
+        document.insert(*field, _stored_value(*kind, row, index)?);
+    }
+    Ok(document)
+}
+
+/// A child row as its owner embeds it, without the owner's times or its stored position.
+fn _embedded(mut row: Document) -> Document {
+    for field in ["startTime", "endTime", "position"] {
+        row.remove(field);
+    }
+    row
+}
+
+/// How an aggregate subquery's value is read back.
+enum Output {
+    Value(Kind),
+    Real,
+    /// Summed outside the database; the subquery is a placeholder.
+    ExactSum(String),
+}
+
+/// Converts a selected value or aggregate to the BSON type Mongo would have returned.
+fn _stored_value(kind: Kind, row: &AnyRow, index: usize) -> RepoResult<Bson> {
+    Ok(match kind {
+        Kind::Integer => row.try_get::<Option<i64>, _>(index)?.map_or(Bson::Null, Bson::Int64),
+        Kind::Text => row.try_get::<Option<String>, _>(index)?.map_or(Bson::Null, Bson::String),
+        Kind::Amount | Kind::Decimal => match row.try_get::<Option<String>, _>(index)? {
+            Some(value) => Bson::Decimal128(Decimal128::from_str(&value).map_err(|e| RepositoryError(e.to_string()))?),
+            None => Bson::Null,
+        },
+    })
+}
+
+/// Start of the bucket holding each row's `start_time`, or one bucket for the whole range.
+fn _bucket_start(dialect: Dialect, interval: Option<Interval>) -> String {
+    let unit = match interval {
+        None => return "0".to_string(),
+        Some(Interval::Hour) => return "start_time - start_time % 3600".to_string(),
+        Some(Interval::Day) => return "start_time - start_time % 86400".to_string(),
+        // 1970-01-01 was a Thursday.
+        Some(Interval::Week) => return "start_time - start_time % 86400 - (start_time / 86400 + 3) % 7 * 86400".to_string(),
+        Some(Interval::Month) => "month",
+        Some(Interval::Quarter) => "quarter",
+        Some(Interval::Year) => "year",
+    };
+    match (dialect, interval) {
+        (Dialect::Sqlite, Some(Interval::Quarter)) => "CAST(strftime('%s', start_time, 'unixepoch', 'start of month', \
+             '-' || ((CAST(strftime('%m', start_time, 'unixepoch') AS INTEGER) - 1) % 3) || ' months') AS INTEGER)"
+            .to_string(),
+        (Dialect::Sqlite, _) => format!("CAST(strftime('%s', start_time, 'unixepoch', 'start of {}') AS INTEGER)", unit),
+        (Dialect::Postgres, _) => {
+            format!("CAST(EXTRACT(EPOCH FROM date_trunc('{}', to_timestamp(start_time) AT TIME ZONE 'UTC')) AS BIGINT)", unit)
+        }
+    }
+}
+
+/// End of the `interval` bucket starting at `bucket`.
+fn _bucket_end(dialect: Dialect, interval: Interval, bucket: &str) -> String {
+    let months = match interval {
+        Interval::Hour => return format!("{} + 3600", bucket),
+        Interval::Day => return format!("{} + 86400", bucket),
+        Interval::Week => return format!("{} + 604800", bucket),
+        Interval::Month => 1,
+        Interval::Quarter => 3,
+        Interval::Year => 12,
+    };
+    match dialect {
+        Dialect::Sqlite => format!("CAST(strftime('%s', {}, 'unixepoch', '+{} months') AS INTEGER)", bucket, months),
+        Dialect::Postgres => format!(
+            "CAST(EXTRACT(EPOCH FROM (to_timestamp({}) AT TIME ZONE 'UTC') + INTERVAL '{} months') AS BIGINT)",
+            bucket, months
+        ),
+    }
+}
+
+/// SQLite sums an amount as the sums of its 12-digit chunks, enough for 39 digits.
+const CHUNKS: i64 = 4;
+const CHUNK: i128 = 1_000_000_000_000;
+
+/// How a bucket's field is read back from the columns `_grouped` selects for it.
+#[derive(Debug, Clone, Copy)]
+enum Part {
+    Value(Kind),
+    /// A computed decimal, rounded as every backend rounds them.
+    Real,
+    /// The sum of `value * weight`, the total weight and the plain mean.
+    Weighted,
+    /// An exact amount sum, as text.
+    Amount,
+    /// The sums of an amount's chunks, lowest first, which only fit together here.
+    Chunks,
+}
+
+impl Part {
+    fn columns(self) -> usize {
+        match self {
+            Part::Weighted => 3,
+            Part::Chunks => CHUNKS as usize,
+            _ => 1,
+        }
+    }
+}
+
+/// Groups `table`'s rows under `condition` into `buckets`, returning the query and how
+/// each field is read back.
+fn _grouped(dialect: Dialect, table: &Table, merges: &[(&str, Merge)], buckets: Buckets, condition: &str) -> (String, Vec<(&'static str, Part)>) {
+    let real = |column: &str| match dialect {
+        Dialect::Sqlite => format!("CAST({} AS REAL)", column),
+        Dialect::Postgres => format!("CAST({} AS DOUBLE PRECISION)", column),
+    };
+    let per_pool = buckets.per_pool && table.kind("pool").is_some();
+    let mut parts = Vec::with_capacity(table.fields.len());
+    let mut columns = Vec::with_capacity(table.fields.len());
+    for (field, kind) in table.fields {
+        let column = _column(field);
+        let merge = merges.iter().find(|(name, _)| name == field).map(|(_, merge)| *merge);
+        let (part, selected) = match (*field, merge) {
+            ("pool", _) if per_pool => (Part::Value(Kind::Text), vec![column]),
+            ("startTime", _) if buckets.interval.is_some() => (Part::Value(Kind::Integer), vec!["bucket".to_string()]),
+            ("startTime", _) => (Part::Value(Kind::Integer), vec!["MIN(start_time)".to_string()]),
+            ("endTime", _) => match buckets.interval {
+                Some(interval) => (Part::Value(Kind::Integer), vec![_bucket_end(dialect, interval, "bucket")]),
+                None => (Part::Value(Kind::Integer), vec!["MAX(end_time)".to_string()]),
+            },
+            ("position", _) => (Part::Value(Kind::Integer), vec!["MIN(appearance)".to_string()]),
+            (_, Some(Merge::Sum)) => match (kind, dialect) {
+                (Kind::Integer, Dialect::Sqlite) => (Part::Value(Kind::Integer), vec![format!("SUM({})", column)]),
+                (Kind::Integer, Dialect::Postgres) => (Part::Value(Kind::Integer), vec![format!("CAST(SUM({}) AS BIGINT)", column)]),
+                (_, Dialect::Sqlite) => {
+                    let sign = format!("CASE WHEN substr({}, 1, 1) = '-' THEN -1 ELSE 1 END", column);
+                    let chunks = (1..=CHUNKS)
+                        .map(|chunk| format!("SUM({} * CAST(substr(ltrim({}, '-'), {}, 12) AS INTEGER))", sign, column, -12 * chunk))
+                        .collect();
+                    (Part::Chunks, chunks)
+                }
+                (_, Dialect::Postgres) => (Part::Amount, vec![format!("CAST(SUM({}) AS TEXT)", column)]),
+            },
+            (_, Some(Merge::SumDecimal)) => (Part::Real, vec![format!("SUM({})", real(&column))]),
+            (_, Some(Merge::Mean)) => (Part::Real, vec![format!("AVG({})", real(&column))]),
+            (_, Some(Merge::Weighted(weight))) => {
+                let weight = real(&_column(weight));
+                let selected = vec![format!("SUM({} * {})", real(&column), weight), format!("SUM({})", weight), format!("AVG({})", real(&column))];
+                (Part::Weighted, selected)
+            }
+            _ => (Part::Value(*kind), vec![_select(dialect, *kind, &format!("MAX(CASE WHEN recency = 1 THEN {} END)", column))]),
+        };
+        parts.push((*field, part));
+        columns.extend(selected);
+    }
+
+    let partition = if per_pool { "bucket, pool" } else { "bucket" };
+    let appearance = match table.kind("position") {
+        Some(_) => ", ROW_NUMBER() OVER (PARTITION BY bucket ORDER BY start_time, position) AS appearance",
+        None => "",
+    };
+    let sql = format!(
+        "SELECT {} FROM (SELECT *, ROW_NUMBER() OVER (PARTITION BY {} ORDER BY {}) AS recency{} \
+         FROM (SELECT *, {} AS bucket FROM {}{}) AS hours) AS ranked GROUP BY {}",
+        columns.join(", "),
+        partition,
+        _time_order(table, "DESC"),
+        appearance,
+        _bucket_start(dialect, buckets.interval),
+        table.name,
+        condition,
+        partition
+    );
+    (sql, parts)
+}
+
+/// An exact amount sum in stored form, or an error when it has no `Amount` to fit in.
+fn _summed_amount(field: &str, total: Option<i128>) -> RepoResult<Bson> {
+    let total = total.ok_or_else(|| RepositoryError(format!("{} overflows when summed", field)))?;
+    Ok(Bson::Decimal128(Decimal128::from_str(&total.to_string()).map_err(|e| RepositoryError(e.to_string()))?))
+}
+
+/// Reads a row selected by `_grouped` back into the serialized field names.
+fn _bucket_document(parts: &[(&'static str, Part)], row: &AnyRow) -> RepoResult<Document> {
+    let real = |index: usize| -> RepoResult<f64> { Ok(row.try_get::<Option<f64>, _>(index)?.unwrap_or_default()) };
+    let mut document = Document::new();
+    let mut index = 0;
+    for (field, part) in parts {
+        let value = match part {
+            Part::Value(kind) => _stored_value(*kind, row, index)?,
+            Part::Real => merged_decimal(real(index)?),
+            Part::Weighted => {
+                let (product, weight) = (real(index)?, real(index + 1)?);
+                merged_decimal(if weight > 0.0 { product / weight } else { real(index + 2)? })
+            }
+            Part::Amount => {
+                let total = row.try_get::<Option<String>, _>(index)?.unwrap_or_default();
+                _summed_amount(field, if total.is_empty() { Some(0) } else { total.parse::<Amount>().ok().map(|total| total.0) })?
+            }
+            Part::Chunks => {
+                let mut total = Some(0_i128);
+                for chunk in (index..index + CHUNKS as usize).rev() {
+                    let sum = row.try_get::<Option<i64>, _>(chunk)?.unwrap_or_default();
+                    total = total.and_then(|total| total.checked_mul(CHUNK)).and_then(|total| total.checked_add(sum as i128));
+                }
+                _summed_amount(field, total)?
+            }
+        };
+        index += part.columns();
+        document.insert(*field, value);
+    }
+    Ok(document)
+}
+
+/// Writes `document` as a row of `table`, replacing the row with the same key.
+async fn _upsert_row(
+    connection: &mut sqlx::AnyConnection,
+    dialect: Dialect,
+    table: &Table,
+    document: &Document,
+) -> RepoResult<()> {
+    let mut arguments = Arguments::default();
+    let mut columns = Vec::with_capacity(table.fields.len());
+    let mut values = Vec::with_capacity(table.fields.len());
+    for (field, kind) in table.fields {
+        columns.push(_column(field));
+        let placeholder = arguments.push(document.get(*field).cloned().unwrap_or(Bson::Null));
+        values.push(_value(dialect, *kind, &placeholder));
+    }
+
+    let key = table.key();
+    let updates: Vec<String> = columns
+        .iter()
+        .filter(|column| !key.split(", ").any(|key| key == column.as_str()))
+        .map(|column| format!("{0} = excluded.{0}", column))
+        .collect();
+    let sql = format!(
+        "INSERT INTO {} ({}) VALUES ({}) ON CONFLICT ({}) DO UPDATE SET {}",
+        table.name,
+        columns.join(", "),
+        values.join(", "),
+        key,
+        updates.join(", ")
+    );
+    arguments.bind(&sql).execute(&mut *connection).await?;
+    Ok(())
+}
+
+pub struct SqlRepository<T> {
+    pool: AnyPool,
+    dialect: Dialect,
+    record: PhantomData<fn() -> T>,
+}
+
+impl<T> SqlRepository<T> {
+    pub fn new(pool: AnyPool, dialect: Dialect) -> Self {
+        SqlRepository { pool, dialect, record: PhantomData }
+    }
+
+    async fn select(&self, table: &Table, query: &HistoryQuery) -> RepoResult<Vec<Document>> {
+        let mut arguments = Arguments::default();
+        let mut condition = _where(table, &query.filter, &mut arguments);
+        let after = match &query.after {
+            Some(values) => _after(self.dialect, table, &query.sort, values, &mut arguments)?,
+            None => None,
+        };
+        if let Some(after) = after {
+            condition = if condition.is_empty() { format!(" WHERE {}", after) } else { format!("{} AND {}", condition, after) };
+        }
+        let fields: Vec<(&'static str, Kind)> = match query.projection() {
+            Some(projection) => table.fields.iter().filter(|(field, _)| projection.contains(field)).copied().collect(),
+            None => table.fields.to_vec(),
+        };
+        let sql = format!(
+            "SELECT {} FROM {}{}{}{}",
+            _select_list(self.dialect, &fields),
+            table.name,
+            condition,
+            _order_by(self.dialect, table, &query.sort)?,
+            _limit(self.dialect, query.skip, query.limit)
+        );
+
+        arguments
+            .bind(&sql)
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(|row| _row_document(&fields, row))
+            .collect()
+    }
+
+    /// `table`'s rows under `condition` grouped into `buckets`, in no particular order.
+    async fn grouped(&self, table: &Table, merges: &[(&str, Merge)], buckets: Buckets, condition: &str, arguments: &Arguments) -> RepoResult<Vec<Document>> {
+        let (sql, parts) = _grouped(self.dialect, table, merges, buckets, condition);
+        arguments.bind(&sql).fetch_all(&self.pool).await?.iter().map(|row| _bucket_document(&parts, row)).collect()
+    }
+
+    /// The buckets of the `table` rows `filter` matches.
+    async fn buckets(&self, table: &Table, merges: &[(&str, Merge)], filter: &HistoryFilter, buckets: Buckets) -> RepoResult<Vec<Document>> {
+        let mut arguments = Arguments::default();
+        let condition = _where(table, filter, &mut arguments);
+        self.grouped(table, merges, buckets, &condition, &arguments).await
+    }
+
+    /// Per-pool buckets of the `children` rows of the `table` records `filter` matches,
+    /// only for `pool` if given, with their amounts summed.
+    async fn child_buckets(&self, table: &Table, children: &Table, filter: &HistoryFilter, pool: Option<&str>, buckets: Buckets) -> RepoResult<Vec<Document>> {
+        let mut arguments = Arguments::default();
+        let mut condition = format!(" WHERE start_time IN (SELECT start_time FROM {}{})", table.name, _where(table, filter, &mut arguments));
+        if let Some(pool) = pool {
+            condition.push_str(&format!(" AND pool = {}", arguments.push(pool)));
+        }
+        let merges: Vec<(&str, Merge)> = children.fields.iter().filter(|(_, kind)| *kind == Kind::Amount).map(|(field, _)| (*field, Merge::Sum)).collect();
+        self.grouped(children, &merges, Buckets { per_pool: true, ..buckets }, &condition, &arguments).await
+    }
+
+    /// Sums an amount column exactly, as `Decimal128` like Mongo, or null when nothing matches.
+    async fn exact_sum(&self, table: &Table, filter: &HistoryFilter, column: &str) -> RepoResult<Bson> {
+        let mut arguments = Arguments::default();
+        let sql = format!("SELECT {} FROM {}{}", column, table.name, _where(table, filter, &mut arguments));
+        let rows = arguments.bind(&sql).fetch_all(&self.pool).await?;
+        if rows.is_empty() {
+            return Ok(Bson::Null);
+        }
+
+        let mut total = Amount::default();
+        for row in &rows {
+            let value: Amount = row.try_get::<String, _>(0)?.parse().map_err(RepositoryError)?;
+            total = total.checked_add(value).ok_or_else(|| RepositoryError(format!("{} overflows when summed", column)))?;
+        }
+        Ok(Bson::Decimal128(Decimal128::from_str(&total.to_string()).map_err(|e| RepositoryError(e.to_string()))?))
+    }
+
+    /// Child rows of the records starting at `start_times`, in stored order per owner.
+    async fn children(&self, table: &Table, start_times: &[i64]) -> RepoResult<Vec<(i64, Document)>> {
+        let mut rows = Vec::new();
+        for chunk in start_times.chunks(500) {
+            let mut arguments = Arguments::default();
+            let placeholders: Vec<String> = chunk.iter().map(|start_time| arguments.push(*start_time)).collect();
+            let order = if table.kind("position").is_some() { "start_time, position" } else { "start_time" };
+            let sql = format!(
+                "SELECT {} FROM {} WHERE start_time IN ({}) ORDER BY {}",
+                _select_list(self.dialect, table.fields),
+                table.name,
+                placeholders.join(", "),
+                order
+            );
+            for row in arguments.bind(&sql).fetch_all(&self.pool).await? {
+                let document = _row_document(table.fields, &row)?;
+                rows.push((document.get_i64("startTime").unwrap_or_default(), document));
+            }
+        }
+        Ok(rows)
+    }
+}
+
+#[async_trait]
+impl<T: SqlRecord> HistoryRepository<T> for SqlRepository<T> {
+    /// SQLite cannot hold an exact amount sum, so buckets are finished and paged here.
+    async fn find_documents(&self, query: &HistoryQuery) -> RepoResult<Vec<Document>> {
+        if let Some(buckets) = query.buckets {
+            let mut documents = self.buckets(T::TABLE, T::MERGES, &query.filter, buckets).await?;
+            if let Some((field, children)) = T::TABLE.children {
+                let rows = self.child_buckets(T::TABLE, children, &query.filter, None, buckets).await?;
+                embed_buckets(&mut documents, rows, field, buckets);
+            }
+            return Ok(find_in(documents, query));
+        }
+
+        let projection = query.projection();
+        let Some((field, children)) = T::TABLE.children.filter(|(field, _)| projection.as_ref().is_none_or(|projection| projection.contains(field))) else {
+            return self.select(T::TABLE, query).await;
+        };
+
+        // Child rows are matched to their owner on `startTime`, so it is read even when left out.
+        let keyless = projection.is_some_and(|projection| !projection.contains(&"startTime"));
+        let mut keyed = query.clone();
+        if let (true, Some(fields)) = (keyless, keyed.fields.as_mut()) {
+            fields.push("startTime".to_string());
+        }
+        let mut documents = self.select(T::TABLE, &keyed).await?;
+        let start_times: Vec<i64> = documents.iter().map(|document| document.get_i64("startTime").unwrap_or_default()).collect();
+        let mut rows: HashMap<i64, Vec<Bson>> = HashMap::new();
+        for (start_time, row) in self.children(children, &start_times).await? {
+            rows.entry(start_time).or_default().push(Bson::Document(_embedded(row)));
+        }
+        for document in documents.iter_mut() {
+            let start_time = document.get_i64("startTime").unwrap_or_default();
+            document.insert(field, rows.remove(&start_time).unwrap_or_default());
+            if keyless {
+                document.remove("startTime");
+            }
+        }
+        Ok(documents)
+    }
+
+    async fn count(&self, query: &HistoryQuery) -> RepoResult<u64> {
+        if let Some(buckets) = query.buckets {
+            return Ok(self.buckets(T::TABLE, T::MERGES, &query.filter, buckets).await?.len() as u64);
+        }
+        let mut arguments = Arguments::default();
+        let sql = format!("SELECT COUNT(*) FROM {}{}", T::TABLE.name, _where(T::TABLE, &query.filter, &mut arguments));
+        let rows: i64 = arguments.bind(&sql).fetch_one(&self.pool).await?.try_get(0)?;
+        Ok(rows as u64)
+    }
+
+    async fn aggregate(&self, filter: &HistoryFilter, accumulators: &[Accumulator]) -> RepoResult<Vec<Bson>> {
+        let table = T::TABLE;
+        let dialect = self.dialect;
+        let mut arguments = Arguments::default();
+        let condition = _where(table, filter, &mut arguments);
+        let column = |field: &str| table.kind(field).map(|kind| (kind, _column(field)));
+
+        // One scalar subquery per accumulator, all sharing the bound filter values.
+        let mut outputs = Vec::with_capacity(accumulators.len());
+        let mut expressions = Vec::with_capacity(accumulators.len());
+        for accumulator in accumulators {
+            let (output, expression) = match *accumulator {
+                Accumulator::Count => (Output::Value(Kind::Integer), format!("SELECT COUNT(*) FROM {}{}", table.name, condition)),
+                Accumulator::Min(field) | Accumulator::Max(field) | Accumulator::First(field) | Accumulator::Last(field) => match column(field) {
+                    Some((kind, column)) => {
+                        let order = match accumulator {
+                            Accumulator::Min(_) => _order(dialect, kind, &column, SortOrder::Asc).join(", "),
+                            Accumulator::Max(_) => _order(dialect, kind, &column, SortOrder::Desc).join(", "),
+                            Accumulator::First(_) => _time_order(table, "ASC"),
+                            _ => _time_order(table, "DESC"),
+                        };
+                        let select = _select(dialect, kind, &column);
+                        (Output::Value(kind), format!("SELECT {} FROM {}{} ORDER BY {} LIMIT 1", select, table.name, condition, order))
+                    }
+                    None => (Output::Value(Kind::Text), "SELECT NULL".to_string()),
+                },
+                Accumulator::Sum(field) => match (column(field), dialect) {
+                    (Some((Kind::Integer, column)), Dialect::Sqlite) => {
+                        (Output::Value(Kind::Integer), format!("SELECT SUM({}) FROM {}{}", column, table.name, condition))
+                    }
+                    (Some((Kind::Integer, column)), Dialect::Postgres) => {
+                        (Output::Value(Kind::Integer), format!("SELECT CAST(SUM({}) AS BIGINT) FROM {}{}", column, table.name, condition))
+                    }
+                    // SQLite has no exact type wide enough, so amounts are summed here instead.
+                    (Some((Kind::Amount, column)), Dialect::Sqlite) => (Output::ExactSum(column), "SELECT NULL".to_string()),
+                    (Some((_, column)), Dialect::Sqlite) => {
+                        (Output::Real, format!("SELECT SUM(CAST({} AS REAL)) FROM {}{}", column, table.name, condition))
+                    }
+                    (Some((kind, column)), Dialect::Postgres) => {
+                        (Output::Value(kind), format!("SELECT CAST(SUM({}) AS TEXT) FROM {}{}", column, table.name, condition))
+                    }
+                    (None, _) => (Output::Value(Kind::Text), "SELECT NULL".to_string()),
+                },
+                Accumulator::SumProduct(value, weight) => match (column(value), column(weight)) {
+                    (Some((_, value)), Some((_, weight))) => match dialect {
+                        Dialect::Sqlite => (
+                            Output::Real,
+                            format!("SELECT SUM(CAST({} AS REAL) * CAST({} AS REAL)) FROM {}{}", value, weight, table.name, condition),
+                        ),
+                        Dialect::Postgres => (
+                            Output::Value(Kind::Decimal),
+                            format!("SELECT CAST(SUM(CAST({} AS NUMERIC) * {}) AS TEXT) FROM {}{}", value, weight, table.name, condition),
+                        ),
+                    },
+                    _ => (Output::Value(Kind::Text), "SELECT NULL".to_string()),
+                },
+            };
+            outputs.push(output);
+            expressions.push(format!("({})", expression));
+        }
+
+        if expressions.is_empty() {
+            return Ok(Vec::new());
+        }
+        let sql = format!("SELECT {}", expressions.join(", "));
+        let row = arguments.bind(&sql).fetch_one(&self.pool).await?;
+
+        let mut values = Vec::with_capacity(outputs.len());
+        for (index, output) in outputs.into_iter().enumerate() {
+            values.push(match output {
+                Output::Value(kind) => _stored_value(kind, &row, index)?,
+                Output::Real => row.try_get::<Option<f64>, _>(index)?.map_or(Bson::Null, Bson::Double),
+                Output::ExactSum(column) => self.exact_sum(table, filter, &column).await?,
+            });
+        }
+        Ok(values)
+    }
+
+    /// Each record and its child rows are written in one transaction, so an interval is
+    /// never visible with only part of its pools.
+    async fn upsert(&self, records: Vec<T>, checkpoint: Option<&BatchCheckpoint<'_>>) -> RepoResult<()> {
+        let table = T::TABLE;
+        let mut transaction = self.pool.begin().await?;
+        for record in records {
+            _upsert_row(&mut transaction, self.dialect, table, &bson::to_document(&record)?).await?;
+
+            if let Some((_, children)) = table.children {
+                sqlx::query(&format!("DELETE FROM {} WHERE start_time = $1", children.name))
+                    .bind(record.start_time())
+                    .execute(&mut *transaction)
+                    .await?;
+                for row in record.child_rows()? {
+                    _upsert_row(&mut transaction, self.dialect, children, &row).await?;
+                }
+            }
+        }
+        if let Some(checkpoint) = checkpoint {
+            _record_batch(&mut transaction, checkpoint).await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn pools(&self) -> RepoResult<Vec<String>> {
+        // Earnings keep their pools in the child table.
+        let table = match T::TABLE.children {
+            _ if T::TABLE.kind("pool").is_some() => T::TABLE,
+            Some((_, children)) if children.kind("pool").is_some() => children,
+            _ => return Ok(Vec::new()),
+        };
+        let rows = sqlx::query(&format!("SELECT DISTINCT pool FROM {} ORDER BY pool", table.name))
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.iter().map(|row| row.try_get::<String, _>(0)).collect::<Result<_, _>>()?)
+    }
+
+    async fn intervals(&self, filter: &HistoryFilter) -> RepoResult<Vec<(i64, i64)>> {
+        let table = T::TABLE;
+        let mut arguments = Arguments::default();
+        let sql = format!(
+            "SELECT start_time, end_time FROM {}{} ORDER BY start_time",
+            table.name,
+            _where(table, filter, &mut arguments)
+        );
+        let rows = arguments.bind(&sql).fetch_all(&self.pool).await?;
+        Ok(rows
+            .iter()
+            .map(|row| Ok((row.try_get::<i64, _>(0)?, row.try_get::<i64, _>(1)?)))
+            .collect::<Result<_, sqlx::Error>>()?)
+    }
+}
+
+#[async_trait]
+impl EarningsRepository for SqlRepository<EarningsHistory> {
+    async fn find_pool_rows(&self, query: &HistoryQuery) -> RepoResult<Vec<Document>> {
+        let mut rows = match query.buckets {
+            Some(buckets) => self.child_buckets(&EARNINGS_TABLE, &POOL_EARNINGS_TABLE, &query.filter, query.filter.pool.as_deref(), buckets).await?,
+            None => self.select(&POOL_EARNINGS_TABLE, query).await?,
+        };
+        for row in rows.iter_mut() {
+            row.remove("position");
+        }
+        Ok(match query.buckets {
+            Some(_) => find_in(rows, query),
+            None => rows,
+        })
+    }
+
+    async fn count_pool_rows(&self, query: &HistoryQuery) -> RepoResult<u64> {
+        if let Some(buckets) = query.buckets {
+            let rows = self.child_buckets(&EARNINGS_TABLE, &POOL_EARNINGS_TABLE, &query.filter, query.filter.pool.as_deref(), buckets).await?;
+            return Ok(rows.len() as u64);
+        }
+        let mut arguments = Arguments::default();
+        let sql = format!("SELECT COUNT(*) FROM {}{}", POOL_EARNINGS_TABLE.name, _where(&POOL_EARNINGS_TABLE, &query.filter, &mut arguments));
+        let rows: i64 = arguments.bind(&sql).fetch_one(&self.pool).await?.try_get(0)?;
+        Ok(rows as u64)
+    }
+}
+
+pub struct SqlCheckpoints {
+    pool: AnyPool,
+}
+
+impl SqlCheckpoints {
+    pub fn new(pool: AnyPool) -> Self {
+        SqlCheckpoints { pool }
+    }
+}
+
+const CHECKPOINT_COLUMNS: &str = "dataset, pool, last_end_time, status, error, last_batch_rows, total_rows, updated_at";
+
+fn _checkpoint(row: &AnyRow) -> Result<IngestionState, sqlx::Error> {
+    let pool: String = row.try_get(1)?;
+    Ok(IngestionState {
+        id: None,
+        dataset: row.try_get(0)?,
+        pool: Some(pool).filter(|pool| !pool.is_empty()),
+        last_end_time: row.try_get(2)?,
+        status: row.try_get(3)?,
+        error: row.try_get(4)?,
+        last_batch_rows: row.try_get(5)?,
+        total_rows: row.try_get(6)?,
+        updated_at: row.try_get(7)?,
+    })
+}
+
+/// Like the Mongo `$max`, the stored checkpoint never moves backwards.
+async fn _record_batch(connection: &mut sqlx::AnyConnection, checkpoint: &BatchCheckpoint<'_>) -> Result<(), sqlx::Error> {
+    sqlx::query(&format!(
+        "INSERT INTO ingestion_state ({}) VALUES ($1, $2, $3, $4, NULL, $5, $5, $6) \
+         ON CONFLICT (dataset, pool) DO UPDATE SET \
+         last_end_time = CASE WHEN excluded.last_end_time > ingestion_state.last_end_time \
+         THEN excluded.last_end_time ELSE ingestion_state.last_end_time END, \
+         total_rows = ingestion_state.total_rows + excluded.total_rows, \
+         status = excluded.status, error = NULL, \
+         last_batch_rows = excluded.last_batch_rows, updated_at = excluded.updated_at",
+        CHECKPOINT_COLUMNS
+    ))
+    .bind(checkpoint.dataset.name())
+    .bind(checkpoint.pool.unwrap_or_default().to_string())
+    .bind(checkpoint.end_time)
+    .bind(STATUS_RUNNING)
+    .bind(checkpoint.rows)
+    .bind(Utc::now().timestamp())
+    .execute(connection)
+    .await?;
+    Ok(())
+}
+
+#[async_trait]
+impl CheckpointRepository for SqlCheckpoints {
+    async fn get(&self, dataset: Dataset, pool: Option<&str>) -> RepoResult<Option<IngestionState>> {
+        let row = sqlx::query(&format!("SELECT {} FROM ingestion_state WHERE dataset = $1 AND pool = $2", CHECKPOINT_COLUMNS))
+            .bind(dataset.name())
+            .bind(pool.unwrap_or_default().to_string())
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.as_ref().map(_checkpoint).transpose()?)
+    }
+
+    async fn record_status(&self, dataset: Dataset, pool: Option<&str>, status: &str, error: Option<String>) -> RepoResult<()> {
+        sqlx::query(&format!(
+            "INSERT INTO ingestion_state ({}) VALUES ($1, $2, 0, $3, $4, 0, 0, $5) \
+             ON CONFLICT (dataset, pool) DO UPDATE SET \
+             status = excluded.status, error = excluded.error, updated_at = excluded.updated_at",
+            CHECKPOINT_COLUMNS
+        ))
+        .bind(dataset.name())
+        .bind(pool.unwrap_or_default().to_string())
+        .bind(status.to_string())
+        .bind(error)
+        .bind(Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn list(&self) -> RepoResult<Vec<IngestionState>> {
+        let rows = sqlx::query(&format!("SELECT {} FROM ingestion_state ORDER BY dataset, pool", CHECKPOINT_COLUMNS))
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.iter().map(_checkpoint).collect::<Result<_, _>>()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::earnings_history::PoolEarnings;
+    use crate::repository::aggregate_string;
+
+    async fn _repository<T>() -> SqlRepository<T> {
+        let (pool, dialect) = crate::db::sql::connect("sqlite::memory:").await.unwrap();
+        SqlRepository::new(pool, dialect)
+    }
+
+    fn _depth(start_time: i64, asset_depth: &str) -> DepthHistory {
+        DepthHistory {
+            pool: "BTC.BTC".to_string(),
+            start_time,
+            end_time: start_time + 3600,
+            asset_depth: asset_depth.parse().unwrap(),
+            ..DepthHistory::default()
+        }
+    }
+
+    fn _sort(keys: &[(&str, SortOrder)]) -> Vec<SortKey> {
+        keys.iter().map(|(field, order)| SortKey { field: field.to_string(), order: *order }).collect()
+    }
+
+    fn _depths(records: &[DepthHistory]) -> Vec<String> {
+        records.iter().map(|record| record.asset_depth.to_string()).collect()
+    }
+
+    /// Depths that an `f64` can't tell apart, beyond `i64`, and negative.
+    async fn _seeded() -> SqlRepository<DepthHistory> {
+        let repository = _repository().await;
+        let depths = ["1", "100000000000000000000", "9", "-5", "-40"];
+        repository.upsert(depths.iter().enumerate().map(|(hour, depth)| _depth(hour as i64 * 3600, depth)).collect(), None).await.unwrap();
+        // Replaces the first hour rather than adding a row.
+        repository.upsert(vec![_depth(0, "100000000000000000001")], None).await.unwrap();
+        repository
+    }
+
+    #[tokio::test]
+    async fn upsert_and_find_round_trip() {
+        let repository = _seeded().await;
+        let query = HistoryQuery { sort: _sort(&[("startTime", SortOrder::Asc)]), ..HistoryQuery::default() };
+        let records = repository.find(&query).await.unwrap();
+
+        assert_eq!(_depths(&records), ["100000000000000000001", "100000000000000000000", "9", "-5", "-40"]);
+        assert_eq!(records[2].pool, "BTC.BTC");
+        assert_eq!((records[2].start_time, records[2].end_time), (7200, 10800));
+    }
+
+    #[tokio::test]
+    async fn sorts_amounts_exactly() {
+        let repository = _seeded().await;
+        let ascending = HistoryQuery { sort: _sort(&[("assetDepth", SortOrder::Asc)]), ..HistoryQuery::default() };
+        let descending = HistoryQuery { sort: _sort(&[("assetDepth", SortOrder::Desc)]), ..HistoryQuery::default() };
+
+        assert_eq!(
+            _depths(&repository.find(&ascending).await.unwrap()),
+            ["-40", "-5", "9", "100000000000000000000", "100000000000000000001"]
+        );
+        assert_eq!(
+            _depths(&repository.find(&descending).await.unwrap()),
+            ["100000000000000000001", "100000000000000000000", "9", "-5", "-40"]
+        );
+    }
+
+    #[tokio::test]
+    async fn resumes_after_amount_keys_exactly() {
+        let repository = _seeded().await;
+        let after = |value: &str, order: SortOrder| HistoryQuery {
+            sort: _sort(&[("assetDepth", order), ("startTime", SortOrder::Desc)]),
+            after: Some(vec![Bson::Decimal128(Decimal128::from_str(value).unwrap()), Bson::Int64(3600)]),
+            ..HistoryQuery::default()
+        };
+
+        let records = repository.find(&after("100000000000000000000", SortOrder::Asc)).await.unwrap();
+        assert_eq!(_depths(&records), ["100000000000000000001"]);
+        let records = repository.find(&after("-5", SortOrder::Asc)).await.unwrap();
+        assert_eq!(_depths(&records), ["9", "100000000000000000000", "100000000000000000001"]);
+        let records = repository.find(&after("9", SortOrder::Desc)).await.unwrap();
+        assert_eq!(_depths(&records), ["-5", "-40"]);
+    }
+
+    #[tokio::test]
+    async fn batches_commit_with_their_checkpoint() {
+        let (pool, dialect) = crate::db::sql::connect("sqlite::memory:").await.unwrap();
+        let repository = SqlRepository::<DepthHistory>::new(pool.clone(), dialect);
+        let checkpoints = SqlCheckpoints::new(pool.clone());
+        let checkpoint = BatchCheckpoint { dataset: Dataset::Depth, pool: Some("BTC.BTC"), end_time: 7200, rows: 2 };
+        repository.upsert(vec![_depth(0, "1"), _depth(3600, "2")], Some(&checkpoint)).await.unwrap();
+        let state = checkpoints.get(Dataset::Depth, Some("BTC.BTC")).await.unwrap().unwrap();
+        assert_eq!((state.last_end_time, state.total_rows), (7200, 2));
+
+        // A checkpoint that can't be written takes its batch down with it.
+        sqlx::query("DROP TABLE ingestion_state").execute(&pool).await.unwrap();
+        let checkpoint = BatchCheckpoint { end_time: 10800, rows: 1, ..checkpoint };
+        assert!(repository.upsert(vec![_depth(7200, "3")], Some(&checkpoint)).await.is_err());
+        assert_eq!(repository.intervals(&HistoryFilter::default()).await.unwrap(), [(0, 3600), (3600, 7200)]);
+    }
+
+    #[tokio::test]
+    async fn rejects_unmapped_sort_fields() {
+        let repository = _seeded().await;
+        let query = HistoryQuery { sort: _sort(&[("depth", SortOrder::Asc)]), ..HistoryQuery::default() };
+        assert!(repository.find(&query).await.is_err());
+
+        let query = HistoryQuery { after: Some(vec![Bson::Int64(0)]), ..query };
+        assert!(repository.find(&query).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn aggregates_amounts_exactly() {
+        let repository = _seeded().await;
+        let accumulators = [
+            Accumulator::Count,
+            Accumulator::Sum("assetDepth"),
+            Accumulator::Min("assetDepth"),
+            Accumulator::Max("assetDepth"),
+            Accumulator::First("assetDepth"),
+            Accumulator::Last("assetDepth"),
+            Accumulator::Max("endTime"),
+        ];
+        let values: Vec<String> = repository.aggregate(&HistoryFilter::default(), &accumulators).await.unwrap().iter().map(aggregate_string).collect();
+        assert_eq!(
+            values,
+            ["5", "199999999999999999965", "-40", "100000000000000000001", "100000000000000000001", "-40", "18000"]
+        );
+
+        let empty = HistoryFilter { from: Some(86400), ..HistoryFilter::default() };
+        let values = repository.aggregate(&empty, &accumulators).await.unwrap();
+        assert_eq!(values[0], Bson::Int64(0));
+        assert!(values[1..].iter().all(|value| *value == Bson::Null));
+    }
+
+    #[tokio::test]
+    async fn first_and_last_break_time_ties_on_pool() {
+        let repository = _repository().await;
+        let depth = |pool: &str, asset_depth: &str| DepthHistory { pool: pool.to_string(), .._depth(0, asset_depth) };
+        repository.upsert(vec![depth("ETH.ETH", "2"), depth("BTC.BTC", "1"), depth("DOGE.DOGE", "3")], None).await.unwrap();
+
+        let values = repository.aggregate(&HistoryFilter::default(), &[Accumulator::First("assetDepth"), Accumulator::Last("assetDepth")]).await.unwrap();
+        assert_eq!(values.iter().map(aggregate_string).collect::<Vec<_>>(), ["1", "2"]);
+    }
+
+    #[tokio::test]
+    async fn round_trips_embedded_pools() {
+        let repository: SqlRepository<EarningsHistory> = _repository().await;
+        let pool = |pool: &str, earnings: i128| PoolEarnings { pool: pool.to_string(), earnings: Amount(earnings), ..PoolEarnings::default() };
+        let interval = EarningsHistory {
+            start_time: 0,
+            end_time: 3600,
+            earnings: Amount(30),
+            pools: vec![pool("ETH.ETH", 20), pool("BTC.BTC", 10)],
+            ..EarningsHistory::default()
+        };
+        repository.upsert(vec![interval], None).await.unwrap();
+
+        let records = repository.find(&HistoryQuery::default()).await.unwrap();
+        let pools: Vec<(&str, i128)> = records[0].pools.iter().map(|pool| (pool.pool.as_str(), pool.earnings.0)).collect();
+        assert_eq!(pools, [("ETH.ETH", 20), ("BTC.BTC", 10)]);
+
+        let query = HistoryQuery { filter: HistoryFilter { pool: Some("BTC.BTC".to_string()), ..HistoryFilter::default() }, ..HistoryQuery::default() };
+        let rows = repository.find_pool_rows(&query).await.unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!((rows[0].get_str("pool").unwrap(), rows[0].get_i64("startTime").unwrap()), ("BTC.BTC", 0));
+        assert_eq!(rows[0].get("earnings"), Some(&Bson::Decimal128(Decimal128::from_str("10").unwrap())));
+        assert_eq!(repository.pools().await.unwrap(), ["BTC.BTC", "ETH.ETH"]);
+    }
+
+    #[tokio::test]
+    async fn projections_read_only_the_fields_and_sort_keys() {
+        let repository: SqlRepository<EarningsHistory> = _repository().await;
+        let interval = EarningsHistory {
+            start_time: 0,
+            end_time: 3600,
+            earnings: Amount(30),
+            pools: vec![PoolEarnings { pool: "BTC.BTC".to_string(), earnings: Amount(30), ..PoolEarnings::default() }],
+            ..EarningsHistory::default()
+        };
+        repository.upsert(vec![interval], None).await.unwrap();
+
+        let sort = vec![SortKey { field: "endTime".to_string(), order: SortOrder::Asc }];
+        let query = |fields: &[&str]| HistoryQuery { sort: sort.clone(), fields: Some(fields.iter().map(|field| field.to_string()).collect()), ..HistoryQuery::default() };
+        let documents = repository.find_documents(&query(&["earnings"])).await.unwrap();
+        assert_eq!(documents[0].keys().collect::<Vec<_>>(), ["endTime", "earnings"]);
+
+        // Pools are matched on `startTime`, which is left out again when not asked for.
+        let documents = repository.find_documents(&query(&["pools"])).await.unwrap();
+        assert_eq!(documents[0].keys().collect::<Vec<_>>(), ["endTime", "pools"]);
+        let pools = documents[0].get_array("pools").unwrap();
+        assert_eq!(pools[0].as_document().unwrap().get_str("pool").unwrap(), "BTC.BTC");
+    }
+
+    fn _bucketed(interval: Option<Interval>, per_pool: bool) -> HistoryQuery {
+        HistoryQuery { sort: _sort(&[("startTime", SortOrder::Asc)]), buckets: Some(Buckets { interval, per_pool }), ..HistoryQuery::default() }
+    }
+
+    #[tokio::test]
+    async fn buckets_keep_the_last_level_of_each_month() {
+        let repository = _repository().await;
+        // 2024-02-28 23:00, 2024-02-29 23:00 and 2024-03-01 00:00 UTC.
+        let hours = [(1709161200, "7"), (1709247600, "8"), (1709251200, "9")];
+        repository.upsert(hours.iter().map(|(start_time, depth)| _depth(*start_time, depth)).collect(), None).await.unwrap();
+
+        let buckets = repository.find(&_bucketed(Some(Interval::Month), true)).await.unwrap();
+        let times: Vec<(i64, i64)> = buckets.iter().map(|bucket| (bucket.start_time, bucket.end_time)).collect();
+        assert_eq!(times, [(1706745600, 1709251200), (1709251200, 1711929600)]);
+        assert_eq!(_depths(&buckets), ["8", "9"]);
+        assert_eq!(repository.count(&_bucketed(Some(Interval::Week), true)).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn buckets_sum_amounts_exactly() {
+        let repository: SqlRepository<SwapHistory> = _repository().await;
+        let swap = |start_time: i64, volume: &str, slip: &str| SwapHistory {
+            pool: "BTC.BTC".to_string(),
+            start_time,
+            end_time: start_time + 3600,
+            total_count: 1,
+            total_volume: volume.parse().unwrap(),
+            average_slip: slip.parse().unwrap(),
+            ..SwapHistory::default()
+        };
+        // Carries across the twelve-digit chunks each way, beyond `i64`.
+        let hours = vec![
+            swap(0, "999999999999", "1"),
+            swap(3600, "1", "5"),
+            swap(86400, "100000000000000000000", "2"),
+            swap(90000, "-1", "2"),
+            swap(93600, "-1000000000000", "2"),
+        ];
+        repository.upsert(hours, None).await.unwrap();
+
+        let buckets = repository.find(&_bucketed(Some(Interval::Day), false)).await.unwrap();
+        let volumes: Vec<String> = buckets.iter().map(|bucket| bucket.total_volume.to_string()).collect();
+        assert_eq!(volumes, ["1000000000000", "99999998999999999999"]);
+        assert_eq!((buckets[0].total_count, buckets[0].average_slip.to_string()), (2, "1.000000000004".to_string()));
+
+        let range = repository.find(&_bucketed(None, false)).await.unwrap();
+        assert_eq!((range[0].start_time, range[0].end_time), (0, 97200));
+        assert_eq!(range[0].total_volume.to_string(), "99999999999999999999");
+    }
+
+    #[tokio::test]
+    async fn buckets_embed_pools_in_order_of_appearance() {
+        let repository: SqlRepository<EarningsHistory> = _repository().await;
+        let pool = |pool: &str, earnings: i128| PoolEarnings { pool: pool.to_string(), earnings: Amount(earnings), ..PoolEarnings::default() };
+        let hour = |start_time: i64, pools: Vec<PoolEarnings>| EarningsHistory {
+            start_time,
+            end_time: start_time + 3600,
+            earnings: Amount(pools.iter().map(|pool| pool.earnings.0).sum()),
+            pools,
+            ..EarningsHistory::default()
+        };
+        let hours = vec![hour(0, vec![pool("BTC.BTC", 3), pool("ETH.ETH", 1)]), hour(3600, vec![pool("ETH.ETH", 4), pool("BNB.BNB", 2)])];
+        repository.upsert(hours, None).await.unwrap();
+
+        let buckets = repository.find(&_bucketed(Some(Interval::Day), false)).await.unwrap();
+        assert_eq!(buckets[0].earnings, Amount(10));
+        let pools: Vec<(&str, i128)> = buckets[0].pools.iter().map(|pool| (pool.pool.as_str(), pool.earnings.0)).collect();
+        assert_eq!(pools, [("BTC.BTC", 3), ("ETH.ETH", 5), ("BNB.BNB", 2)]);
+
+        let query = HistoryQuery { filter: HistoryFilter { pool: Some("ETH.ETH".to_string()), ..HistoryFilter::default() }, .._bucketed(Some(Interval::Day), true) };
+        let rows = repository.find_pool_rows(&query).await.unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!((rows[0].get_i64("startTime").unwrap(), rows[0].get_i64("endTime").unwrap()), (0, 86400));
+        assert_eq!(rows[0].get("earnings"), Some(&Bson::Decimal128(Decimal128::from_str("5").unwrap())));
+        assert_eq!(repository.count_pool_rows(&_bucketed(Some(Interval::Day), true)).await.unwrap(), 3);
+    }
+
+    #[test]
+    fn postgres_keeps_amounts_numeric() {
+        assert_eq!(_value(Dialect::Postgres, Kind::Amount, "$1"), "CAST($1 AS NUMERIC)");
+        assert_eq!(_select(Dialect::Postgres, Kind::Decimal, "asset_price"), "CAST(asset_price AS TEXT)");
+        assert_eq!(_order(Dialect::Postgres, Kind::Amount, "asset_depth", SortOrder::Desc), ["asset_depth DESC"]);
+        assert_eq!(_limit(Dialect::Postgres, 20, None), " OFFSET 20");
+
+        let mut arguments = Arguments::default();
+        let sort = _sort(&[("assetDepth", SortOrder::Asc), ("startTime", SortOrder::Desc)]);
+        let values = [Bson::Decimal128(Decimal128::from_str("-5").unwrap()), Bson::Int64(3600)];
+        let after = _after(Dialect::Postgres, &DEPTH_TABLE, &sort, &values, &mut arguments).unwrap();
+        assert_eq!(
+            after.as_deref(),
+            Some("((asset_depth > CAST($1 AS NUMERIC)) OR (asset_depth = CAST($1 AS NUMERIC) AND start_time < $2))")
+        );
+        assert_eq!(arguments.0, [Bson::String("-5".to_string()), Bson::Int64(3600)]);
+    }
+
+    #[test]
+    fn postgres_buckets_in_utc_calendar_units() {
+        assert_eq!(
+            _bucket_start(Dialect::Postgres, Some(Interval::Quarter)),
+            "CAST(EXTRACT(EPOCH FROM date_trunc('quarter', to_timestamp(start_time) AT TIME ZONE 'UTC')) AS BIGINT)"
+        );
+        assert_eq!(_bucket_start(Dialect::Postgres, Some(Interval::Day)), "start_time - start_time % 86400");
+        assert_eq!(
+            _bucket_end(Dialect::Postgres, Interval::Year, "bucket"),
+            "CAST(EXTRACT(EPOCH FROM (to_timestamp(bucket) AT TIME ZONE 'UTC') + INTERVAL '12 months') AS BIGINT)"
+        );
+
+        let (sql, parts) = _grouped(Dialect::Postgres, &RUNEPOOL_TABLE, RunePoolHistory::MERGES, Buckets { interval: None, per_pool: false }, " WHERE start_time >= $1");
+        assert_eq!(
+            sql,
+            "SELECT MIN(start_time), MAX(end_time), MAX(CASE WHEN recency = 1 THEN count END), \
+             CAST(MAX(CASE WHEN recency = 1 THEN units END) AS TEXT) \
+             FROM (SELECT *, ROW_NUMBER() OVER (PARTITION BY bucket ORDER BY start_time DESC) AS recency \
+             FROM (SELECT *, 0 AS bucket FROM runepool_history WHERE start_time >= $1) AS hours) AS ranked GROUP BY bucket"
+        );
+        assert_eq!(parts.len(), 4);
+    }
+
+    #[test]
+    fn postgres_sums_amounts_exactly_in_numeric() {
+        let buckets = Buckets { interval: Some(Interval::Month), per_pool: true };
+        let (sql, parts) = _grouped(Dialect::Postgres, &SWAPS_TABLE, SwapHistory::MERGES, buckets, "");
+        for fragment in [
+            "CAST(SUM(total_volume) AS TEXT)",
+            "CAST(SUM(total_count) AS BIGINT)",
+            "SUM(CAST(average_slip AS DOUBLE PRECISION) * CAST(total_volume AS DOUBLE PRECISION))",
+            "PARTITION BY bucket, pool ORDER BY start_time DESC, pool DESC",
+            "date_trunc('month', to_timestamp(start_time) AT TIME ZONE 'UTC')",
+            "GROUP BY bucket, pool",
+        ] {
+            assert!(sql.contains(fragment), "{} not in {}", fragment, sql);
+        }
+        assert!(!sql.contains("substr"), "{}", sql);
+        let part = |field: &str| parts.iter().find(|(name, _)| *name == field).map(|(_, part)| *part);
+        assert!(matches!(part("totalVolume"), Some(Part::Amount)));
+        assert!(matches!(part("averageSlip"), Some(Part::Weighted)));
+    }
+}
@@ -0,0 +1,121 @@
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+use rand::Rng;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use crate::data_fetcher::fetch_and_store_dataset;
+use crate::models::ingestion_state::Dataset;
+use crate::repository::Repositories;
+use crate::services::midgard_client::MidgardClient;
+
+const DEFAULT_INTERVAL_SECS: u64 = 3600;
+const DEFAULT_JITTER_SECS: u64 = 60;
+
+#[derive(Debug, Clone)]
+pub struct SchedulerConfig {
+    pub enabled: bool,
+    pub jitter: Duration,
+    pub intervals: Vec<(Dataset, Duration)>,
+}
+
+impl SchedulerConfig {
+    /// Reads `INGEST_INTERVAL_SECS` and its per-dataset overrides, such as
+    /// `INGEST_DEPTH_INTERVAL_SECS`; `0` disables a dataset.
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let enabled = match env::var("INGEST_ENABLED") {
+            Ok(value) => value.parse::<bool>().map_err(|_| "Invalid INGEST_ENABLED format")?,
+            Err(_) => true,
+        };
+        let default_interval = env_secs("INGEST_INTERVAL_SECS")?.unwrap_or(DEFAULT_INTERVAL_SECS);
+        let jitter = env_secs("INGEST_JITTER_SECS")?.unwrap_or(DEFAULT_JITTER_SECS);
+
+        let mut intervals = Vec::new();
+        for dataset in Dataset::ALL {
+            let key = format!("INGEST_{}_INTERVAL_SECS", dataset.name().to_uppercase());
+            let secs = env_secs(&key)?.unwrap_or(default_interval);
+            if secs > 0 {
+                intervals.push((dataset, Duration::from_secs(secs)));
+            }
+        }
+
+        Ok(SchedulerConfig {
+            enabled,
+            jitter: Duration::from_secs(jitter),
+            intervals,
+        })
+    }
+}
+
+fn env_secs(key: &str) -> Result<Option<u64>, Box<dyn std::error::Error>> {
+    match env::var(key) {
+        Ok(value) => Ok(Some(value.parse::<u64>().map_err(|_| format!("Invalid {} format", key))?)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Spawns one jittered ingestion loop per enabled dataset, stopped by `shutdown`.
+pub fn spawn(
+    repos: Repositories,
+    midgard: Arc<MidgardClient>,
+    config: SchedulerConfig,
+    shutdown: watch::Receiver<bool>,
+) -> Vec<JoinHandle<()>> {
+    if !config.enabled {
+        log::info!("Ingestion scheduler disabled");
+        return Vec::new();
+    }
+
+    config
+        .intervals
+        .iter()
+        .map(|&(dataset, interval)| {
+            tokio::spawn(run_dataset_loop(repos.clone(), midgard.clone(), dataset, interval, config.jitter, shutdown.clone()))
+        })
+        .collect()
+}
+
+async fn run_dataset_loop(
+    repos: Repositories,
+    midgard: Arc<MidgardClient>,
+    dataset: Dataset,
+    interval: Duration,
+    jitter: Duration,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    log::info!("Scheduling {} ingestion every {}s", dataset, interval.as_secs());
+
+    loop {
+        if *shutdown.borrow() {
+            break;
+        }
+
+        tokio::select! {
+            result = run_once(&repos, &midgard, dataset) => {
+                if let Err(e) = result {
+                    log::error!("Error ingesting {} data: {}", dataset, e);
+                }
+            }
+            _ = shutdown.changed() => break,
+        }
+
+        let delay = interval + random_jitter(jitter);
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = shutdown.changed() => break,
+        }
+    }
+
+    log::info!("Stopped {} ingestion", dataset);
+}
+
+async fn run_once(repos: &Repositories, midgard: &MidgardClient, dataset: Dataset) -> Result<(), String> {
+    fetch_and_store_dataset(repos, midgard, dataset, None).await.map_err(|e| e.to_string())
+}
+
+fn random_jitter(max: Duration) -> Duration {
+    if max.is_zero() {
+        return Duration::ZERO;
+    }
+    Duration::from_millis(rand::thread_rng().gen_range(0..=max.as_millis() as u64))
+}
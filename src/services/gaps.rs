@@ -0,0 +1,103 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+use std::error::Error;
+use crate::data_fetcher::fetch_and_store_range;
+use crate::models::ingestion_state::Dataset;
+use crate::repository::{HistoryFilter, RangeMatch, Repositories};
+use crate::services::midgard_client::MidgardClient;
+
+/// A missing `[startTime, endTime)` range in a stored hourly series.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Gap {
+    pub dataset: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool: Option<String>,
+    #[serde(rename = "startTime")]
+    pub start_time: i64,
+    #[serde(rename = "endTime")]
+    pub end_time: i64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BackfillOutcome {
+    #[serde(flatten)]
+    pub gap: Gap,
+    /// Why the gap could not be fetched; absent once it is filled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Holes between consecutive intervals of a dataset, per pool for depth and swaps, and at
+/// the head or tail of `from` / `to` when given.
+pub async fn find_gaps(
+    repos: &Repositories,
+    dataset: Dataset,
+    pool: Option<&str>,
+    from: Option<i64>,
+    to: Option<i64>,
+) -> Result<Vec<Gap>, Box<dyn Error>> {
+    let pools: Vec<Option<String>> = if !dataset.per_pool() {
+        vec![None]
+    } else if let Some(pool) = pool {
+        vec![Some(pool.to_string())]
+    } else {
+        repos.pools(dataset).await?.into_iter().map(Some).collect()
+    };
+
+    let mut gaps = Vec::new();
+    for pool in pools {
+        // Overlap rather than containment, so intervals straddling `from` / `to` count.
+        let filter = HistoryFilter { from, to, range: RangeMatch::Overlapping, pool: pool.clone() };
+        let intervals = repos.intervals(dataset, &filter).await?;
+
+        let gap = |start_time: i64, end_time: i64| Gap {
+            dataset: dataset.name().to_string(),
+            pool: pool.clone(),
+            start_time,
+            end_time,
+        };
+
+        let mut covered_until = from;
+        for (start_time, end_time) in intervals {
+            if let Some(covered_until) = covered_until {
+                if start_time > covered_until {
+                    gaps.push(gap(covered_until, start_time));
+                }
+            }
+            covered_until = Some(covered_until.map_or(end_time, |covered| covered.max(end_time)));
+        }
+
+        if let (Some(covered_until), Some(to)) = (covered_until, to) {
+            if covered_until < to {
+                gaps.push(gap(covered_until, to));
+            }
+        }
+    }
+
+    Ok(gaps)
+}
+
+/// Re-fetches exactly the given ranges from Midgard, carrying on past failures.
+pub async fn backfill_gaps(repos: &Repositories, midgard: &MidgardClient, gaps: Vec<Gap>) -> Vec<BackfillOutcome> {
+    let mut outcomes = Vec::with_capacity(gaps.len());
+
+    for gap in gaps {
+        log::info!(
+            "Backfilling {} history{} from {} to {}",
+            gap.dataset,
+            gap.pool.as_deref().map(|pool| format!(" for {}", pool)).unwrap_or_default(),
+            gap.start_time,
+            gap.end_time
+        );
+        let error = match gap.dataset.parse::<Dataset>() {
+            Ok(dataset) => fetch_and_store_range(repos, midgard, dataset, gap.pool.as_deref(), gap.start_time, gap.end_time)
+                .await
+                .err()
+                .map(|e| e.to_string()),
+            Err(e) => Some(e),
+        };
+        outcomes.push(BackfillOutcome { gap, error });
+    }
+
+    outcomes
+}
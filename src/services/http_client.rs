@@ -0,0 +1,199 @@
+use std::env;
+use std::error::Error;
+use std::sync::Mutex;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::{header::{HeaderMap, RETRY_AFTER}, StatusCode};
+use tokio::time::Instant;
+
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_MAX_RETRIES: u32 = 5;
+const DEFAULT_BASE_DELAY_MS: u64 = 500;
+const DEFAULT_MAX_DELAY_SECS: u64 = 60;
+const DEFAULT_REQUESTS_PER_SECOND: f64 = 5.0;
+const DEFAULT_BREAKER_THRESHOLD: u32 = 10;
+const DEFAULT_BREAKER_COOLDOWN_SECS: u64 = 120;
+
+/// HTTP client shared by every Midgard fetcher: rate limited, retrying transient failures
+/// with backoff, and failing fast behind a circuit breaker.
+pub struct HttpClient {
+    client: reqwest::Client,
+    max_retries: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    min_request_spacing: Duration,
+    next_request_at: tokio::sync::Mutex<Instant>,
+    breaker_threshold: u32,
+    breaker_cooldown: Duration,
+    breaker: Mutex<BreakerState>,
+}
+
+#[derive(Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+enum Attempt {
+    Retry { reason: String, retry_after: Option<Duration> },
+    Fatal(String),
+}
+
+impl HttpClient {
+    /// Builds the client from the `MIDGARD_*` timeout, retry, rate and breaker settings.
+    pub fn from_env(default_headers: HeaderMap) -> Result<Self, Box<dyn Error>> {
+        let timeout = env_parse("MIDGARD_TIMEOUT_SECS", DEFAULT_TIMEOUT_SECS)?;
+        let requests_per_second = env_parse("MIDGARD_REQUESTS_PER_SECOND", DEFAULT_REQUESTS_PER_SECOND)?;
+        if requests_per_second <= 0.0 {
+            return Err("MIDGARD_REQUESTS_PER_SECOND must be positive".into());
+        }
+
+        let client = reqwest::Client::builder()
+            .default_headers(default_headers)
+            .timeout(Duration::from_secs(timeout))
+            .connect_timeout(Duration::from_secs(timeout.min(10)))
+            .build()?;
+
+        Ok(HttpClient {
+            client,
+            max_retries: env_parse("MIDGARD_MAX_RETRIES", DEFAULT_MAX_RETRIES)?,
+            base_delay: Duration::from_millis(env_parse("MIDGARD_BASE_DELAY_MS", DEFAULT_BASE_DELAY_MS)?),
+            max_delay: Duration::from_secs(env_parse("MIDGARD_MAX_DELAY_SECS", DEFAULT_MAX_DELAY_SECS)?),
+            min_request_spacing: Duration::from_secs_f64(1.0 / requests_per_second),
+            next_request_at: tokio::sync::Mutex::new(Instant::now()),
+            breaker_threshold: env_parse("MIDGARD_BREAKER_THRESHOLD", DEFAULT_BREAKER_THRESHOLD)?,
+            breaker_cooldown: Duration::from_secs(env_parse("MIDGARD_BREAKER_COOLDOWN_SECS", DEFAULT_BREAKER_COOLDOWN_SECS)?),
+            breaker: Mutex::new(BreakerState::default()),
+        })
+    }
+
+    /// Fetches `url` and returns the response body, retrying transient failures.
+    pub async fn get_text(&self, url: &str) -> Result<String, Box<dyn Error>> {
+        let mut attempt = 0;
+
+        loop {
+            self.check_breaker()?;
+            self.wait_for_slot().await;
+
+            let reason = match self.send(url).await {
+                Ok(body) => {
+                    self.record_success();
+                    return Ok(body);
+                }
+                Err(Attempt::Fatal(reason)) => return Err(format!("GET {} failed: {}", url, reason).into()),
+                Err(Attempt::Retry { reason, retry_after }) => {
+                    self.record_failure();
+                    if attempt >= self.max_retries {
+                        reason
+                    } else {
+                        let delay = retry_after.unwrap_or_else(|| self.backoff(attempt)).min(self.max_delay);
+                        log::warn!(
+                            "GET {} failed ({}), retrying in {}ms ({}/{})",
+                            url,
+                            reason,
+                            delay.as_millis(),
+                            attempt + 1,
+                            self.max_retries
+                        );
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                        continue;
+                    }
+                }
+            };
+
+            return Err(format!("GET {} failed after {} attempts: {}", url, attempt + 1, reason).into());
+        }
+    }
+
+    async fn send(&self, url: &str) -> Result<String, Attempt> {
+        let response = match self.client.get(url).send().await {
+            Ok(response) => response,
+            Err(e) if e.is_timeout() || e.is_connect() || e.is_request() => {
+                return Err(Attempt::Retry { reason: e.to_string(), retry_after: None });
+            }
+            Err(e) => return Err(Attempt::Fatal(e.to_string())),
+        };
+
+        let status = response.status();
+        if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after);
+            return Err(Attempt::Retry { reason: format!("status {}", status), retry_after });
+        }
+        if !status.is_success() {
+            return Err(Attempt::Fatal(format!("status {}", status)));
+        }
+
+        response
+            .text()
+            .await
+            .map_err(|e| Attempt::Retry { reason: e.to_string(), retry_after: None })
+    }
+
+    /// Full-jitter exponential backoff: a random delay up to `base * 2^attempt`.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        let millis = ceiling.as_millis() as u64;
+        Duration::from_millis(rand::thread_rng().gen_range(0..=millis))
+    }
+
+    async fn wait_for_slot(&self) {
+        let mut next_request_at = self.next_request_at.lock().await;
+        let now = Instant::now();
+        if *next_request_at > now {
+            tokio::time::sleep_until(*next_request_at).await;
+        }
+        *next_request_at = Instant::now().max(*next_request_at) + self.min_request_spacing;
+    }
+
+    fn check_breaker(&self) -> Result<(), Box<dyn Error>> {
+        let breaker = self.breaker.lock().unwrap_or_else(|e| e.into_inner());
+        match breaker.open_until {
+            Some(open_until) if open_until > Instant::now() => Err(format!(
+                "Midgard circuit breaker open for another {}s after {} consecutive failures",
+                (open_until - Instant::now()).as_secs(),
+                breaker.consecutive_failures
+            )
+            .into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn record_success(&self) {
+        let mut breaker = self.breaker.lock().unwrap_or_else(|e| e.into_inner());
+        breaker.consecutive_failures = 0;
+        breaker.open_until = None;
+    }
+
+    fn record_failure(&self) {
+        let mut breaker = self.breaker.lock().unwrap_or_else(|e| e.into_inner());
+        breaker.consecutive_failures += 1;
+        if breaker.consecutive_failures >= self.breaker_threshold {
+            breaker.open_until = Some(Instant::now() + self.breaker_cooldown);
+        }
+    }
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(secs) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    (date.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+fn env_parse<T: std::str::FromStr>(key: &str, default: T) -> Result<T, Box<dyn Error>> {
+    match env::var(key) {
+        Ok(value) => value.parse::<T>().map_err(|_| format!("Invalid {} format", key).into()),
+        Err(_) => Ok(default),
+    }
+}
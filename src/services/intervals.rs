@@ -0,0 +1,180 @@
+use std::str::FromStr;
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
+
+/// Midgard allows at most this many buckets per request.
+const MAX_COUNT: u32 = 400;
+
+/// Bucket sizes the hourly history can be aggregated into, as named by Midgard's
+/// `interval` parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interval {
+    Hour,
+    Day,
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+impl FromStr for Interval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hour" => Ok(Interval::Hour),
+            "day" => Ok(Interval::Day),
+            "week" => Ok(Interval::Week),
+            "month" => Ok(Interval::Month),
+            "quarter" => Ok(Interval::Quarter),
+            "year" => Ok(Interval::Year),
+            "5min" => Err("interval 5min is finer than the stored hourly history".to_string()),
+            other => Err(format!("unknown interval {:?}, expected one of hour, day, week, month, quarter, year", other)),
+        }
+    }
+}
+
+impl Interval {
+    /// Start of the UTC bucket holding `timestamp`; weeks start on Monday, as in Midgard.
+    pub fn start_of(self, timestamp: i64) -> i64 {
+        match self {
+            Interval::Hour => timestamp - timestamp.rem_euclid(3600),
+            Interval::Day => timestamp - timestamp.rem_euclid(86400),
+            // 1970-01-01 was a Thursday.
+            Interval::Week => {
+                let day = timestamp.div_euclid(86400);
+                (day - (day + 3).rem_euclid(7)) * 86400
+            }
+            Interval::Month | Interval::Quarter | Interval::Year => {
+                let date = DateTime::from_timestamp(timestamp, 0).unwrap_or_default().date_naive();
+                let month = match self {
+                    Interval::Month => date.month(),
+                    Interval::Quarter => (date.month() - 1) / 3 * 3 + 1,
+                    _ => 1,
+                };
+                NaiveDate::from_ymd_opt(date.year(), month, 1)
+                    .and_then(|start| start.and_hms_opt(0, 0, 0))
+                    .map_or(timestamp, |start| start.and_utc().timestamp())
+            }
+        }
+    }
+
+    /// Start of the bucket `steps` buckets after (or, when negative, before) the one
+    /// starting at `start`.
+    pub fn step(self, start: i64, steps: i64) -> i64 {
+        let months = match self {
+            Interval::Hour => return start + steps * 3600,
+            Interval::Day => return start + steps * 86400,
+            Interval::Week => return start + steps * 7 * 86400,
+            Interval::Month => 1,
+            Interval::Quarter => 3,
+            Interval::Year => 12,
+        };
+        let Some(date) = DateTime::from_timestamp(start, 0) else {
+            return start;
+        };
+        let shift = Months::new((months * steps.unsigned_abs()) as u32);
+        let shifted = if steps >= 0 { date.checked_add_months(shift) } else { date.checked_sub_months(shift) };
+        shifted.map_or(start, |shifted| shifted.timestamp())
+    }
+}
+
+/// A parsed interval with the `from` and `to` its buckets cover.
+pub type BucketRange = (Option<Interval>, Option<i64>, Option<i64>);
+
+/// Parses `interval` and `count` and resolves the `[from, to)` their buckets cover, as
+/// Midgard does, with `from` moved to a bucket start.
+pub fn from_params(
+    interval: Option<&str>,
+    count: Option<u32>,
+    from: Option<i64>,
+    to: Option<i64>,
+) -> Result<BucketRange, String> {
+    let Some(interval) = interval else {
+        return Ok((None, from, to));
+    };
+    let interval: Interval = interval.parse()?;
+
+    let (from, to) = match (count, from, to) {
+        (Some(count), _, _) if count == 0 || count > MAX_COUNT => {
+            return Err(format!("count must be between 1 and {}", MAX_COUNT));
+        }
+        (Some(_), Some(_), Some(_)) => return Err("count cannot be combined with both from and to".to_string()),
+        (None, Some(from), Some(to)) => {
+            let start = interval.start_of(from);
+            if interval.step(start, MAX_COUNT as i64) < to {
+                return Err(format!("from and to span more than {} intervals; narrow the range or pass count", MAX_COUNT));
+            }
+            (Some(start), Some(to))
+        }
+        (count, Some(from), None) => {
+            let start = interval.start_of(from);
+            (Some(start), Some(interval.step(start, count.unwrap_or(MAX_COUNT) as i64)))
+        }
+        (count, None, to) => {
+            let to = to.unwrap_or_else(|| Utc::now().timestamp());
+            let last = interval.start_of(to - 1);
+            (Some(interval.step(last, 1 - count.unwrap_or(MAX_COUNT) as i64)), Some(to))
+        }
+    };
+    Ok((Some(interval), from, to))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn _at(year: i32, month: u32, day: u32, hour: u32) -> i64 {
+        Utc.with_ymd_and_hms(year, month, day, hour, 0, 0).unwrap().timestamp()
+    }
+
+    #[test]
+    fn start_of_rounds_down_to_bucket_starts() {
+        let time = _at(2024, 2, 29, 13) + 45 * 60;
+        assert_eq!(Interval::Hour.start_of(time), _at(2024, 2, 29, 13));
+        assert_eq!(Interval::Day.start_of(time), _at(2024, 2, 29, 0));
+        assert_eq!(Interval::Week.start_of(time), _at(2024, 2, 26, 0));
+        assert_eq!(Interval::Month.start_of(time), _at(2024, 2, 1, 0));
+        assert_eq!(Interval::Quarter.start_of(time), _at(2024, 1, 1, 0));
+        assert_eq!(Interval::Year.start_of(time), _at(2024, 1, 1, 0));
+        // A bucket start is its own start.
+        assert_eq!(Interval::Week.start_of(_at(2024, 2, 26, 0)), _at(2024, 2, 26, 0));
+    }
+
+    #[test]
+    fn steps_roll_over_months_quarters_and_years() {
+        assert_eq!(Interval::Month.step(_at(2024, 1, 1, 0), 1), _at(2024, 2, 1, 0));
+        assert_eq!(Interval::Month.step(_at(2024, 12, 1, 0), 1), _at(2025, 1, 1, 0));
+        assert_eq!(Interval::Month.step(_at(2024, 1, 1, 0), -1), _at(2023, 12, 1, 0));
+        assert_eq!(Interval::Quarter.step(_at(2024, 10, 1, 0), 1), _at(2025, 1, 1, 0));
+        assert_eq!(Interval::Quarter.step(_at(2024, 1, 1, 0), -2), _at(2023, 7, 1, 0));
+        assert_eq!(Interval::Year.step(_at(2024, 1, 1, 0), 3), _at(2027, 1, 1, 0));
+        assert_eq!(Interval::Week.step(_at(2024, 12, 30, 0), 1), _at(2025, 1, 6, 0));
+    }
+
+    #[test]
+    fn from_params_bounds_every_range() {
+        let day = Some("day");
+        let from = _at(2024, 1, 1, 5);
+        assert_eq!(from_params(None, None, Some(from), None), Ok((None, Some(from), None)));
+        assert_eq!(
+            from_params(day, None, Some(from), None),
+            Ok((Some(Interval::Day), Some(_at(2024, 1, 1, 0)), Some(_at(2024, 1, 1, 0) + 400 * 86400)))
+        );
+        assert_eq!(
+            from_params(day, Some(3), None, Some(_at(2024, 1, 10, 12))),
+            Ok((Some(Interval::Day), Some(_at(2024, 1, 8, 0)), Some(_at(2024, 1, 10, 12))))
+        );
+        assert_eq!(
+            from_params(Some("month"), Some(2), Some(_at(2024, 12, 15, 0)), None),
+            Ok((Some(Interval::Month), Some(_at(2024, 12, 1, 0)), Some(_at(2025, 2, 1, 0))))
+        );
+        // `from` moves back to midnight, so the 400 days end before `from + 400d`.
+        assert!(from_params(day, None, Some(from), Some(_at(2024, 1, 1, 0) + 400 * 86400)).is_ok());
+        assert!(from_params(day, None, Some(from), Some(from + 400 * 86400)).is_err());
+        assert!(from_params(day, Some(0), None, None).is_err());
+        assert!(from_params(day, Some(401), None, None).is_err());
+        assert!(from_params(day, Some(2), Some(from), Some(from + 86400)).is_err());
+        assert!(from_params(Some("5min"), None, None, None).is_err());
+    }
+}
@@ -0,0 +1,104 @@
+use std::env;
+use std::error::Error;
+use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
+use serde::de::DeserializeOwned;
+use crate::models::{depth_history::DepthHistory, earnings_history::EarningsHistory, runepool_history::RunePoolHistory, swaps_history::SwapHistory};
+use crate::models::midgard::{DepthIntervalDto, EarningsIntervalDto, HistoryResponseDto, PoolDto, RunePoolIntervalDto, SwapIntervalDto};
+use crate::services::http_client::HttpClient;
+
+const DEFAULT_BASE_URL: &str = "https://midgard.ninerealms.com";
+
+/// Largest `count` Midgard accepts on history endpoints.
+pub const MAX_COUNT: u32 = 400;
+
+/// The `count` of hourly intervals to request from `from` so a page stops at `to`, at
+/// least one and at most `MAX_COUNT`.
+pub fn page_count(from: i64, to: i64) -> u32 {
+    let hours = (to - from + 3599).div_euclid(3600);
+    hours.clamp(1, MAX_COUNT as i64) as u32
+}
+
+/// One page of a Midgard `/v2/history/*` response.
+#[derive(Debug)]
+pub struct HistoryResponse<T> {
+    pub intervals: Vec<T>,
+    /// `meta.endTime`, i.e. where the next page starts.
+    pub meta_end_time: i64,
+}
+
+/// Typed client for the Midgard endpoints we ingest from.
+pub struct MidgardClient {
+    base_url: String,
+    http: HttpClient,
+}
+
+impl MidgardClient {
+    /// Builds the client from `MIDGARD_BASE_URL`, `MIDGARD_USER_AGENT` and `MIDGARD_CLIENT_ID`.
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let base_url = env::var("MIDGARD_BASE_URL").unwrap_or_else(|_| DEFAULT_BASE_URL.to_string());
+        let user_agent = env::var("MIDGARD_USER_AGENT")
+            .unwrap_or_else(|_| format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_AGENT, HeaderValue::from_str(&user_agent)?);
+        if let Ok(client_id) = env::var("MIDGARD_CLIENT_ID") {
+            headers.insert("x-client-id", HeaderValue::from_str(&client_id)?);
+        }
+
+        Ok(MidgardClient {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            http: HttpClient::from_env(headers)?,
+        })
+    }
+
+    pub async fn depth_history(&self, pool: &str, from: i64, count: u32) -> Result<HistoryResponse<DepthHistory>, Box<dyn Error>> {
+        let url = format!("{}/v2/history/depths/{}?interval=hour&count={}&from={}", self.base_url, pool, count, from);
+        let response: HistoryResponseDto<DepthIntervalDto> = _parse(&self.http.get_text(&url).await?)?;
+        Ok(HistoryResponse {
+            intervals: response.intervals.into_iter().map(|interval| interval.into_model(pool)).collect(),
+            meta_end_time: response.meta.end_time,
+        })
+    }
+
+    pub async fn swaps_history(&self, pool: &str, from: i64, count: u32) -> Result<HistoryResponse<SwapHistory>, Box<dyn Error>> {
+        let url = format!("{}/v2/history/swaps?interval=hour&pool={}&count={}&from={}", self.base_url, pool, count, from);
+        let response: HistoryResponseDto<SwapIntervalDto> = _parse(&self.http.get_text(&url).await?)?;
+        Ok(HistoryResponse {
+            intervals: response.intervals.into_iter().map(|interval| interval.into_model(pool)).collect(),
+            meta_end_time: response.meta.end_time,
+        })
+    }
+
+    pub async fn runepool_history(&self, from: i64, count: u32) -> Result<HistoryResponse<RunePoolHistory>, Box<dyn Error>> {
+        let url = format!("{}/v2/history/runepool?interval=hour&count={}&from={}", self.base_url, count, from);
+        let response: HistoryResponseDto<RunePoolIntervalDto> = _parse(&self.http.get_text(&url).await?)?;
+        Ok(HistoryResponse {
+            intervals: response.intervals.into_iter().map(RunePoolHistory::from).collect(),
+            meta_end_time: response.meta.end_time,
+        })
+    }
+
+    pub async fn earnings_history(&self, from: i64, count: u32) -> Result<HistoryResponse<EarningsHistory>, Box<dyn Error>> {
+        let url = format!("{}/v2/history/earnings?interval=hour&count={}&from={}", self.base_url, count, from);
+        let response: HistoryResponseDto<EarningsIntervalDto> = _parse(&self.http.get_text(&url).await?)?;
+        Ok(HistoryResponse {
+            intervals: response.intervals.into_iter().map(EarningsHistory::from).collect(),
+            meta_end_time: response.meta.end_time,
+        })
+    }
+
+    /// Asset identifiers (e.g. `BTC.BTC`) of every pool.
+    pub async fn pools(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let url = format!("{}/v2/pools", self.base_url);
+        let pools: Vec<PoolDto> = _parse(&self.http.get_text(&url).await?)?;
+        Ok(pools.into_iter().map(|pool| pool.asset).collect())
+    }
+}
+
+/// Deserializes a Midgard response, naming the offending field (e.g.
+/// `intervals[3].assetDepth`) when one fails to parse.
+fn _parse<T: DeserializeOwned>(response: &str) -> Result<T, Box<dyn Error>> {
+    let deserializer = &mut serde_json::Deserializer::from_str(response);
+    serde_path_to_error::deserialize(deserializer)
+        .map_err(|e| format!("Invalid Midgard response at {}: {}", e.path(), e.inner()).into())
+}
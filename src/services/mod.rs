@@ -1,4 +1,11 @@
 pub mod fetch_depth;
 pub mod fetch_runepool;
 pub mod fetch_swaps;
-pub mod fetch_earnings;
\ No newline at end of file
+pub mod fetch_earnings;
+pub mod gaps;
+pub mod http_client;
+pub mod intervals;
+pub mod midgard_client;
+pub mod pagination;
+pub mod pools;
+pub mod sorting;
\ No newline at end of file
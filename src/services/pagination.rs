@@ -0,0 +1,276 @@
+use std::future::Future;
+use bson::{doc, Bson, Document};
+use serde::Serialize;
+use utoipa::ToSchema;
+use crate::models::depth_history::Metadata as DepthHistoryMetadata;
+use crate::models::earnings_history::Metadata as EarningsHistoryMetadata;
+use crate::models::runepool_history::Metadata as RunePoolHistoryMetadata;
+use crate::models::swaps_history::Metadata as SwapHistoryMetadata;
+use crate::repository::{HistoryQuery, RepoResult, SortKey, SortOrder};
+
+/// A position in a sorted history: a served row's sort values and which way to read on.
+/// Tokens are hex-encoded BSON.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor {
+    fields: Vec<String>,
+    values: Vec<Bson>,
+    backward: bool,
+}
+
+impl Cursor {
+    fn at(sort: &[SortKey], row: &Document, backward: bool) -> Self {
+        Cursor {
+            fields: sort.iter().map(|key| key.field.clone()).collect(),
+            values: sort.iter().map(|key| row.get(&key.field).cloned().unwrap_or(Bson::Null)).collect(),
+            backward,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        let document = doc! { "fields": &self.fields, "values": &self.values, "backward": self.backward };
+        bson::to_vec(&document).unwrap_or_default().iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    pub fn decode(token: &str) -> Result<Self, String> {
+        let invalid = || "cursor is not a token returned by this API".to_string();
+        if !token.len().is_multiple_of(2) || !token.is_ascii() {
+            return Err(invalid());
+        }
+        let bytes = (0..token.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&token[index..index + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| invalid())?;
+        let document: Document = bson::from_slice(&bytes).map_err(|_| invalid())?;
+
+        let fields = document.get_array("fields").map_err(|_| invalid())?;
+        let values = document.get_array("values").map_err(|_| invalid())?;
+        // Only plain values; a document such as `{"$ne": null}` would be an operator to Mongo.
+        let plain = |value: &Bson| {
+            matches!(value, Bson::Null | Bson::Boolean(_) | Bson::Int32(_) | Bson::Int64(_) | Bson::Double(_) | Bson::Decimal128(_) | Bson::String(_))
+        };
+        if fields.len() != values.len() || !values.iter().all(plain) {
+            return Err(invalid());
+        }
+        Ok(Cursor {
+            fields: fields.iter().map(|field| field.as_str().map(str::to_string).ok_or_else(invalid)).collect::<Result<_, _>>()?,
+            values: values.clone(),
+            backward: document.get_bool("backward").map_err(|_| invalid())?,
+        })
+    }
+
+    /// Cursors only resume the sort they were issued for.
+    pub fn check(&self, sort: &[SortKey]) -> Result<(), String> {
+        if self.fields.iter().eq(sort.iter().map(|key| &key.field)) {
+            Ok(())
+        } else {
+            Err("cursor was issued for a different sort; drop it or keep the original sort_by".to_string())
+        }
+    }
+}
+
+/// Ends `sort` with the natural key, so every row has a distinct position to resume from.
+pub fn with_tiebreak(mut sort: Vec<SortKey>, key: &[&str]) -> Vec<SortKey> {
+    for field in key {
+        if !sort.iter().any(|existing| existing.field == *field) {
+            sort.push(SortKey { field: field.to_string(), order: SortOrder::Desc });
+        }
+    }
+    sort
+}
+
+/// One page of rows with the cursors either side of it.
+pub struct Page<R> {
+    pub rows: Vec<R>,
+    pub next_cursor: Option<String>,
+    pub prev_cursor: Option<String>,
+}
+
+/// The response body shared by the paged history routes.
+#[derive(Debug, Serialize, ToSchema)]
+#[aliases(
+    DepthHistoryPage = Envelope<DepthHistoryMetadata>,
+    SwapHistoryPage = Envelope<SwapHistoryMetadata>,
+    RunePoolHistoryPage = Envelope<RunePoolHistoryMetadata>,
+    EarningsHistoryPage = Envelope<EarningsHistoryMetadata>,
+)]
+pub struct Envelope<M> {
+    /// Rows of the route's model, or of `PoolEarnings` with their interval's times.
+    #[schema(value_type = Vec<Object>)]
+    pub data: Vec<Document>,
+    /// Over the whole range; null when it holds no rows.
+    pub meta: Option<M>,
+    pub next_cursor: Option<String>,
+    pub prev_cursor: Option<String>,
+    /// Rows across every page, when `include_total=true`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+}
+
+/// Reads the page of `query` at `cursor`, or its `skip` / `limit` page without one.
+/// `document` gives a row's stored form, which cursors take their sort values from.
+pub async fn read<R, F, Fut>(query: &HistoryQuery, cursor: Option<&Cursor>, find: F, document: fn(&R) -> RepoResult<Document>) -> RepoResult<Page<R>>
+where
+    F: FnOnce(HistoryQuery) -> Fut,
+    Fut: Future<Output = RepoResult<Vec<R>>>,
+{
+    let limit = query.limit.unwrap_or(10).max(1);
+    let backward = cursor.is_some_and(|cursor| cursor.backward);
+    let mut page_query = HistoryQuery { limit: Some(limit + 1), ..query.clone() };
+    if let Some(cursor) = cursor {
+        page_query.skip = 0;
+        page_query.after = Some(cursor.values.clone());
+    }
+    if backward {
+        for key in &mut page_query.sort {
+            key.order = if key.order == SortOrder::Asc { SortOrder::Desc } else { SortOrder::Asc };
+        }
+    }
+
+    let mut rows = find(page_query).await?;
+    let more = rows.len() as i64 > limit;
+    rows.truncate(limit as usize);
+    if backward {
+        rows.reverse();
+    }
+
+    let (Some(first), Some(last)) = (rows.first(), rows.last()) else {
+        return Ok(Page { rows, next_cursor: None, prev_cursor: None });
+    };
+    let next = Cursor::at(&query.sort, &document(last)?, false).encode();
+    let prev = Cursor::at(&query.sort, &document(first)?, true).encode();
+    let (next_cursor, prev_cursor) = if backward {
+        (Some(next), more.then_some(prev))
+    } else {
+        (more.then_some(next), (cursor.is_some() || query.skip > 0).then_some(prev))
+    };
+    Ok(Page { rows, next_cursor, prev_cursor })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use bson::Decimal128;
+    use crate::models::amount::Amount;
+    use crate::models::depth_history::DepthHistory;
+    use crate::repository::memory::MemoryRepository;
+    use crate::repository::{stored_document, HistoryRepository};
+
+    fn _token(document: Document) -> String {
+        bson::to_vec(&document).unwrap().iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    /// Five hours of one pool, two pairs of them tied on `assetDepth`.
+    async fn _repository() -> MemoryRepository<DepthHistory> {
+        let repository = MemoryRepository::default();
+        let depths = [5, 7, 5, 9, 7];
+        let rows = depths
+            .iter()
+            .enumerate()
+            .map(|(hour, depth)| DepthHistory {
+                pool: "BTC.BTC".to_string(),
+                start_time: hour as i64 * 3600,
+                end_time: hour as i64 * 3600 + 3600,
+                asset_depth: Amount(*depth),
+                ..DepthHistory::default()
+            })
+            .collect();
+        repository.upsert(rows, None).await.unwrap();
+        repository
+    }
+
+    fn _query(limit: i64) -> HistoryQuery {
+        let sort = vec![SortKey { field: "assetDepth".to_string(), order: SortOrder::Desc }];
+        HistoryQuery { sort: with_tiebreak(sort, &["startTime", "pool"]), limit: Some(limit), ..HistoryQuery::default() }
+    }
+
+    async fn _read(repository: &MemoryRepository<DepthHistory>, query: &HistoryQuery, cursor: Option<&str>) -> Page<DepthHistory> {
+        let cursor = cursor.map(|token| Cursor::decode(token).unwrap());
+        let find = |query: HistoryQuery| async move { repository.find(&query).await };
+        read(query, cursor.as_ref(), find, stored_document).await.unwrap()
+    }
+
+    fn _hours(page: &Page<DepthHistory>) -> Vec<i64> {
+        page.rows.iter().map(|row| row.start_time / 3600).collect()
+    }
+
+    #[test]
+    fn cursors_round_trip() {
+        let cursor = Cursor {
+            fields: vec!["assetDepth".to_string(), "startTime".to_string()],
+            values: vec![Bson::Decimal128(Decimal128::from_str("100000000000000000001").unwrap()), Bson::Int64(3600)],
+            backward: true,
+        };
+        assert_eq!(Cursor::decode(&cursor.encode()), Ok(cursor));
+    }
+
+    #[test]
+    fn rejects_tokens_not_issued_here() {
+        let valid = doc! { "fields": ["startTime"], "values": [0_i64], "backward": false };
+        assert!(Cursor::decode(&_token(valid.clone())).is_ok());
+
+        let mut tampered = _token(valid);
+        tampered.replace_range(0..2, "ff");
+        let tokens = [
+            "".to_string(),
+            "abc".to_string(),
+            "zz".to_string(),
+            "é0".to_string(),
+            "00112233".to_string(),
+            tampered,
+            _token(doc! { "fields": ["startTime"], "values": [{ "$ne": Bson::Null }], "backward": false }),
+            _token(doc! { "fields": ["startTime"], "values": [[1_i64, 2_i64]], "backward": false }),
+            _token(doc! { "fields": ["startTime", "pool"], "values": [0_i64], "backward": false }),
+            _token(doc! { "fields": [1_i32], "values": [0_i64], "backward": false }),
+            _token(doc! { "fields": ["startTime"], "values": [0_i64] }),
+        ];
+        for token in tokens {
+            assert_eq!(Cursor::decode(&token), Err("cursor is not a token returned by this API".to_string()), "{:?}", token);
+        }
+    }
+
+    #[test]
+    fn cursors_only_resume_their_own_sort() {
+        let sort = _query(2).sort;
+        let cursor = Cursor::at(&sort, &doc! { "assetDepth": 5_i64, "startTime": 0_i64, "pool": "BTC.BTC" }, false);
+        assert!(cursor.check(&sort).is_ok());
+        assert!(cursor.check(&sort[1..]).is_err());
+    }
+
+    #[tokio::test]
+    async fn pages_through_ties_both_ways() {
+        let repository = _repository().await;
+        let query = _query(2);
+
+        // Ties on `assetDepth` fall back to `startTime`, descending.
+        let first = _read(&repository, &query, None).await;
+        assert_eq!((_hours(&first), first.prev_cursor.is_none()), (vec![3, 4], true));
+        let second = _read(&repository, &query, first.next_cursor.as_deref()).await;
+        assert_eq!(_hours(&second), [1, 2]);
+        let third = _read(&repository, &query, second.next_cursor.as_deref()).await;
+        assert_eq!((_hours(&third), third.next_cursor.is_none()), (vec![0], true));
+
+        let back = _read(&repository, &query, third.prev_cursor.as_deref()).await;
+        assert_eq!(_hours(&back), [1, 2]);
+        let start = _read(&repository, &query, back.prev_cursor.as_deref()).await;
+        assert_eq!((_hours(&start), start.prev_cursor.is_none()), (vec![3, 4], true));
+        assert_eq!(start.next_cursor, first.next_cursor);
+    }
+
+    #[tokio::test]
+    async fn looks_one_row_ahead_for_a_next_page() {
+        let repository = _repository().await;
+
+        // Exactly a page left: no next cursor to an empty page.
+        let last = _read(&repository, &HistoryQuery { skip: 3, .._query(2) }, None).await;
+        assert_eq!(_hours(&last), [2, 0]);
+        assert!(last.next_cursor.is_none());
+        assert!(last.prev_cursor.is_some());
+
+        let whole = _read(&repository, &_query(5), None).await;
+        assert_eq!((whole.rows.len(), whole.next_cursor, whole.prev_cursor), (5, None, None));
+        let short = _read(&repository, &_query(4), None).await;
+        assert!(short.next_cursor.is_some());
+    }
+}
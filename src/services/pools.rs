@@ -0,0 +1,55 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use crate::repository::{HistoryRecord, HistoryRepository, RepoResult};
+
+/// A `pool=` parameter: comma-separated pool names and `CHAIN.*` style prefix wildcards.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolSelection {
+    names: Vec<String>,
+    prefixes: Vec<String>,
+}
+
+impl PoolSelection {
+    pub fn parse(param: &str) -> Result<Self, String> {
+        let mut selection = PoolSelection { names: Vec::new(), prefixes: Vec::new() };
+        for pattern in param.split(',').map(str::trim) {
+            match pattern.strip_suffix('*') {
+                _ if pattern.is_empty() => return Err("pool must not contain empty entries".to_string()),
+                Some(prefix) if !prefix.is_empty() && !prefix.contains('*') => selection.prefixes.push(prefix.to_string()),
+                None if !pattern.contains('*') => selection.names.push(pattern.to_string()),
+                _ => return Err(format!("pool {} may only use * as a trailing wildcard, as in ETH.*", pattern)),
+            }
+        }
+        Ok(selection)
+    }
+
+    /// The pool, when exactly one is named without a wildcard.
+    pub fn single(&self) -> Option<&str> {
+        match (self.names.as_slice(), self.prefixes.is_empty()) {
+            ([name], true) => Some(name),
+            _ => None,
+        }
+    }
+
+    pub fn matches(&self, pool: &str) -> bool {
+        self.names.iter().any(|name| name == pool) || self.prefixes.iter().any(|prefix| pool.starts_with(prefix.as_str()))
+    }
+
+    /// The stored pools the selection matches, sorted.
+    pub async fn resolve<T: HistoryRecord, R: HistoryRepository<T> + ?Sized>(&self, repository: &R) -> RepoResult<Vec<String>> {
+        Ok(repository.pools().await?.into_iter().filter(|pool| self.matches(pool)).collect())
+    }
+}
+
+/// Runs `page` once for each pool, keyed by pool.
+pub async fn grouped<R, F, Fut>(pools: Vec<String>, page: F) -> RepoResult<BTreeMap<String, R>>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = RepoResult<R>>,
+{
+    let mut groups = BTreeMap::new();
+    for pool in pools {
+        groups.insert(pool.clone(), page(pool).await?);
+    }
+    Ok(groups)
+}
@@ -0,0 +1,108 @@
+use serde::Deserialize;
+use crate::models::fields::serialized_fields;
+use crate::repository::{SortKey, SortOrder};
+
+/// Model fields that are never sortable: storage ids and embedded arrays.
+const UNSORTABLE: [&str; 2] = ["_id", "pools"];
+
+/// The fields a resource backed by the model `T` can be sorted by.
+pub fn sortable<'de, T: Deserialize<'de>>() -> Vec<&'static str> {
+    serialized_fields::<T>().iter().copied().filter(|field| !UNSORTABLE.contains(field)).collect()
+}
+
+fn _unknown(field: &str, allowed: &[&str]) -> String {
+    format!("cannot sort by {:?}; sortable fields are {}", field, allowed.join(", "))
+}
+
+/// Sort keys from `sort=-totalVolume,startTime`, or the older `sort_by` / `order` pair;
+/// `startTime` descending by default.
+pub fn parse(sort: Option<&str>, sort_by: Option<&str>, order: Option<&str>, allowed: &[&str]) -> Result<Vec<SortKey>, String> {
+    if sort.is_some() && order.is_some() {
+        return Err("order applies to sort_by; give sort fields a leading - for descending instead".to_string());
+    }
+    let order = match order {
+        None | Some("desc") => SortOrder::Desc,
+        Some("asc") => SortOrder::Asc,
+        Some(other) => return Err(format!("order must be asc or desc, not {:?}", other)),
+    };
+
+    match (sort, sort_by) {
+        (Some(_), Some(_)) => Err("use either sort or sort_by, not both".to_string()),
+        (Some(sort), None) => {
+            let mut keys: Vec<SortKey> = Vec::new();
+            for term in sort.split(',').map(str::trim) {
+                let (field, order) = match term.strip_prefix('-') {
+                    Some(field) => (field, SortOrder::Desc),
+                    None => (term.strip_prefix('+').unwrap_or(term), SortOrder::Asc),
+                };
+                if !allowed.contains(&field) {
+                    return Err(_unknown(field, allowed));
+                }
+                if keys.iter().any(|key| key.field == field) {
+                    return Err(format!("sort names {:?} more than once", field));
+                }
+                keys.push(SortKey { field: field.to_string(), order });
+            }
+            Ok(keys)
+        }
+        (None, Some(field)) if allowed.contains(&field) => Ok(vec![SortKey { field: field.to_string(), order }]),
+        (None, Some(field)) => Err(_unknown(field, allowed)),
+        (None, None) => Ok(vec![SortKey { field: "startTime".to_string(), order }]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::earnings_history::EarningsHistory;
+
+    const ALLOWED: [&str; 3] = ["startTime", "totalVolume", "pool"];
+
+    fn _keys(keys: &[SortKey]) -> Vec<(&str, SortOrder)> {
+        keys.iter().map(|key| (key.field.as_str(), key.order)).collect()
+    }
+
+    #[test]
+    fn parses_several_keys_with_directions() {
+        let keys = parse(Some("-totalVolume, +pool,startTime"), None, None, &ALLOWED).unwrap();
+        assert_eq!(_keys(&keys), [("totalVolume", SortOrder::Desc), ("pool", SortOrder::Asc), ("startTime", SortOrder::Asc)]);
+        assert!(parse(Some("pool,-pool"), None, None, &ALLOWED).is_err());
+        assert!(parse(Some("pool,"), None, None, &ALLOWED).is_err());
+    }
+
+    #[test]
+    fn parses_sort_by_and_order() {
+        let keys = parse(None, Some("totalVolume"), Some("asc"), &ALLOWED).unwrap();
+        assert_eq!(_keys(&keys), [("totalVolume", SortOrder::Asc)]);
+        let keys = parse(None, Some("totalVolume"), None, &ALLOWED).unwrap();
+        assert_eq!(_keys(&keys), [("totalVolume", SortOrder::Desc)]);
+        assert!(parse(None, Some("pool"), Some("up"), &ALLOWED).is_err());
+    }
+
+    #[test]
+    fn defaults_to_start_time_in_order() {
+        assert_eq!(_keys(&parse(None, None, None, &ALLOWED).unwrap()), [("startTime", SortOrder::Desc)]);
+        assert_eq!(_keys(&parse(None, None, Some("asc"), &ALLOWED).unwrap()), [("startTime", SortOrder::Asc)]);
+    }
+
+    #[test]
+    fn rejects_conflicting_parameters() {
+        assert_eq!(parse(Some("pool"), Some("pool"), None, &ALLOWED).unwrap_err(), "use either sort or sort_by, not both");
+        assert!(parse(Some("pool"), None, Some("asc"), &ALLOWED).is_err());
+    }
+
+    #[test]
+    fn unknown_fields_list_the_sortable_ones() {
+        let expected = "cannot sort by \"depth\"; sortable fields are startTime, totalVolume, pool";
+        assert_eq!(parse(Some("-depth"), None, None, &ALLOWED).unwrap_err(), expected);
+        assert_eq!(parse(None, Some("depth"), None, &ALLOWED).unwrap_err(), expected);
+    }
+
+    #[test]
+    fn storage_ids_and_embedded_pools_are_not_sortable() {
+        let sortable = sortable::<EarningsHistory>();
+        assert!(sortable.contains(&"startTime") && sortable.contains(&"runePriceUSD"));
+        assert!(!sortable.contains(&"_id") && !sortable.contains(&"pools"));
+        assert!(parse(Some("pools"), None, None, &sortable).is_err());
+    }
+}